@@ -0,0 +1,133 @@
+use std::fmt;
+
+
+/// Handle to an entity. The generation is bumped every time an index is
+/// recycled, so a handle kept after a despawn never matches the new entity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EntityAllocator {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl EntityAllocator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allocate(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            },
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: (self.generations.len() - 1) as u32,
+                    generation: 0,
+                }
+            },
+        }
+    }
+
+    /// Frees the entity index for reuse. Returns false if the handle was already stale.
+    pub fn deallocate(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.alive.len() && self.alive[index] && self.generations[index] == entity.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate().filter(|(_, alive)| **alive).map(move |(index, _)| Entity {
+            index: index as u32,
+            generation: self.generations[index],
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recycled_index_gets_new_generation() {
+        let mut entities = EntityAllocator::new();
+        let first = entities.allocate();
+        let second = entities.allocate();
+        assert!(entities.deallocate(first));
+        let third = entities.allocate();
+        assert_eq!(third.index(), first.index());
+        assert_ne!(third, first);
+        assert!(!entities.is_alive(first));
+        assert!(entities.is_alive(second));
+        assert!(entities.is_alive(third));
+    }
+
+    #[test]
+    fn stale_handle_cannot_be_freed_twice() {
+        let mut entities = EntityAllocator::new();
+        let entity = entities.allocate();
+        assert!(entities.deallocate(entity));
+        assert!(!entities.deallocate(entity));
+        assert_eq!(entities.len(), 0);
+        let recycled = entities.allocate();
+        assert!(!entities.deallocate(entity));
+        assert!(entities.is_alive(recycled));
+    }
+
+    #[test]
+    fn iter_skips_dead_entities() {
+        let mut entities = EntityAllocator::new();
+        let a = entities.allocate();
+        let b = entities.allocate();
+        let c = entities.allocate();
+        entities.deallocate(b);
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![a, c]);
+    }
+}
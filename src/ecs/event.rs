@@ -0,0 +1,223 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::iter::Chain;
+use std::marker::PhantomData;
+use std::slice::Iter;
+
+
+pub trait Event: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Event for T {}
+
+pub type EventIter<'a, E> = Chain<Iter<'a, E>, Iter<'a, E>>;
+
+/// Double buffered channel: an event stays readable during the frame it was sent and the next one,
+/// so readers running before or after the sender in a frame all see it exactly once.
+#[derive(Debug)]
+pub struct Events<E> {
+    previous: Vec<E>,
+    current: Vec<E>,
+    previous_start: usize,
+    count: usize,
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            count: 0,
+        }
+    }
+}
+
+impl<E> Events<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+        self.count += 1;
+    }
+
+    /// Events not read since the last call with this reader.
+    pub fn read<'a>(&'a self, reader: &mut EventReader<E>) -> EventIter<'a, E> {
+        let start = reader.next.max(self.previous_start);
+        let current_start = self.previous_start + self.previous.len();
+        let previous = &self.previous[(start - self.previous_start).min(self.previous.len())..];
+        let current = &self.current[start.saturating_sub(current_start).min(self.current.len())..];
+        reader.next = self.count;
+        previous.iter().chain(current.iter())
+    }
+
+    /// Reader that ignores the events already sent.
+    pub fn reader(&self) -> EventReader<E> {
+        EventReader {
+            next: self.count,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Swaps the buffers, events sent two frames ago are dropped.
+    pub fn update(&mut self) {
+        self.previous_start += self.previous.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Cursor of one reader in an event channel.
+#[derive(Debug)]
+pub struct EventReader<E> {
+    next: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E> Default for EventReader<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> EventReader<E> {
+    /// Reader seeing every event still buffered.
+    pub fn new() -> Self {
+        Self {
+            next: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+trait AnyEvents: Send + Sync {
+    fn update(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<E: Event> AnyEvents for Events<E> {
+    fn update(&mut self) {
+        Events::update(self);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// One channel per event type, e.g. `bus.send(Collision(a, b))` and `bus.read::<Collision>(&mut self.reader)`.
+#[derive(Default)]
+pub struct EventBus {
+    channels: HashMap<TypeId, Box<dyn AnyEvents>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send<E: Event>(&mut self, event: E) {
+        self.channel_mut::<E>().send(event);
+    }
+
+    pub fn read<E: Event>(&self, reader: &mut EventReader<E>) -> EventIter<'_, E> {
+        match self.channel::<E>() {
+            Some(events) => events.read(reader),
+            None => [].iter().chain([].iter()),
+        }
+    }
+
+    pub fn reader<E: Event>(&self) -> EventReader<E> {
+        self.channel::<E>().map(|events| events.reader()).unwrap_or_default()
+    }
+
+    pub fn channel<E: Event>(&self) -> Option<&Events<E>> {
+        self.channels.get(&TypeId::of::<E>())
+            .and_then(|events| events.as_any().downcast_ref::<Events<E>>())
+    }
+
+    pub fn channel_mut<E: Event>(&mut self) -> &mut Events<E> {
+        self.channels.entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Events::<E>::new()))
+            .as_any_mut()
+            .downcast_mut::<Events<E>>()
+            .unwrap()
+    }
+
+    /// Called once per frame by the engine before any event is sent.
+    pub fn update(&mut self) {
+        for events in self.channels.values_mut() {
+            events.update();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Hit(u32);
+
+    #[test]
+    fn each_reader_sees_events_once() {
+        let mut bus = EventBus::new();
+        let mut first = EventReader::<Hit>::new();
+        let mut second = EventReader::<Hit>::new();
+        bus.send(Hit(1));
+        bus.send(Hit(2));
+        assert_eq!(bus.read(&mut first).collect::<Vec<_>>(), vec![&Hit(1), &Hit(2)]);
+        bus.send(Hit(3));
+        assert_eq!(bus.read(&mut first).collect::<Vec<_>>(), vec![&Hit(3)]);
+        assert_eq!(bus.read(&mut second).count(), 3);
+        assert_eq!(bus.read(&mut second).count(), 0);
+    }
+
+    #[test]
+    fn events_live_two_frames() {
+        let mut bus = EventBus::new();
+        let mut late = EventReader::<Hit>::new();
+        bus.send(Hit(1));
+        bus.update();
+        bus.send(Hit(2));
+        assert_eq!(bus.read(&mut late).collect::<Vec<_>>(), vec![&Hit(1), &Hit(2)]);
+        bus.update();
+        bus.update();
+        let mut reader = EventReader::<Hit>::new();
+        assert_eq!(bus.read(&mut reader).count(), 0);
+    }
+
+    #[test]
+    fn reader_skips_dropped_events() {
+        let mut bus = EventBus::new();
+        let mut reader = EventReader::<Hit>::new();
+        bus.send(Hit(1));
+        bus.update();
+        bus.update();
+        bus.send(Hit(2));
+        assert_eq!(bus.read(&mut reader).collect::<Vec<_>>(), vec![&Hit(2)]);
+    }
+
+    #[test]
+    fn new_reader_ignores_past_events() {
+        let mut bus = EventBus::new();
+        bus.send(Hit(1));
+        let mut reader = bus.reader::<Hit>();
+        bus.send(Hit(2));
+        assert_eq!(bus.read(&mut reader).collect::<Vec<_>>(), vec![&Hit(2)]);
+    }
+}
@@ -0,0 +1,11 @@
+pub mod entity;
+pub mod storage;
+pub mod query;
+pub mod world;
+pub mod event;
+
+pub use self::entity::{Entity, EntityAllocator};
+pub use self::storage::{Component, ComponentStorage, AnyStorage};
+pub use self::query::{Access, Fetch, ReadOnlyFetch, Filter, Query, QueryIter, With, Without, Changed};
+pub use self::world::World;
+pub use self::event::{Event, Events, EventBus, EventReader, EventIter};
@@ -0,0 +1,515 @@
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use crate::ecs::{Entity, World, Component, ComponentStorage};
+
+
+/// Component types read and written by a query, used to reject aliasing mutable borrows.
+#[derive(Debug, Default, Clone)]
+pub struct Access {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_read<T: Component>(&mut self) {
+        self.reads.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    pub fn add_write<T: Component>(&mut self) {
+        self.writes.push((TypeId::of::<T>(), type_name::<T>()));
+    }
+
+    /// Name of the first component written more than once or both read and written.
+    pub fn conflict(&self) -> Option<&'static str> {
+        for (i, (id, name)) in self.writes.iter().enumerate() {
+            let written_again = self.writes[i + 1..].iter().any(|(other, _)| other == id);
+            let read = self.reads.iter().any(|(other, _)| other == id);
+            if written_again || read {
+                return Some(name);
+            }
+        }
+        None
+    }
+}
+
+/// Something that can be fetched for every entity matched by a query:
+/// `&T`, `&mut T`, `Option<&T>`, `Entity` and tuples of those.
+pub trait Fetch {
+    type Item<'a>;
+    type State: Copy;
+
+    fn access(access: &mut Access);
+    /// Returns None when a required component has never been inserted, the query is then empty.
+    fn init(world: &mut World) -> Option<Self::State>;
+    /// Entities to iterate over, the query picks the smallest candidate list.
+    fn candidates(state: &Self::State) -> Option<*const [Entity]>;
+    fn matches(state: &Self::State, entity: Entity) -> bool;
+    /// # Safety
+    /// `entity` must match and no other reference to its components may be alive.
+    unsafe fn fetch<'a>(state: &Self::State, entity: Entity, change_tick: u32) -> Self::Item<'a>;
+}
+
+/// Fetch that never hands out mutable references, usable from a shared `&World`.
+pub trait ReadOnlyFetch: Fetch {
+    fn init_read(world: &World) -> Option<Self::State>;
+}
+
+impl Fetch for Entity {
+    type Item<'a> = Entity;
+    type State = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn init(_world: &mut World) -> Option<Self::State> {
+        Some(())
+    }
+
+    fn candidates(_state: &Self::State) -> Option<*const [Entity]> {
+        None
+    }
+
+    fn matches(_state: &Self::State, _entity: Entity) -> bool {
+        true
+    }
+
+    unsafe fn fetch<'a>(_state: &Self::State, entity: Entity, _change_tick: u32) -> Self::Item<'a> {
+        entity
+    }
+}
+
+impl ReadOnlyFetch for Entity {
+    fn init_read(_world: &World) -> Option<Self::State> {
+        Some(())
+    }
+}
+
+impl<T: Component> Fetch for &T {
+    type Item<'a> = &'a T;
+    type State = *const ComponentStorage<T>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    fn init(world: &mut World) -> Option<Self::State> {
+        world.storage::<T>().map(|storage| storage as *const _)
+    }
+
+    fn candidates(state: &Self::State) -> Option<*const [Entity]> {
+        Some(unsafe { (**state).entities() } as *const _)
+    }
+
+    fn matches(state: &Self::State, entity: Entity) -> bool {
+        unsafe { (**state).contains(entity) }
+    }
+
+    unsafe fn fetch<'a>(state: &Self::State, entity: Entity, _change_tick: u32) -> Self::Item<'a> {
+        (**state).get(entity).unwrap()
+    }
+}
+
+impl<T: Component> ReadOnlyFetch for &T {
+    fn init_read(world: &World) -> Option<Self::State> {
+        world.storage::<T>().map(|storage| storage as *const _)
+    }
+}
+
+impl<T: Component> Fetch for &mut T {
+    type Item<'a> = &'a mut T;
+    type State = *mut ComponentStorage<T>;
+
+    fn access(access: &mut Access) {
+        access.add_write::<T>();
+    }
+
+    fn init(world: &mut World) -> Option<Self::State> {
+        world.storage_mut::<T>().map(|storage| storage as *mut _)
+    }
+
+    fn candidates(state: &Self::State) -> Option<*const [Entity]> {
+        Some(unsafe { (**state).entities() } as *const _)
+    }
+
+    fn matches(state: &Self::State, entity: Entity) -> bool {
+        unsafe { (**state).contains(entity) }
+    }
+
+    unsafe fn fetch<'a>(state: &Self::State, entity: Entity, change_tick: u32) -> Self::Item<'a> {
+        (**state).get_mut(entity, change_tick).unwrap()
+    }
+}
+
+impl<T: Component> Fetch for Option<&T> {
+    type Item<'a> = Option<&'a T>;
+    type State = Option<*const ComponentStorage<T>>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    fn init(world: &mut World) -> Option<Self::State> {
+        Some(world.storage::<T>().map(|storage| storage as *const _))
+    }
+
+    fn candidates(_state: &Self::State) -> Option<*const [Entity]> {
+        None
+    }
+
+    fn matches(_state: &Self::State, _entity: Entity) -> bool {
+        true
+    }
+
+    unsafe fn fetch<'a>(state: &Self::State, entity: Entity, _change_tick: u32) -> Self::Item<'a> {
+        state.and_then(|storage| (*storage).get(entity))
+    }
+}
+
+impl<T: Component> ReadOnlyFetch for Option<&T> {
+    fn init_read(world: &World) -> Option<Self::State> {
+        Some(world.storage::<T>().map(|storage| storage as *const _))
+    }
+}
+
+/// Restricts the entities matched by a query without fetching anything.
+pub trait Filter {
+    type State: Copy;
+
+    fn init(world: &World) -> Self::State;
+    fn matches(state: &Self::State, entity: Entity, last_change_tick: u32) -> bool;
+}
+
+impl Filter for () {
+    type State = ();
+
+    fn init(_world: &World) -> Self::State {}
+
+    fn matches(_state: &Self::State, _entity: Entity, _last_change_tick: u32) -> bool {
+        true
+    }
+}
+
+/// Matches entities having a `T` component.
+pub struct With<T>(PhantomData<T>);
+
+/// Matches entities without a `T` component.
+pub struct Without<T>(PhantomData<T>);
+
+/// Matches entities whose `T` component was added or mutably accessed during the current frame.
+pub struct Changed<T>(PhantomData<T>);
+
+impl<T: Component> Filter for With<T> {
+    type State = Option<*const ComponentStorage<T>>;
+
+    fn init(world: &World) -> Self::State {
+        world.storage::<T>().map(|storage| storage as *const _)
+    }
+
+    fn matches(state: &Self::State, entity: Entity, _last_change_tick: u32) -> bool {
+        state.is_some_and(|storage| unsafe { (*storage).contains(entity) })
+    }
+}
+
+impl<T: Component> Filter for Without<T> {
+    type State = Option<*const ComponentStorage<T>>;
+
+    fn init(world: &World) -> Self::State {
+        world.storage::<T>().map(|storage| storage as *const _)
+    }
+
+    fn matches(state: &Self::State, entity: Entity, _last_change_tick: u32) -> bool {
+        state.is_none_or(|storage| unsafe { !(*storage).contains(entity) })
+    }
+}
+
+impl<T: Component> Filter for Changed<T> {
+    type State = Option<*const ComponentStorage<T>>;
+
+    fn init(world: &World) -> Self::State {
+        world.storage::<T>().map(|storage| storage as *const _)
+    }
+
+    fn matches(state: &Self::State, entity: Entity, last_change_tick: u32) -> bool {
+        state.and_then(|storage| unsafe { (*storage).changed_tick(entity) })
+            .is_some_and(|tick| tick > last_change_tick)
+    }
+}
+
+macro_rules! impl_tuples {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($name: Fetch),*> Fetch for ($($name,)*) {
+            type Item<'a> = ($($name::Item<'a>,)*);
+            type State = ($($name::State,)*);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)*
+            }
+
+            fn init(world: &mut World) -> Option<Self::State> {
+                Some(($($name::init(world)?,)*))
+            }
+
+            fn candidates(state: &Self::State) -> Option<*const [Entity]> {
+                let ($($name,)*) = state;
+                let mut smallest: Option<*const [Entity]> = None;
+                $(
+                    if let Some(candidates) = $name::candidates($name) {
+                        if smallest.is_none_or(|current| candidates.len() < current.len()) {
+                            smallest = Some(candidates);
+                        }
+                    }
+                )*
+                smallest
+            }
+
+            fn matches(state: &Self::State, entity: Entity) -> bool {
+                let ($($name,)*) = state;
+                $($name::matches($name, entity))&&*
+            }
+
+            unsafe fn fetch<'a>(state: &Self::State, entity: Entity, change_tick: u32) -> Self::Item<'a> {
+                let ($($name,)*) = state;
+                ($($name::fetch($name, entity, change_tick),)*)
+            }
+        }
+
+        impl<$($name: ReadOnlyFetch),*> ReadOnlyFetch for ($($name,)*) {
+            fn init_read(world: &World) -> Option<Self::State> {
+                Some(($($name::init_read(world)?,)*))
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($name: Filter),*> Filter for ($($name,)*) {
+            type State = ($($name::State,)*);
+
+            fn init(world: &World) -> Self::State {
+                ($($name::init(world),)*)
+            }
+
+            fn matches(state: &Self::State, entity: Entity, last_change_tick: u32) -> bool {
+                let ($($name,)*) = state;
+                $($name::matches($name, entity, last_change_tick))&&*
+            }
+        }
+    };
+}
+
+impl_tuples!(A);
+impl_tuples!(A, B);
+impl_tuples!(A, B, C);
+impl_tuples!(A, B, C, D);
+impl_tuples!(A, B, C, D, E);
+impl_tuples!(A, B, C, D, E, F);
+impl_tuples!(A, B, C, D, E, F, G);
+impl_tuples!(A, B, C, D, E, F, G, H);
+
+enum Candidates {
+    Storage(*const [Entity]),
+    Alive(Vec<Entity>),
+}
+
+/// Typed view over the world, e.g. `world.query::<(&mut Transform, &Mesh)>()`.
+pub struct Query<'w, Q: Fetch, F: Filter = ()> {
+    state: Option<(Q::State, F::State)>,
+    candidates: Candidates,
+    change_tick: u32,
+    last_change_tick: u32,
+    _marker: PhantomData<&'w mut World>,
+}
+
+impl<'w, Q: Fetch, F: Filter> Query<'w, Q, F> {
+    pub(crate) fn new(world: &'w mut World) -> Self {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        if let Some(name) = access.conflict() {
+            panic!("Query {} borrows {} mutably more than once", type_name::<Q>(), name);
+        }
+        let filter = F::init(world);
+        let state = Q::init(world).map(|fetch| (fetch, filter));
+        Self::from_state(world, state)
+    }
+
+    fn from_state(world: &World, state: Option<(Q::State, F::State)>) -> Self {
+        let candidates = match state.as_ref().and_then(|(fetch, _)| Q::candidates(fetch)) {
+            Some(entities) => Candidates::Storage(entities),
+            None => Candidates::Alive(world.entities().iter().collect()),
+        };
+        Self {
+            state,
+            candidates,
+            change_tick: world.change_tick(),
+            last_change_tick: world.last_change_tick(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&mut self) -> QueryIter<'_, Q, F> {
+        let entities: &[Entity] = match &self.candidates {
+            Candidates::Storage(entities) => unsafe { &**entities },
+            Candidates::Alive(entities) => entities,
+        };
+        QueryIter {
+            state: self.state,
+            entities,
+            position: 0,
+            change_tick: self.change_tick,
+            last_change_tick: self.last_change_tick,
+        }
+    }
+
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        let (fetch, filter) = self.state.as_ref()?;
+        if Q::matches(fetch, entity) && F::matches(filter, entity, self.last_change_tick) {
+            Some(unsafe { Q::fetch(fetch, entity, self.change_tick) })
+        } else {
+            None
+        }
+    }
+}
+
+impl<'w, Q: ReadOnlyFetch, F: Filter> Query<'w, Q, F> {
+    pub(crate) fn new_read(world: &'w World) -> Self {
+        let filter = F::init(world);
+        let state = Q::init_read(world).map(|fetch| (fetch, filter));
+        Self::from_state(world, state)
+    }
+}
+
+impl<'q, 'w, Q: Fetch, F: Filter> IntoIterator for &'q mut Query<'w, Q, F> {
+    type Item = Q::Item<'q>;
+    type IntoIter = QueryIter<'q, Q, F>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct QueryIter<'q, Q: Fetch, F: Filter> {
+    state: Option<(Q::State, F::State)>,
+    entities: &'q [Entity],
+    position: usize,
+    change_tick: u32,
+    last_change_tick: u32,
+}
+
+impl<'q, Q: Fetch, F: Filter> Iterator for QueryIter<'q, Q, F> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (fetch, filter) = self.state.as_ref()?;
+        while let Some(entity) = self.entities.get(self.position) {
+            self.position += 1;
+            if Q::matches(fetch, *entity) && F::matches(filter, *entity, self.last_change_tick) {
+                // Every entity is visited once, so mutable items never alias
+                return Some(unsafe { Q::fetch(fetch, *entity, self.change_tick) });
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32);
+
+    struct Frozen;
+
+    #[test]
+    fn query_mutates_matching_entities() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Position(0.0));
+        world.insert(a, Velocity(2.0));
+        world.insert(b, Position(1.0));
+        for (position, velocity) in world.query::<(&mut Position, &Velocity)>().iter() {
+            position.0 += velocity.0;
+        }
+        assert_eq!(world.get::<Position>(a), Some(&Position(2.0)));
+        assert_eq!(world.get::<Position>(b), Some(&Position(1.0)));
+    }
+
+    #[test]
+    fn query_with_entity_and_option() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Position(0.0));
+        world.insert(b, Position(1.0));
+        world.insert(b, Velocity(3.0));
+        let mut found: Vec<(Entity, Option<f32>)> = world.query::<(Entity, &Position, Option<&Velocity>)>()
+            .iter()
+            .map(|(entity, _, velocity)| (entity, velocity.map(|v| v.0)))
+            .collect();
+        found.sort_by_key(|(entity, _)| *entity);
+        assert_eq!(found, vec![(a, None), (b, Some(3.0))]);
+    }
+
+    #[test]
+    fn with_and_without_filters() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Position(0.0));
+        world.insert(b, Position(1.0));
+        world.insert(b, Frozen);
+        let frozen: Vec<Entity> = world.query_filtered::<Entity, With<Frozen>>().iter().collect();
+        assert_eq!(frozen, vec![b]);
+        let moving: Vec<Entity> = world.query_filtered::<Entity, (With<Position>, Without<Frozen>)>().iter().collect();
+        assert_eq!(moving, vec![a]);
+    }
+
+    #[test]
+    fn changed_filter_tracks_mutations() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Position(0.0));
+        world.insert(b, Position(1.0));
+        assert_eq!(world.query_filtered::<Entity, Changed<Position>>().iter().count(), 2);
+        world.clear_trackers();
+        assert_eq!(world.query_filtered::<Entity, Changed<Position>>().iter().count(), 0);
+        world.get_mut::<Position>(b).unwrap().0 = 5.0;
+        let changed: Vec<Entity> = world.query_filtered::<Entity, Changed<Position>>().iter().collect();
+        assert_eq!(changed, vec![b]);
+    }
+
+    #[test]
+    fn missing_component_gives_empty_query() {
+        let mut world = World::new();
+        let a = world.spawn();
+        world.insert(a, Position(0.0));
+        assert_eq!(world.query::<(&Position, &Velocity)>().iter().count(), 0);
+        assert!(world.query::<&Position>().get(a).is_some());
+    }
+
+    #[test]
+    fn read_query_from_shared_world() {
+        let mut world = World::new();
+        let a = world.spawn();
+        world.insert(a, Position(4.0));
+        let world = &world;
+        let total: f32 = world.query_read::<&Position>().iter().map(|position| position.0).sum();
+        assert_eq!(total, 4.0);
+        assert_eq!(world.query_read_filtered::<Entity, Without<Velocity>>().iter().collect::<Vec<_>>(), vec![a]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn aliasing_mutable_query_panics() {
+        let mut world = World::new();
+        world.query::<(&mut Position, &Position)>();
+    }
+}
@@ -0,0 +1,187 @@
+use std::any::Any;
+use crate::ecs::Entity;
+
+
+/// Anything stored in the `World`. The bounds allow systems reading the world to run on other threads.
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+/// Sparse set storage: components are packed in a dense array, the sparse array maps
+/// an entity index to its position in the dense one.
+#[derive(Debug)]
+pub struct ComponentStorage<T> {
+    sparse: Vec<Option<usize>>,
+    entities: Vec<Entity>,
+    components: Vec<T>,
+    changed: Vec<u32>,
+}
+
+impl<T> Default for ComponentStorage<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
+
+impl<T> ComponentStorage<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        match self.sparse.get(entity.index() as usize) {
+            Some(Some(dense)) if self.entities[*dense] == entity => Some(*dense),
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, component: T, tick: u32) -> Option<T> {
+        if let Some(dense) = self.dense_index(entity) {
+            self.changed[dense] = tick;
+            return Some(std::mem::replace(&mut self.components[dense], component));
+        }
+        let index = entity.index() as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, None);
+        }
+        // The slot may still point to a component of a stale generation
+        if let Some(dense) = self.sparse[index].take() {
+            self.swap_remove(dense);
+        }
+        self.sparse[index] = Some(self.components.len());
+        self.entities.push(entity);
+        self.components.push(component);
+        self.changed.push(tick);
+        None
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let dense = self.dense_index(entity)?;
+        self.sparse[entity.index() as usize] = None;
+        Some(self.swap_remove(dense))
+    }
+
+    fn swap_remove(&mut self, dense: usize) -> T {
+        let last = self.entities.len() - 1;
+        if dense != last {
+            let moved = self.entities[last];
+            self.sparse[moved.index() as usize] = Some(dense);
+        }
+        self.entities.swap_remove(dense);
+        self.changed.swap_remove(dense);
+        self.components.swap_remove(dense)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.dense_index(entity).map(|dense| &self.components[dense])
+    }
+
+    /// Mutable access flags the component as changed at `tick`.
+    pub fn get_mut(&mut self, entity: Entity, tick: u32) -> Option<&mut T> {
+        let dense = self.dense_index(entity)?;
+        self.changed[dense] = tick;
+        Some(&mut self.components[dense])
+    }
+
+    pub fn changed_tick(&self, entity: Entity) -> Option<u32> {
+        self.dense_index(entity).map(|dense| self.changed[dense])
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().cloned().zip(self.components.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+}
+
+/// Type-erased access to a `ComponentStorage` so the world can hold every component type in one map.
+pub trait AnyStorage: Send + Sync {
+    fn remove_entity(&mut self, entity: Entity);
+    fn contains_entity(&self, entity: Entity) -> bool;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> AnyStorage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn contains_entity(&self, entity: Entity) -> bool {
+        self.contains(entity)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::EntityAllocator;
+
+    #[test]
+    fn remove_keeps_storage_dense() {
+        let mut entities = EntityAllocator::new();
+        let (a, b, c) = (entities.allocate(), entities.allocate(), entities.allocate());
+        let mut storage = ComponentStorage::new();
+        storage.insert(a, 1, 0);
+        storage.insert(b, 2, 0);
+        storage.insert(c, 3, 0);
+        assert_eq!(storage.remove(a), Some(1));
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.get(c), Some(&3));
+        assert_eq!(storage.get(b), Some(&2));
+        assert_eq!(storage.entities(), &[c, b]);
+    }
+
+    #[test]
+    fn stale_entity_does_not_alias() {
+        let mut entities = EntityAllocator::new();
+        let old = entities.allocate();
+        let mut storage = ComponentStorage::new();
+        storage.insert(old, "old", 0);
+        entities.deallocate(old);
+        let new = entities.allocate();
+        assert_eq!(storage.get(new), None);
+        assert_eq!(storage.insert(new, "new", 0), None);
+        assert_eq!(storage.get(old), None);
+        assert_eq!(storage.get(new), Some(&"new"));
+        assert_eq!(storage.len(), 1);
+    }
+
+    #[test]
+    fn get_mut_marks_changed() {
+        let mut entities = EntityAllocator::new();
+        let entity = entities.allocate();
+        let mut storage = ComponentStorage::new();
+        storage.insert(entity, 1.0, 1);
+        *storage.get_mut(entity, 5).unwrap() += 1.0;
+        assert_eq!(storage.changed_tick(entity), Some(5));
+    }
+}
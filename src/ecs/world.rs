@@ -0,0 +1,155 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use crate::ecs::{Entity, EntityAllocator, Component, ComponentStorage, AnyStorage, Fetch, ReadOnlyFetch, Filter, Query};
+
+
+/// Entities and every component type attached to them.
+pub struct World {
+    entities: EntityAllocator,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    change_tick: u32,
+    last_change_tick: u32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            entities: EntityAllocator::new(),
+            storages: HashMap::new(),
+            change_tick: 1,
+            last_change_tick: 0,
+        }
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.allocate()
+    }
+
+    /// Removes the entity and all its components. Returns false if the entity was already despawned.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.is_alive(entity) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+        self.entities.deallocate(entity)
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn entities(&self) -> &EntityAllocator {
+        &self.entities
+    }
+
+    /// Adds or replaces the `T` component of the entity, returning the previous one.
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> Option<T> {
+        assert!(self.is_alive(entity), "Trying to add a component to despawned entity {}", entity);
+        let tick = self.change_tick;
+        self.storages.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<ComponentStorage<T>>()
+            .unwrap()
+            .insert(entity, component, tick)
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        self.storage_mut::<T>()?.remove(entity)
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        let tick = self.change_tick;
+        self.storage_mut::<T>()?.get_mut(entity, tick)
+    }
+
+    pub fn contains<T: Component>(&self, entity: Entity) -> bool {
+        self.storage::<T>().is_some_and(|storage| storage.contains(entity))
+    }
+
+    /// Number of entities having a `T` component.
+    pub fn count<T: Component>(&self) -> usize {
+        self.storage::<T>().map_or(0, |storage| storage.len())
+    }
+
+    pub fn storage<T: Component>(&self) -> Option<&ComponentStorage<T>> {
+        self.storages.get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref::<ComponentStorage<T>>())
+    }
+
+    pub(crate) fn storage_mut<T: Component>(&mut self) -> Option<&mut ComponentStorage<T>> {
+        self.storages.get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<ComponentStorage<T>>())
+    }
+
+    pub fn query<Q: Fetch>(&mut self) -> Query<'_, Q> {
+        Query::new(self)
+    }
+
+    pub fn query_filtered<Q: Fetch, F: Filter>(&mut self) -> Query<'_, Q, F> {
+        Query::new(self)
+    }
+
+    pub fn query_read<Q: ReadOnlyFetch>(&self) -> Query<'_, Q> {
+        Query::new_read(self)
+    }
+
+    pub fn query_read_filtered<Q: ReadOnlyFetch, F: Filter>(&self) -> Query<'_, Q, F> {
+        Query::new_read(self)
+    }
+
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+
+    /// Ends the current change detection frame, `Changed` filters only see later modifications.
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.change_tick += 1;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn despawn_removes_components() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, 1u32);
+        world.insert(entity, "name");
+        assert!(world.despawn(entity));
+        assert!(!world.despawn(entity));
+        assert_eq!(world.count::<u32>(), 0);
+        assert_eq!(world.count::<&str>(), 0);
+        let recycled = world.spawn();
+        assert_eq!(world.get::<u32>(recycled), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_on_despawned_entity_panics() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.despawn(entity);
+        world.insert(entity, 1u32);
+    }
+}
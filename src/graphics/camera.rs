@@ -1,5 +1,6 @@
 pub use cgmath::prelude::*;
 pub use cgmath::{Vector3, Matrix4, Point3, Quaternion, Euler, Deg};
+use cgmath::Vector2;
 
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -23,7 +24,46 @@ impl Camera {
         }
     }
 
+    pub fn projection(&self) -> Matrix4<f32> {
+        cgmath::perspective(Deg(45.0), 1024.0/768.0, 0.1, 100.0)
+    }
+
     pub fn lookat(&self) -> Matrix4<f32> {
         Matrix4::look_at_dir(Point3::new(self.position.x, self.position.y, self.position.z), self.direction, Vector3::new(0.0, 1.0, 0.0))
     }
-}
\ No newline at end of file
+}
+
+/// Orthographic camera of the sprites, in pixels with y up.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera2D {
+    /// World position shown at the center of the window.
+    pub position: Vector2<f32>,
+    /// Window pixels per world unit.
+    pub zoom: f32,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Self::new(Vector2::new(0.0, 0.0), 1.0)
+    }
+}
+
+impl Camera2D {
+    pub fn new(position: Vector2<f32>, zoom: f32) -> Self {
+        Self {
+            position,
+            zoom,
+        }
+    }
+
+    /// Projection for a viewport of `width` by `height` pixels.
+    pub fn projection(&self, width: f32, height: f32) -> Matrix4<f32> {
+        let half = Vector2::new(width, height) / (2.0 * self.zoom);
+        cgmath::ortho(self.position.x - half.x, self.position.x + half.x, self.position.y - half.y, self.position.y + half.y, -1.0, 1.0)
+    }
+
+    /// World position under `point`, in pixels from the top left corner of a viewport of `width` by `height` pixels.
+    pub fn screen_to_world(&self, point: Vector2<f32>, width: f32, height: f32) -> Vector2<f32> {
+        self.position + Vector2::new(point.x - width / 2.0, height / 2.0 - point.y) / self.zoom
+    }
+}
@@ -0,0 +1,78 @@
+use crate::ecs::Component;
+use crate::graphics::{Index, ComponentStorageManager};
+use crate::graphics::entity::EntityBuilder;
+use crate::graphics::states::EntityState;
+use crate::graphics::game_states::{GameState, Transition};
+
+
+pub(crate) enum Command {
+    Build(Index, Box<EntityBuilder>),
+    Apply(Box<dyn FnOnce(&mut ComponentStorageManager)>),
+    AddStates(Index, Box<dyn EntityState>),
+    Despawn(Index),
+    Transition(Transition),
+}
+
+/// Structural changes requested while the world is being iterated, the engine applies them at the end of each stage.
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the builder components to an entity allocated with `ComponentStorageManager::spawn`,
+    /// `GameData::spawn` does both.
+    pub fn build(&mut self, id: Index, builder: EntityBuilder) {
+        self.queue.push(Command::Build(id, Box::new(builder)));
+    }
+
+    pub fn insert<T: Component>(&mut self, id: Index, component: T) {
+        self.queue.push(Command::Apply(Box::new(move |storage: &mut ComponentStorageManager| {
+            if storage.is_alive(id) {
+                storage.insert(id, component);
+            }
+        })));
+    }
+
+    pub fn remove<T: Component>(&mut self, id: Index) {
+        self.queue.push(Command::Apply(Box::new(move |storage: &mut ComponentStorageManager| {
+            storage.remove::<T>(id);
+        })));
+    }
+
+    pub fn add_states<T: EntityState + 'static>(&mut self, id: Index, states: T) {
+        self.queue.push(Command::AddStates(id, Box::new(states)));
+    }
+
+    pub fn despawn(&mut self, id: Index) {
+        self.queue.push(Command::Despawn(id));
+    }
+
+    pub fn push_state<T: GameState + 'static>(&mut self, state: T) {
+        self.queue.push(Command::Transition(Transition::Push(Box::new(state))));
+    }
+
+    pub fn pop_state(&mut self) {
+        self.queue.push(Command::Transition(Transition::Pop));
+    }
+
+    pub fn switch_state<T: GameState + 'static>(&mut self, state: T) {
+        self.queue.push(Command::Transition(Transition::Switch(Box::new(state))));
+    }
+
+    pub fn quit(&mut self) {
+        self.queue.push(Command::Transition(Transition::Quit));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.queue)
+    }
+}
@@ -0,0 +1,654 @@
+use cgmath::{Matrix, Matrix3, Matrix4, Vector3, Vector4, SquareMatrix, InnerSpace};
+use crate::ecs::World;
+use crate::graphics::{Mesh, Transform};
+use crate::graphics::camera::Camera;
+use crate::graphics::light::{Light, PointLight};
+use crate::graphics::shader::Shader;
+
+
+/// Length of the normal and tangent lines.
+const DEBUG_VECTOR_LENGTH: f32 = 0.2;
+const NORMAL_COLOR: Vector4<f32> = Vector4::new(0.0, 0.0, 1.0, 1.0);
+const TANGENT_COLOR: Vector4<f32> = Vector4::new(1.0, 0.0, 0.0, 1.0);
+const BOUNDS_COLOR: Vector4<f32> = Vector4::new(1.0, 1.0, 0.0, 1.0);
+const FRUSTUM_COLOR: Vector4<f32> = Vector4::new(1.0, 1.0, 1.0, 1.0);
+const WIREFRAME_COLOR: Vector4<f32> = Vector4::new(0.0, 0.0, 0.0, 1.0);
+const CIRCLE_SEGMENTS: u32 = 24;
+const GLYPH_HEIGHT: f32 = 6.0;
+const GLYPH_ADVANCE: f32 = 6.0;
+
+/// Built-in debug views, toggled at runtime with `DebugDraw::toggle_view`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DebugView {
+    /// Triangle edges drawn over the shaded meshes.
+    Wireframe,
+    Normals,
+    Tangents,
+    /// World space bounding box of each mesh.
+    Bounds,
+    Lights,
+    /// Frustum of the entities holding a `Camera` component.
+    Frustums,
+}
+
+impl DebugView {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+struct DebugVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+/// How long a debug shape stays visible and whether it is hidden by the scene.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DebugOptions {
+    /// Seconds the shape is drawn for, 0 draws it for the current frame only.
+    pub duration: f32,
+    /// Drawn over the scene without depth test.
+    pub on_top: bool,
+}
+
+impl Default for DebugOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOptions {
+    pub fn new() -> Self {
+        Self {
+            duration: 0.0,
+            on_top: false,
+        }
+    }
+
+    pub fn on_top(mut self) -> Self {
+        self.on_top = true;
+        self
+    }
+
+    pub fn for_seconds(mut self, duration: f32) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct DebugLine {
+    vertices: [DebugVertex; 2],
+    options: DebugOptions,
+}
+
+#[derive(Debug, Clone)]
+struct DebugLabel {
+    position: Vector3<f32>,
+    text: String,
+    size: f32,
+    color: Vector4<f32>,
+    options: DebugOptions,
+}
+
+/// Lines, shapes and labels drawn over the scene, batched into one vertex buffer per frame.
+#[derive(Debug, Default)]
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+    labels: Vec<DebugLabel>,
+    options: DebugOptions,
+    views: u32,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `draw` with the shapes it adds using `options`, e.g. `DebugOptions::new().on_top().for_seconds(2.0)`.
+    pub fn with_options<F: FnOnce(&mut DebugDraw)>(&mut self, options: DebugOptions, draw: F) {
+        let previous = std::mem::replace(&mut self.options, options);
+        draw(self);
+        self.options = previous;
+    }
+
+    pub fn line(&mut self, start: Vector3<f32>, end: Vector3<f32>, color: Vector4<f32>) {
+        self.lines.push(DebugLine {
+            vertices: [
+                DebugVertex { position: start.into(), color: color.into() },
+                DebugVertex { position: end.into(), color: color.into() },
+            ],
+            options: self.options,
+        });
+    }
+
+    /// Line ending with a head of four strokes at `end`.
+    pub fn arrow(&mut self, start: Vector3<f32>, end: Vector3<f32>, color: Vector4<f32>) {
+        self.line(start, end, color);
+        let direction = end - start;
+        let length = direction.magnitude();
+        if length == 0.0 {
+            return;
+        }
+        let direction = direction / length;
+        let (side, up) = Self::perpendiculars(direction);
+        let head = length.min(1.0) * 0.2;
+        for offset in [side, -side, up, -up] {
+            self.line(end, end - direction * head + offset * head * 0.5, color);
+        }
+    }
+
+    /// Three axis aligned lines of length `size` crossing at `center`.
+    pub fn cross(&mut self, center: Vector3<f32>, size: f32, color: Vector4<f32>) {
+        let half = size / 2.0;
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            self.line(center - axis * half, center + axis * half, color);
+        }
+    }
+
+    pub fn circle(&mut self, center: Vector3<f32>, normal: Vector3<f32>, radius: f32, color: Vector4<f32>) {
+        let (side, up) = Self::perpendiculars(normal.normalize());
+        let point = |i: u32| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (side * angle.cos() + up * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Wire sphere made of one circle around each axis.
+    pub fn sphere(&mut self, center: Vector3<f32>, radius: f32, color: Vector4<f32>) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            self.circle(center, axis, radius, color);
+        }
+    }
+
+    pub fn aabb(&mut self, min: Vector3<f32>, max: Vector3<f32>, color: Vector4<f32>) {
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| Vector3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        ));
+        self.box_edges(&corners, color);
+    }
+
+    /// Unit cube centered on the origin transformed by `model`, e.g. the `local_transform` of an entity.
+    pub fn oriented_box(&mut self, model: Matrix4<f32>, color: Vector4<f32>) {
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            let corner = Vector3::new(
+                if i & 1 == 0 { -0.5 } else { 0.5 },
+                if i & 2 == 0 { -0.5 } else { 0.5 },
+                if i & 4 == 0 { -0.5 } else { 0.5 },
+            );
+            (model * corner.extend(1.0)).truncate()
+        });
+        self.box_edges(&corners, color);
+    }
+
+    /// Square grid on the XZ plane with `divisions` cells per side.
+    pub fn grid(&mut self, center: Vector3<f32>, size: f32, divisions: u32, color: Vector4<f32>) {
+        let half = size / 2.0;
+        let divisions = divisions.max(1);
+        for i in 0..=divisions {
+            let offset = -half + size * i as f32 / divisions as f32;
+            self.line(center + Vector3::new(offset, 0.0, -half), center + Vector3::new(offset, 0.0, half), color);
+            self.line(center + Vector3::new(-half, 0.0, offset), center + Vector3::new(half, 0.0, offset), color);
+        }
+    }
+
+    /// Label facing the camera, `size` is the height of a character in world units.
+    /// Letters, digits and common punctuation are drawn with strokes, other characters are left blank.
+    pub fn text(&mut self, position: Vector3<f32>, text: &str, size: f32, color: Vector4<f32>) {
+        self.labels.push(DebugLabel { position, text: text.to_string(), size, color, options: self.options });
+    }
+
+    /// Edges of the volume seen through `view_projection`.
+    pub fn frustum(&mut self, view_projection: Matrix4<f32>, color: Vector4<f32>) {
+        let inverse = match view_projection.invert() {
+            Some(inverse) => inverse,
+            None => return,
+        };
+        let corners = [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            let ndc = Vector4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+                1.0,
+            );
+            let corner = inverse * ndc;
+            corner.truncate() / corner.w
+        });
+        self.box_edges(&corners, color);
+    }
+
+    /// Corners are indexed by their x, y and z bits, the edges join the corners differing by one bit.
+    fn box_edges(&mut self, corners: &[Vector3<f32>; 8], color: Vector4<f32>) {
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// Two unit vectors orthogonal to `direction` and to each other.
+    fn perpendiculars(direction: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+        let reference = if direction.y.abs() < 0.99 { Vector3::unit_y() } else { Vector3::unit_x() };
+        let side = direction.cross(reference).normalize();
+        (side, side.cross(direction))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.labels.clear();
+    }
+
+    pub fn toggle_view(&mut self, view: DebugView) {
+        self.views ^= view.bit();
+    }
+
+    pub fn set_view(&mut self, view: DebugView, enabled: bool) {
+        if enabled {
+            self.views |= view.bit();
+        } else {
+            self.views &= !view.bit();
+        }
+    }
+
+    pub fn is_view_enabled(&self, view: DebugView) -> bool {
+        self.views & view.bit() != 0
+    }
+
+    /// Removes the shapes whose duration is over, called at the start of each frame for the shapes of the previous one.
+    pub(crate) fn update(&mut self, delta: f32) {
+        self.lines.retain_mut(|line| {
+            line.options.duration -= delta;
+            line.options.duration > 0.0
+        });
+        self.labels.retain_mut(|label| {
+            label.options.duration -= delta;
+            label.options.duration > 0.0
+        });
+    }
+
+    /// Vertices of the depth tested and of the always on top lines, labels are turned toward `camera`.
+    fn build_vertices(&self, camera: &Camera) -> (Vec<DebugVertex>, Vec<DebugVertex>) {
+        let mut depth_tested = Vec::new();
+        let mut on_top = Vec::new();
+        for line in self.lines.iter() {
+            let vertices = if line.options.on_top { &mut on_top } else { &mut depth_tested };
+            vertices.extend_from_slice(&line.vertices);
+        }
+        let forward = camera.direction.normalize();
+        let world_up = if forward.y.abs() < 0.99 { Vector3::unit_y() } else { Vector3::unit_z() };
+        let right = forward.cross(world_up).normalize();
+        let up = right.cross(forward);
+        for label in self.labels.iter() {
+            let vertices = if label.options.on_top { &mut on_top } else { &mut depth_tested };
+            let scale = label.size / GLYPH_HEIGHT;
+            for (i, character) in label.text.chars().enumerate() {
+                let origin = label.position + right * (i as f32 * GLYPH_ADVANCE * scale);
+                for (x1, y1, x2, y2) in glyph_strokes(character) {
+                    for (x, y) in [(x1, y1), (x2, y2)] {
+                        let position = origin + right * (x * scale) + up * (y * scale);
+                        vertices.push(DebugVertex { position: position.into(), color: label.color.into() });
+                    }
+                }
+            }
+        }
+        (depth_tested, on_top)
+    }
+
+    /// Adds the lines of the enabled views, the wireframe is drawn by the renderer.
+    pub(crate) fn collect_views(&mut self, world: &World, light: &Light, camera: &Camera) {
+        let normals = self.is_view_enabled(DebugView::Normals);
+        let tangents = self.is_view_enabled(DebugView::Tangents);
+        let bounds = self.is_view_enabled(DebugView::Bounds);
+        if normals || tangents || bounds {
+            for (mesh, transform) in world.query_read::<(&Mesh, &Transform)>().iter() {
+                let model = transform.local_transform;
+                if normals {
+                    self.mesh_vectors(mesh, model, &mesh.normals, NORMAL_COLOR);
+                }
+                if tangents {
+                    self.mesh_vectors(mesh, model, &mesh.calculate_tangents(), TANGENT_COLOR);
+                }
+                if bounds {
+                    self.mesh_bounds(mesh, model);
+                }
+            }
+        }
+        if self.is_view_enabled(DebugView::Lights) {
+            self.cross(light.position, 0.5, light.color.extend(1.0));
+            self.line(light.position, Vector3::new(0.0, 0.0, 0.0), light.color.extend(0.5));
+            for (point_light, transform) in world.query_read::<(&PointLight, &Transform)>().iter() {
+                self.cross(transform.translation, 0.25, point_light.color.extend(1.0));
+                self.sphere(transform.translation, point_light.radius, point_light.color.extend(0.3));
+            }
+        }
+        if self.is_view_enabled(DebugView::Frustums) {
+            //The engine camera first, then the camera components
+            let mut query = world.query_read::<&Camera>();
+            for camera in std::iter::once(camera).chain(query.iter()) {
+                self.frustum(camera.projection() * camera.lookat(), FRUSTUM_COLOR);
+            }
+        }
+    }
+
+    /// One line per vertex along `vectors`, given in the local space of the mesh.
+    fn mesh_vectors(&mut self, mesh: &Mesh, model: Matrix4<f32>, vectors: &[Vector3<f32>], color: Vector4<f32>) {
+        let linear = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+        let normal_matrix = linear.invert().map_or(linear, |inverse| inverse.transpose());
+        for (position, vector) in mesh.positions.iter().zip(vectors.iter()) {
+            let direction = normal_matrix * vector;
+            if direction.magnitude2() == 0.0 {
+                continue;
+            }
+            let start = (model * position.to_vector().extend(1.0)).truncate();
+            self.line(start, start + direction.normalize() * DEBUG_VECTOR_LENGTH, color);
+        }
+    }
+
+    fn mesh_bounds(&mut self, mesh: &Mesh, model: Matrix4<f32>) {
+        let (min, max) = match mesh.bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let mut world_min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut world_max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for i in 0..8 {
+            let corner = Vector3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let corner = (model * corner.extend(1.0)).truncate();
+            world_min = Vector3::new(world_min.x.min(corner.x), world_min.y.min(corner.y), world_min.z.min(corner.z));
+            world_max = Vector3::new(world_max.x.max(corner.x), world_max.y.max(corner.y), world_max.z.max(corner.z));
+        }
+        self.aabb(world_min, world_max, BOUNDS_COLOR);
+    }
+}
+
+/// Strokes of a character on a 4 by 6 grid, as "x1y1x2y2" groups.
+fn glyph(character: char) -> &'static str {
+    match character.to_ascii_uppercase() {
+        '0' => "0040 4046 4606 0600 0046",
+        '1' => "2026 2615 0040",
+        '2' => "0646 4643 4303 0300 0040",
+        '3' => "0646 4640 4000 0343",
+        '4' => "0603 0343 4640",
+        '5' | 'S' => "4606 0603 0343 4340 4000",
+        '6' => "4606 0600 0040 4043 4303",
+        '7' => "0646 4620",
+        '8' => "0040 4046 4606 0600 0343",
+        '9' => "4303 0306 0646 4640 4000",
+        'A' => "0026 2640 1333",
+        'B' => "0006 0636 3633 0343 4340 4000",
+        'C' => "4606 0600 0040",
+        'D' => "0006 0636 3645 4541 4130 3000",
+        'E' => "4606 0600 0040 0333",
+        'F' => "4606 0600 0333",
+        'G' => "4606 0600 0040 4043 4323",
+        'H' => "0006 4046 0343",
+        'I' => "0646 2026 0040",
+        'J' => "0646 3630 3010 1001",
+        'K' => "0006 0346 0340",
+        'L' => "0600 0040",
+        'M' => "0006 0623 2346 4640",
+        'N' => "0006 0640 4046",
+        'O' => "0040 4046 4606 0600",
+        'P' => "0006 0646 4643 4303",
+        'Q' => "0040 4046 4606 0600 2241",
+        'R' => "0006 0646 4643 4303 2340",
+        'T' => "0646 2620",
+        'U' => "0600 0040 4046",
+        'V' => "0620 2046",
+        'W' => "0600 0023 2340 4046",
+        'X' => "0046 0640",
+        'Y' => "0623 2346 2320",
+        'Z' => "0646 4600 0040",
+        '-' => "0343",
+        '+' => "0343 2125",
+        '=' => "0242 0444",
+        '.' => "2021",
+        ',' => "2110",
+        ':' => "2122 2425",
+        '/' => "0046",
+        '_' => "0040",
+        '(' => "3012 1214 1436",
+        ')' => "1032 3234 3416",
+        _ => "",
+    }
+}
+
+fn glyph_strokes(character: char) -> impl Iterator<Item = (f32, f32, f32, f32)> {
+    glyph(character).split_whitespace().map(|stroke| {
+        let digit = |i: usize| (stroke.as_bytes()[i] - b'0') as f32;
+        (digit(0), digit(1), digit(2), digit(3))
+    })
+}
+
+/// GL resources drawing the `DebugDraw` lines and the wireframe overlay.
+pub(crate) struct DebugRenderer {
+    line_shader: Shader,
+    wireframe_shader: Shader,
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+}
+
+impl DebugRenderer {
+    pub(crate) fn new() -> Self {
+        let line_vert = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+        layout (location = 1) in vec4 aColor;
+
+        out vec4 ourColor;
+
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+
+        void main()
+        {
+            gl_Position = projection * view * vec4(aPos, 1.0);
+            ourColor = aColor;
+        }
+    "#;
+        let line_frag = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        in vec4 ourColor;
+
+        void main()
+        {
+            FragColor = ourColor;
+        }
+    "#;
+        let wireframe_vert = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+        layout (location = 4) in mat4 aInstanceModel;
+
+        uniform mat4 model;
+        uniform bool instanced;
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+
+        void main()
+        {
+            gl_Position = projection * view * (instanced ? aInstanceModel : model) * vec4(aPos, 1.0);
+        }
+    "#;
+        let wireframe_frag = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        uniform vec4 color;
+
+        void main()
+        {
+            FragColor = color;
+        }
+    "#;
+
+        let mut renderer = Self {
+            line_shader: Shader::new(line_vert, line_frag),
+            wireframe_shader: Shader::new(wireframe_vert, wireframe_frag),
+            vao: 0,
+            vbo: 0,
+        };
+        let stride = std::mem::size_of::<DebugVertex>() as gl::types::GLint;
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::BindVertexArray(renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 3]>() as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+        renderer
+    }
+
+    /// Shader drawing meshes in a flat color, the polygon mode is set by the caller.
+    pub(crate) fn wireframe_shader(&self) -> Shader {
+        self.wireframe_shader.use_program();
+        self.wireframe_shader.set_vec4("color", WIREFRAME_COLOR);
+        self.wireframe_shader
+    }
+
+    /// Draws the depth tested lines then the ones on top of everything, both from one stream buffer.
+    pub(crate) fn draw_lines(&self, debug_draw: &DebugDraw, camera: &Camera) {
+        let (depth_tested, on_top) = debug_draw.build_vertices(camera);
+        if depth_tested.is_empty() && on_top.is_empty() {
+            return;
+        }
+        let vertex_size = std::mem::size_of::<DebugVertex>();
+        self.line_shader.use_program();
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                ((depth_tested.len() + on_top.len()) * vertex_size) as gl::types::GLsizeiptr,
+                std::ptr::null(),
+                gl::STREAM_DRAW,
+            );
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, (depth_tested.len() * vertex_size) as gl::types::GLsizeiptr, depth_tested.as_ptr() as *const gl::types::GLvoid);
+            gl::BufferSubData(
+                gl::ARRAY_BUFFER,
+                (depth_tested.len() * vertex_size) as gl::types::GLintptr,
+                (on_top.len() * vertex_size) as gl::types::GLsizeiptr,
+                on_top.as_ptr() as *const gl::types::GLvoid,
+            );
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DrawArrays(gl::LINES, 0, depth_tested.len() as i32);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::DrawArrays(gl::LINES, depth_tested.len() as i32, on_top.len() as i32);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::primitives::PrimitiveBuilder;
+    use crate::graphics::mesh::Color;
+
+    #[test]
+    fn shapes_add_lines() {
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.line(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), FRUSTUM_COLOR);
+        debug_draw.aabb(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0), BOUNDS_COLOR);
+        assert_eq!(debug_draw.line_count(), 13);
+        debug_draw.frustum(Camera::default().projection() * Camera::default().lookat(), FRUSTUM_COLOR);
+        assert_eq!(debug_draw.line_count(), 25);
+        debug_draw.clear();
+        assert_eq!(debug_draw.line_count(), 0);
+    }
+
+    #[test]
+    fn views_follow_toggles() {
+        let mut world = World::new();
+        let id = world.spawn();
+        world.insert(id, PrimitiveBuilder::plane(Color::new(1.0, 1.0, 1.0)));
+        world.insert(id, Transform::new_default());
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.collect_views(&world, &Light::default(), &Camera::default());
+        assert_eq!(debug_draw.line_count(), 0);
+
+        debug_draw.toggle_view(DebugView::Bounds);
+        debug_draw.set_view(DebugView::Lights, true);
+        assert!(debug_draw.is_view_enabled(DebugView::Bounds) && !debug_draw.is_view_enabled(DebugView::Normals));
+        debug_draw.collect_views(&world, &Light::default(), &Camera::default());
+        assert_eq!(debug_draw.line_count(), 12 + 4);
+        debug_draw.toggle_view(DebugView::Bounds);
+        assert!(!debug_draw.is_view_enabled(DebugView::Bounds));
+
+        //The engine camera frustum is drawn along those of the camera components
+        debug_draw.clear();
+        debug_draw.set_view(DebugView::Lights, false);
+        debug_draw.set_view(DebugView::Frustums, true);
+        debug_draw.collect_views(&world, &Light::default(), &Camera::default());
+        assert_eq!(debug_draw.line_count(), 12);
+        world.insert(id, Camera::default());
+        debug_draw.clear();
+        debug_draw.collect_views(&world, &Light::default(), &Camera::default());
+        assert_eq!(debug_draw.line_count(), 24);
+    }
+
+    #[test]
+    fn shapes_last_for_their_duration() {
+        let mut debug_draw = DebugDraw::new();
+        debug_draw.sphere(Vector3::new(0.0, 0.0, 0.0), 1.0, FRUSTUM_COLOR);
+        debug_draw.with_options(DebugOptions::new().on_top().for_seconds(1.0), |debug_draw| {
+            debug_draw.arrow(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), FRUSTUM_COLOR);
+            debug_draw.text(Vector3::new(0.0, 0.0, 0.0), "Hi 1", 0.5, FRUSTUM_COLOR);
+        });
+        debug_draw.grid(Vector3::new(0.0, 0.0, 0.0), 10.0, 10, FRUSTUM_COLOR);
+        assert_eq!(debug_draw.line_count(), 3 * CIRCLE_SEGMENTS as usize + 5 + 22);
+
+        let (depth_tested, on_top) = debug_draw.build_vertices(&Camera::default());
+        assert_eq!(depth_tested.len(), 2 * (3 * CIRCLE_SEGMENTS as usize + 22));
+        //5 arrow lines, 3 strokes for H, 3 for I and 3 for 1
+        assert_eq!(on_top.len(), 2 * (5 + 9));
+
+        debug_draw.update(0.5);
+        assert_eq!((debug_draw.line_count(), debug_draw.label_count()), (5, 1));
+        debug_draw.update(0.5);
+        assert_eq!((debug_draw.line_count(), debug_draw.label_count()), (0, 0));
+    }
+}
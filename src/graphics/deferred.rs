@@ -0,0 +1,401 @@
+use std::f32::consts::PI;
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3, InnerSpace};
+use crate::graphics::shader::Shader;
+use crate::graphics::render_state::{RenderState, BlendMode, CullMode};
+use crate::graphics::light::{include_lights, PointLightData};
+use crate::graphics::fog::include_fog;
+use crate::graphics::post_process::FULLSCREEN_VERT;
+
+
+/// First texture unit of the G-buffer textures, the ones before hold the environment maps.
+pub const GBUFFER_UNIT: u32 = 4;
+
+const SPHERE_SEGMENTS: usize = 12;
+const SPHERE_RINGS: usize = 8;
+
+/// Frame block, G-buffer samplers and position reconstruction shared by the lighting passes.
+const GBUFFER_GLSL: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+        uniform sampler2D gAlbedo;
+        uniform sampler2D gNormal;
+        uniform sampler2D gMaterial;
+        uniform sampler2D gDepth;
+        uniform mat4 inverseViewProjection;
+        #include "lights"
+        #include "fog"
+
+        vec3 worldPosition(vec2 uv, float depth)
+        {
+            vec4 world = inverseViewProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+            return world.xyz / world.w;
+        }
+    "#;
+
+/// Ambient and scene light of the lit surfaces, unlit ones keep their albedo. Background pixels are left untouched.
+const LIGHTING_FRAG: &str = r#"
+        in vec2 TexCoord;
+
+        void main()
+        {
+            float depth = texture(gDepth, TexCoord).r;
+            if (depth == 1.0)
+                discard;
+            vec3 albedo = texture(gAlbedo, TexCoord).rgb;
+            vec4 material = texture(gMaterial, TexCoord);
+            vec3 fragPos = worldPosition(TexCoord, depth);
+            vec3 color = albedo;
+            if (material.y > 0.5) {
+                vec3 norm = normalize(texture(gNormal, TexCoord).xyz);
+                vec3 viewDir = normalize(viewPos.xyz - fragPos);
+                color = sceneLight(fragPos, norm, viewDir, material.x) * albedo;
+            }
+            FragColor = vec4(applyFog(color, fragPos, viewPos.xyz), 1.0);
+        }
+    "#;
+
+const VOLUME_VERT: &str = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+        layout (location = 1) in vec4 aLightPosition;
+        layout (location = 2) in vec4 aLightColor;
+
+        flat out vec4 volumePosition;
+        flat out vec4 volumeColor;
+
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+        uniform float volumeScale;
+
+        void main()
+        {
+            volumePosition = aLightPosition;
+            volumeColor = aLightColor;
+            vec3 position = aLightPosition.xyz + aPos * aLightPosition.w * volumeScale;
+            gl_Position = projection * view * vec4(position, 1.0);
+        }
+    "#;
+
+/// Light added by one point light, faded by the fog the same way `applyFog` fades the forward lighting.
+const VOLUME_FRAG: &str = r#"
+        flat in vec4 volumePosition;
+        flat in vec4 volumeColor;
+
+        uniform vec2 screenSize;
+
+        void main()
+        {
+            vec2 uv = gl_FragCoord.xy / screenSize;
+            float depth = texture(gDepth, uv).r;
+            vec4 material = texture(gMaterial, uv);
+            if (depth == 1.0 || material.y < 0.5)
+                discard;
+            vec3 albedo = texture(gAlbedo, uv).rgb;
+            vec3 fragPos = worldPosition(uv, depth);
+            vec3 norm = normalize(texture(gNormal, uv).xyz);
+            vec3 viewDir = normalize(viewPos.xyz - fragPos);
+            vec3 light = pointLight(volumePosition, volumeColor.rgb, fragPos, norm, viewDir, material.x);
+            FragColor = vec4(light * albedo * (1.0 - fogFactor(fragPos, viewPos.xyz)), 1.0);
+        }
+    "#;
+
+/// Targets of the geometry pass: albedo, world space normals, material parameters (x specular strength, y lit) and depth.
+struct GBuffer {
+    fbo: gl::types::GLuint,
+    albedo: gl::types::GLuint,
+    normal: gl::types::GLuint,
+    material: gl::types::GLuint,
+    depth: gl::types::GLuint,
+    width: i32,
+    height: i32,
+}
+
+impl GBuffer {
+    fn new(width: i32, height: i32) -> Self {
+        let mut fbo = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        }
+        let gbuffer = Self {
+            fbo,
+            albedo: create_texture(gl::RGBA16F, gl::RGBA, gl::FLOAT, width, height),
+            normal: create_texture(gl::RGBA16F, gl::RGBA, gl::FLOAT, width, height),
+            material: create_texture(gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, width, height),
+            depth: create_texture(gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8, width, height),
+            width,
+            height,
+        };
+        unsafe {
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, gbuffer.albedo, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT1, gl::TEXTURE_2D, gbuffer.normal, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT2, gl::TEXTURE_2D, gbuffer.material, 0);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::TEXTURE_2D, gbuffer.depth, 0);
+            let attachments = [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1, gl::COLOR_ATTACHMENT2];
+            gl::DrawBuffers(attachments.len() as i32, attachments.as_ptr());
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                println!("G-buffer {}x{} is incomplete", width, height);
+            }
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        gbuffer
+    }
+
+    fn textures(&self) -> [gl::types::GLuint; 4] {
+        [self.albedo, self.normal, self.material, self.depth]
+    }
+
+    fn delete(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            let textures = self.textures();
+            gl::DeleteTextures(textures.len() as i32, textures.as_ptr());
+        }
+    }
+}
+
+fn create_texture(internal_format: gl::types::GLenum, format: gl::types::GLenum, kind: gl::types::GLenum, width: i32, height: i32) -> gl::types::GLuint {
+    let mut texture = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, width, height, 0, format, kind, std::ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    }
+    texture
+}
+
+/// Unit sphere with outward facing counter clockwise triangles.
+fn sphere_volume() -> (Vec<[f32; 3]>, Vec<u32>) {
+    let mut positions = Vec::new();
+    for ring in 0..=SPHERE_RINGS {
+        let theta = PI * ring as f32 / SPHERE_RINGS as f32;
+        for segment in 0..SPHERE_SEGMENTS {
+            let phi = 2.0 * PI * segment as f32 / SPHERE_SEGMENTS as f32;
+            positions.push([theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()]);
+        }
+    }
+    let mut indices = Vec::new();
+    for ring in 0..SPHERE_RINGS {
+        for segment in 0..SPHERE_SEGMENTS {
+            let next = (segment + 1) % SPHERE_SEGMENTS;
+            let (a, b) = ((ring * SPHERE_SEGMENTS + segment) as u32, (ring * SPHERE_SEGMENTS + next) as u32);
+            let (c, d) = (((ring + 1) * SPHERE_SEGMENTS + segment) as u32, ((ring + 1) * SPHERE_SEGMENTS + next) as u32);
+            indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+    }
+    (positions, indices)
+}
+
+/// Scale making the faces of the volume, and not only its vertices, enclose the unit sphere.
+fn volume_scale(positions: &[[f32; 3]], indices: &[u32]) -> f32 {
+    let nearest = indices.chunks(3).filter_map(|triangle| {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() < 1e-10 {
+            return None;
+        }
+        Some(normal.normalize().dot(a).abs())
+    }).fold(1.0f32, f32::min);
+    1.0 / nearest
+}
+
+/// Deferred path: opaque surfaces are written to a G-buffer, then lit once by the scene light and once per point light
+/// inside the sphere bounding its radius. The forward path also fills the G-buffer when the SSAO needs it.
+pub(crate) struct DeferredRenderer {
+    gbuffer: Option<GBuffer>,
+    /// Framebuffer bound when the geometry pass started, receiving the lighting.
+    target: gl::types::GLuint,
+    lighting_shader: Shader,
+    volume_shader: Shader,
+    fullscreen_vao: gl::types::GLuint,
+    volume_vao: gl::types::GLuint,
+    instance_vbo: gl::types::GLuint,
+    volume_index_count: i32,
+    volume_scale: f32,
+}
+
+impl DeferredRenderer {
+    pub(crate) fn new() -> Self {
+        let lighting_source = include_fog(&include_lights(&format!("{}{}", GBUFFER_GLSL, LIGHTING_FRAG)));
+        let volume_source = include_fog(&include_lights(&format!("{}{}", GBUFFER_GLSL, VOLUME_FRAG)));
+        let (positions, indices) = sphere_volume();
+        let mut renderer = Self {
+            gbuffer: None,
+            target: 0,
+            lighting_shader: Shader::new(FULLSCREEN_VERT, &lighting_source),
+            volume_shader: Shader::new(VOLUME_VERT, &volume_source),
+            fullscreen_vao: 0,
+            volume_vao: 0,
+            instance_vbo: 0,
+            volume_index_count: indices.len() as i32,
+            volume_scale: volume_scale(&positions, &indices),
+        };
+        let light_stride = std::mem::size_of::<PointLightData>() as gl::types::GLint;
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.fullscreen_vao);
+            gl::GenVertexArrays(1, &mut renderer.volume_vao);
+            gl::BindVertexArray(renderer.volume_vao);
+            let (mut vbo, mut ebo) = (0, 0);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(positions.as_slice()) as gl::types::GLsizeiptr, positions.as_ptr() as *const gl::types::GLvoid, gl::STATIC_DRAW);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, std::mem::size_of_val(indices.as_slice()) as gl::types::GLsizeiptr, indices.as_ptr() as *const gl::types::GLvoid, gl::STATIC_DRAW);
+            //Locations 1 and 2 hold the position and the color of the light
+            gl::GenBuffers(1, &mut renderer.instance_vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.instance_vbo);
+            for location in 1..3 {
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, light_stride, ((location as usize - 1) * std::mem::size_of::<[f32; 4]>()) as *const gl::types::GLvoid);
+                gl::VertexAttribDivisor(location, 1);
+            }
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        renderer
+    }
+
+    /// Binds and clears the G-buffer, sized like the viewport. The framebuffer bound before receives the lighting.
+    pub(crate) fn begin_geometry(&mut self) {
+        let mut target = 0;
+        let mut viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut target);
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        }
+        self.target = target as gl::types::GLuint;
+        let (width, height) = (viewport[2].max(1), viewport[3].max(1));
+        if self.gbuffer.as_ref().is_none_or(|gbuffer| (gbuffer.width, gbuffer.height) != (width, height)) {
+            if let Some(mut old) = self.gbuffer.take() {
+                old.delete();
+            }
+            self.gbuffer = Some(GBuffer::new(width, height));
+        }
+        let gbuffer = self.gbuffer.as_ref().unwrap();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, gbuffer.fbo);
+            gl::Viewport(0, 0, width, height);
+            gl::DepthMask(gl::TRUE);
+            let zero = [0.0f32; 4];
+            for buffer in 0..3 {
+                gl::ClearBufferfv(gl::COLOR, buffer, zero.as_ptr());
+            }
+            gl::ClearBufferfi(gl::DEPTH_STENCIL, 0, 1.0, 0);
+        }
+    }
+
+    /// Binds back the framebuffer bound before `begin_geometry`.
+    pub(crate) fn end_geometry(&self) {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, self.target) };
+    }
+
+    /// Normal and depth textures of the G-buffer, with its size.
+    pub(crate) fn normal_and_depth(&self) -> Option<(gl::types::GLuint, gl::types::GLuint, i32, i32)> {
+        self.gbuffer.as_ref().map(|gbuffer| (gbuffer.normal, gbuffer.depth, gbuffer.width, gbuffer.height))
+    }
+
+    /// Copies the G-buffer depth to the target, for the forward draws, then adds the scene light and the point lights.
+    /// `set_environment` sets the environment uniforms of the lighting shaders, whose maps must be bound.
+    pub(crate) fn light<F: Fn(&Shader)>(&self, lights: &[PointLightData], view_projection: Matrix4<f32>, set_environment: F) {
+        let gbuffer = match &self.gbuffer {
+            Some(gbuffer) => gbuffer,
+            None => return,
+        };
+        let (width, height) = (gbuffer.width, gbuffer.height);
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, gbuffer.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.target);
+            gl::BlitFramebuffer(0, 0, width, height, 0, 0, width, height, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.target);
+            for (i, texture) in gbuffer.textures().iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + GBUFFER_UNIT + i as u32);
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+        let inverse_view_projection = view_projection.invert().unwrap_or_else(Matrix4::identity);
+
+        let fullscreen = RenderState { depth_test: false, depth_write: false, ..RenderState::opaque() };
+        fullscreen.apply(None);
+        self.use_lighting_shader(&self.lighting_shader, inverse_view_projection, &set_environment);
+        unsafe {
+            gl::BindVertexArray(self.fullscreen_vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        //Front faces are culled so the volumes still cover the pixels when the camera is inside them
+        let volumes = RenderState { blend: BlendMode::Additive, cull: CullMode::Front, ..fullscreen };
+        if !lights.is_empty() {
+            volumes.apply(Some(&fullscreen));
+            self.use_lighting_shader(&self.volume_shader, inverse_view_projection, &set_environment);
+            self.volume_shader.set_vec2("screenSize", Vector2::new(width as f32, height as f32));
+            self.volume_shader.set_float("volumeScale", self.volume_scale);
+            unsafe {
+                gl::BindVertexArray(self.volume_vao);
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+                gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(lights) as gl::types::GLsizeiptr, lights.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+                gl::DrawElementsInstanced(gl::TRIANGLES, self.volume_index_count, gl::UNSIGNED_INT, std::ptr::null(), lights.len() as i32);
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+        }
+        unsafe { gl::BindVertexArray(0) };
+        RenderState::opaque().apply(Some(if lights.is_empty() { &fullscreen } else { &volumes }));
+    }
+
+    fn use_lighting_shader<F: Fn(&Shader)>(&self, shader: &Shader, inverse_view_projection: Matrix4<f32>, set_environment: &F) {
+        shader.use_program();
+        for (i, name) in ["gAlbedo", "gNormal", "gMaterial", "gDepth"].iter().enumerate() {
+            shader.set_int(name, (GBUFFER_UNIT + i as u32) as i32);
+        }
+        shader.set_mat4("inverseViewProjection", inverse_view_projection);
+        set_environment(shader);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_volume_faces_outward() {
+        let (positions, indices) = sphere_volume();
+        assert_eq!(positions.len(), (SPHERE_RINGS + 1) * SPHERE_SEGMENTS);
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+            let normal = (b - a).cross(c - a);
+            assert!(normal.magnitude2() < 1e-10 || normal.dot(a + b + c) > 0.0);
+        }
+    }
+
+    #[test]
+    fn scaled_volume_encloses_the_radius() {
+        let (positions, indices) = sphere_volume();
+        let scale = volume_scale(&positions, &indices);
+        assert!(scale > 1.0 && scale < 1.2);
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+            assert!(((a + b + c) / 3.0 * scale).magnitude() >= 1.0 - 1e-5);
+        }
+    }
+}
@@ -1,10 +1,13 @@
-use crate::graphics::{Mesh, Material, MaterialBuilder, Transform, PrimitiveBuilder, Engine, Color};
+use crate::graphics::{Mesh, Material, MaterialBuilder, Transform, PrimitiveBuilder, Engine, Color, Index, ComponentStorageManager};
+use crate::graphics::serialization::{MeshAsset, MaterialAsset};
 
 #[derive(Debug)]
 pub struct EntityBuilder {
     mesh: Mesh,
     material: Material,
-    transform: Transform
+    transform: Transform,
+    mesh_asset: Option<String>,
+    material_asset: Option<String>,
 }
 
 impl EntityBuilder {
@@ -13,21 +16,26 @@ impl EntityBuilder {
             mesh: Mesh::new_empty(),
             material: MaterialBuilder::simple_material_2d(),
             transform: Transform::new_default(),
+            mesh_asset: None,
+            material_asset: Some("simple".to_string()),
         }
     }
 
     pub fn with_mesh(&mut self, mesh: Mesh) -> &mut Self {
         self.mesh = mesh;
+        self.mesh_asset = None;
         self
     }
 
     pub fn with_material(&mut self, material: Material) -> &mut Self {
         self.material = material;
+        self.material_asset = None;
         self
     }
 
     pub fn with_texture(&mut self, texture_path: &str) -> &mut Self {
         self.material = MaterialBuilder::simple_texture_material_2d(texture_path);
+        self.material_asset = Some(format!("texture:{}", texture_path));
         self
     }
 
@@ -38,21 +46,33 @@ impl EntityBuilder {
 
     pub fn with_quad_mesh(&mut self, size: f32) -> &mut Self {
         self.mesh = PrimitiveBuilder::quad();
+        self.mesh_asset = Some("quad".to_string());
         self.transform.scale *= size;
         self
     }
 
     pub fn with_cube_mesh(&mut self, size: f32) -> &mut Self {
         self.mesh = PrimitiveBuilder::cube(Color::new(1.0, 0.5, 0.31));
+        self.mesh_asset = Some("cube".to_string());
         self.transform.scale *= size;
         self
     }
 
-    pub fn build(&self, engine: &mut Engine) -> u32 {
+    pub fn build(&self, engine: &mut Engine) -> Index {
         let entity = engine.create_scene_object();
-        engine.add_mesh(entity.id, self.mesh.clone());
-        engine.add_material(entity.id, self.material);
-        engine.add_transform(entity.id, self.transform);
+        self.insert_components(&mut engine.storage, entity.id);
         entity.id
     }
+
+    pub fn insert_components(&self, storage: &mut ComponentStorageManager, id: Index) {
+        storage.insert(id, self.mesh.clone());
+        storage.insert(id, self.material);
+        storage.insert(id, self.transform);
+        if let Some(name) = &self.mesh_asset {
+            storage.insert(id, MeshAsset(name.clone()));
+        }
+        if let Some(name) = &self.material_asset {
+            storage.insert(id, MaterialAsset(name.clone()));
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,564 @@
+extern crate image;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use crate::graphics::shader::Shader;
+
+
+/// Texture units of the environment maps read by the lit materials.
+pub const IRRADIANCE_UNIT: u32 = 1;
+pub const PREFILTER_UNIT: u32 = 2;
+const SKYBOX_UNIT: u32 = 3;
+
+const ENVIRONMENT_SIZE: i32 = 512;
+const IRRADIANCE_SIZE: i32 = 32;
+const PREFILTER_SIZE: i32 = 128;
+/// Mip levels of the prefiltered map, from mirror-like to fully rough reflections.
+pub const PREFILTER_MIPS: i32 = 5;
+
+const CAPTURE_VERT: &str = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+
+        out vec3 LocalPos;
+
+        uniform mat4 projection;
+        uniform mat4 view;
+
+        void main()
+        {
+            LocalPos = aPos;
+            gl_Position = projection * view * vec4(aPos, 1.0);
+        }
+    "#;
+
+const EQUIRECTANGULAR_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec3 LocalPos;
+
+        uniform sampler2D equirectangularMap;
+
+        void main()
+        {
+            vec3 direction = normalize(LocalPos);
+            vec2 uv = vec2(atan(direction.z, direction.x) * 0.1591, asin(direction.y) * 0.3183) + 0.5;
+            FragColor = vec4(texture(equirectangularMap, vec2(uv.x, 1.0 - uv.y)).rgb, 1.0);
+        }
+    "#;
+
+const GRADIENT_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec3 LocalPos;
+
+        uniform vec3 zenith;
+        uniform vec3 horizon;
+        uniform vec3 ground;
+
+        void main()
+        {
+            float height = normalize(LocalPos).y;
+            vec3 color = height > 0.0 ? mix(horizon, zenith, pow(height, 0.5)) : mix(horizon, ground, pow(-height, 0.5));
+            FragColor = vec4(color, 1.0);
+        }
+    "#;
+
+const IRRADIANCE_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec3 LocalPos;
+
+        uniform samplerCube environmentMap;
+
+        const float PI = 3.14159265359;
+
+        void main()
+        {
+            vec3 normal = normalize(LocalPos);
+            vec3 up = abs(normal.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0);
+            vec3 right = normalize(cross(up, normal));
+            up = cross(normal, right);
+
+            vec3 irradiance = vec3(0.0);
+            float samples = 0.0;
+            for (float phi = 0.0; phi < 2.0 * PI; phi += 0.05) {
+                for (float theta = 0.0; theta < 0.5 * PI; theta += 0.05) {
+                    vec3 tangentSample = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+                    vec3 sampleDir = tangentSample.x * right + tangentSample.y * up + tangentSample.z * normal;
+                    irradiance += texture(environmentMap, sampleDir).rgb * cos(theta) * sin(theta);
+                    samples += 1.0;
+                }
+            }
+            FragColor = vec4(PI * irradiance / samples, 1.0);
+        }
+    "#;
+
+const PREFILTER_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec3 LocalPos;
+
+        uniform samplerCube environmentMap;
+        uniform float roughness;
+
+        const float PI = 3.14159265359;
+        const uint SAMPLE_COUNT = 256u;
+
+        float radicalInverse(uint bits)
+        {
+            bits = (bits << 16u) | (bits >> 16u);
+            bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+            bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+            bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+            bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+            return float(bits) * 2.3283064365386963e-10;
+        }
+
+        vec3 importanceSampleGGX(vec2 xi, vec3 normal, float roughness)
+        {
+            float a = roughness * roughness;
+            float phi = 2.0 * PI * xi.x;
+            float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+            float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+            vec3 halfway = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+            vec3 up = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+            vec3 tangent = normalize(cross(up, normal));
+            vec3 bitangent = cross(normal, tangent);
+            return normalize(tangent * halfway.x + bitangent * halfway.y + normal * halfway.z);
+        }
+
+        void main()
+        {
+            vec3 normal = normalize(LocalPos);
+            vec3 color = vec3(0.0);
+            float totalWeight = 0.0;
+            for (uint i = 0u; i < SAMPLE_COUNT; ++i) {
+                vec2 xi = vec2(float(i) / float(SAMPLE_COUNT), radicalInverse(i));
+                vec3 halfway = importanceSampleGGX(xi, normal, roughness);
+                vec3 light = normalize(2.0 * dot(normal, halfway) * halfway - normal);
+                float weight = max(dot(normal, light), 0.0);
+                if (weight > 0.0) {
+                    color += textureLod(environmentMap, light, roughness * 4.0).rgb * weight;
+                    totalWeight += weight;
+                }
+            }
+            FragColor = vec4(color / totalWeight, 1.0);
+        }
+    "#;
+
+const SKYBOX_VERT: &str = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+
+        out vec3 LocalPos;
+
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+
+        void main()
+        {
+            LocalPos = aPos;
+            vec4 position = projection * mat4(mat3(view)) * vec4(aPos, 1.0);
+            gl_Position = position.xyww;
+        }
+    "#;
+
+const SKYBOX_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec3 LocalPos;
+
+        uniform samplerCube environmentMap;
+
+        void main()
+        {
+            FragColor = vec4(texture(environmentMap, LocalPos).rgb, 1.0);
+        }
+    "#;
+
+/// Where the sky colors come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sky {
+    /// Six images in the +X, -X, +Y, -Y, +Z, -Z order.
+    CubeMap([String; 6]),
+    /// Panorama image, `.hdr` files keep their high dynamic range.
+    Equirectangular(String),
+    /// Procedural sky blending the horizon color toward the zenith above and the ground below.
+    Gradient { zenith: Vector3<f32>, horizon: Vector3<f32>, ground: Vector3<f32> },
+}
+
+impl Sky {
+    pub fn default_gradient() -> Self {
+        Sky::Gradient {
+            zenith: Vector3::new(0.25, 0.45, 0.85),
+            horizon: Vector3::new(0.8, 0.85, 0.9),
+            ground: Vector3::new(0.3, 0.28, 0.25),
+        }
+    }
+}
+
+struct EnvironmentMaps {
+    environment: gl::types::GLuint,
+    irradiance: gl::types::GLuint,
+    prefiltered: gl::types::GLuint,
+}
+
+impl EnvironmentMaps {
+    fn delete(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.environment);
+            gl::DeleteTextures(1, &self.irradiance);
+            gl::DeleteTextures(1, &self.prefiltered);
+        }
+    }
+}
+
+/// Sky drawn behind the scene and lighting the lit materials through its irradiance and prefiltered specular maps.
+pub struct Environment {
+    sky: Sky,
+    /// Multiplies the ambient lighting of the lit materials.
+    pub intensity: f32,
+    /// Whether the sky is drawn behind the scene, otherwise it only lights it.
+    pub draw_background: bool,
+    maps: Option<EnvironmentMaps>,
+    failed: bool,
+    skybox_shader: Option<Shader>,
+    cube_vao: gl::types::GLuint,
+}
+
+impl Environment {
+    pub fn new(sky: Sky) -> Self {
+        Self {
+            sky,
+            intensity: 1.0,
+            draw_background: true,
+            maps: None,
+            failed: false,
+            skybox_shader: None,
+            cube_vao: 0,
+        }
+    }
+
+    pub fn get_sky(&self) -> &Sky {
+        &self.sky
+    }
+
+    /// Replaces the sky, the maps are generated again on the next frame.
+    pub fn set_sky(&mut self, sky: Sky) {
+        self.sky = sky;
+        if let Some(maps) = self.maps.take() {
+            maps.delete();
+        }
+        self.failed = false;
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.maps.is_some()
+    }
+
+    /// Generates the maps of the sky if needed, a sky failing to load is reported once and ignored.
+    pub(crate) fn prepare(&mut self) {
+        if self.maps.is_some() || self.failed {
+            return;
+        }
+        if self.cube_vao == 0 {
+            self.cube_vao = create_cube_vao();
+            self.skybox_shader = Some(Shader::new(SKYBOX_VERT, SKYBOX_FRAG));
+            unsafe { gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS) };
+        }
+        let mut viewport = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        match self.generate() {
+            Ok(maps) => self.maps = Some(maps),
+            Err(err) => {
+                println!("Environment not generated: {}", err);
+                self.failed = true;
+            },
+        }
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+    }
+
+    fn generate(&self) -> Result<EnvironmentMaps, String> {
+        let mut fbo = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        }
+        let environment = match &self.sky {
+            Sky::CubeMap(paths) => load_cubemap(paths)?,
+            Sky::Equirectangular(path) => {
+                let panorama = load_panorama(path)?;
+                let environment = create_cubemap(ENVIRONMENT_SIZE, true);
+                let shader = Shader::new(CAPTURE_VERT, EQUIRECTANGULAR_FRAG);
+                unsafe { gl::BindTexture(gl::TEXTURE_2D, panorama) };
+                self.render_to_cube(&shader, environment, ENVIRONMENT_SIZE, 0);
+                unsafe { gl::DeleteTextures(1, &panorama) };
+                environment
+            },
+            Sky::Gradient { zenith, horizon, ground } => {
+                let environment = create_cubemap(ENVIRONMENT_SIZE, true);
+                let shader = Shader::new(CAPTURE_VERT, GRADIENT_FRAG);
+                shader.use_program();
+                shader.set_vec3("zenith", *zenith);
+                shader.set_vec3("horizon", *horizon);
+                shader.set_vec3("ground", *ground);
+                self.render_to_cube(&shader, environment, ENVIRONMENT_SIZE, 0);
+                environment
+            },
+        };
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment);
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+
+        let irradiance = create_cubemap(IRRADIANCE_SIZE, false);
+        let shader = Shader::new(CAPTURE_VERT, IRRADIANCE_FRAG);
+        unsafe { gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment) };
+        self.render_to_cube(&shader, irradiance, IRRADIANCE_SIZE, 0);
+
+        let prefiltered = create_cubemap(PREFILTER_SIZE, true);
+        unsafe { gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment) };
+        let shader = Shader::new(CAPTURE_VERT, PREFILTER_FRAG);
+        for mip in 0..PREFILTER_MIPS {
+            shader.use_program();
+            shader.set_float("roughness", mip as f32 / (PREFILTER_MIPS - 1) as f32);
+            self.render_to_cube(&shader, prefiltered, PREFILTER_SIZE >> mip, mip);
+        }
+        unsafe { gl::DeleteFramebuffers(1, &fbo) };
+        Ok(EnvironmentMaps { environment, irradiance, prefiltered })
+    }
+
+    /// Draws the unit cube seen from its center into the six faces of `cubemap`, the source is bound on unit 0.
+    fn render_to_cube(&self, shader: &Shader, cubemap: gl::types::GLuint, size: i32, mip: i32) {
+        shader.use_program();
+        shader.set_mat4("projection", cgmath::perspective(Deg(90.0), 1.0, 0.1, 10.0));
+        unsafe {
+            gl::Viewport(0, 0, size, size);
+            gl::BindVertexArray(self.cube_vao);
+        }
+        for (face, view) in capture_views().iter().enumerate() {
+            shader.set_mat4("view", *view);
+            unsafe {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32, cubemap, mip);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            }
+        }
+        unsafe { gl::BindVertexArray(0) };
+    }
+
+    /// Binds the irradiance and prefiltered maps on their texture units.
+    pub(crate) fn bind_maps(&self) {
+        if let Some(maps) = &self.maps {
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + IRRADIANCE_UNIT);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, maps.irradiance);
+                gl::ActiveTexture(gl::TEXTURE0 + PREFILTER_UNIT);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, maps.prefiltered);
+                gl::ActiveTexture(gl::TEXTURE0);
+            }
+        }
+    }
+
+    /// Draws the sky where nothing was drawn yet, depth writes are left disabled by the caller's state.
+    pub(crate) fn draw_skybox(&self) {
+        let (maps, shader) = match (&self.maps, &self.skybox_shader) {
+            (Some(maps), Some(shader)) if self.draw_background => (maps, shader),
+            _ => return,
+        };
+        shader.use_program();
+        shader.set_int("environmentMap", SKYBOX_UNIT as i32);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + SKYBOX_UNIT);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, maps.environment);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::DepthFunc(gl::LEQUAL);
+            gl::DepthMask(gl::FALSE);
+            gl::BindVertexArray(self.cube_vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            gl::BindVertexArray(0);
+            gl::DepthMask(gl::TRUE);
+            gl::DepthFunc(gl::LESS);
+        }
+    }
+}
+
+/// Views from the origin toward the +X, -X, +Y, -Y, +Z and -Z cube map faces.
+fn capture_views() -> [Matrix4<f32>; 6] {
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    [
+        Matrix4::look_at(origin, Point3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+        Matrix4::look_at(origin, Point3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+        Matrix4::look_at(origin, Point3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+        Matrix4::look_at(origin, Point3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+        Matrix4::look_at(origin, Point3::new(0.0, 0.0, 1.0), Vector3::new(0.0, -1.0, 0.0)),
+        Matrix4::look_at(origin, Point3::new(0.0, 0.0, -1.0), Vector3::new(0.0, -1.0, 0.0)),
+    ]
+}
+
+fn create_cube_vao() -> gl::types::GLuint {
+    let mut positions: Vec<f32> = Vec::with_capacity(36 * 3);
+    //Two triangles per face, wound toward the inside of the cube
+    for axis in 0..3 {
+        for side in [-1.0f32, 1.0] {
+            let corner = |u: f32, v: f32| {
+                let mut position = [0.0; 3];
+                position[axis] = side;
+                position[(axis + 1) % 3] = u;
+                position[(axis + 2) % 3] = v;
+                position
+            };
+            for (u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)] {
+                positions.extend_from_slice(&corner(u, v));
+            }
+        }
+    }
+    let (mut vao, mut vbo) = (0, 0);
+    unsafe {
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(positions.as_slice()) as gl::types::GLsizeiptr,
+            positions.as_ptr() as *const gl::types::GLvoid,
+            gl::STATIC_DRAW,
+        );
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * std::mem::size_of::<f32>() as gl::types::GLint, std::ptr::null());
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+    }
+    vao
+}
+
+fn create_cubemap(size: i32, mipmaps: bool) -> gl::types::GLuint {
+    let mut cubemap = 0;
+    unsafe {
+        gl::GenTextures(1, &mut cubemap);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+        for face in 0..6 {
+            gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, 0, gl::RGB16F as i32, size, size, 0, gl::RGB, gl::FLOAT, std::ptr::null());
+        }
+        set_cubemap_parameters(mipmaps);
+        if mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+    }
+    cubemap
+}
+
+unsafe fn set_cubemap_parameters(mipmaps: bool) {
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+    let min_filter = if mipmaps { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR };
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+}
+
+fn load_cubemap(paths: &[String; 6]) -> Result<gl::types::GLuint, String> {
+    let mut cubemap = 0;
+    unsafe {
+        gl::GenTextures(1, &mut cubemap);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    }
+    for (face, path) in paths.iter().enumerate() {
+        let image = image::open(path).map_err(|err| format!("{}: {}", path, err))?.to_rgb();
+        let (width, height) = image.dimensions();
+        unsafe {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                0,
+                gl::RGB16F as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGB,
+                gl::UNSIGNED_BYTE,
+                image.into_raw().as_ptr() as *const gl::types::GLvoid,
+            );
+        }
+    }
+    unsafe {
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        set_cubemap_parameters(true);
+    }
+    Ok(cubemap)
+}
+
+/// Loads a panorama as a floating point texture, `.hdr` images are read without clamping.
+fn load_panorama(path: &str) -> Result<gl::types::GLuint, String> {
+    let is_hdr = Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+    let (pixels, width, height) = if is_hdr {
+        let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+        let decoder = image::hdr::HDRDecoder::new(BufReader::new(file)).map_err(|err| format!("{}: {}", path, err))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(|err| format!("{}: {}", path, err))?;
+        let pixels: Vec<f32> = pixels.iter().flat_map(|pixel| pixel.data.iter().copied()).collect();
+        (pixels, metadata.width, metadata.height)
+    } else {
+        let image = image::open(path).map_err(|err| format!("{}: {}", path, err))?.to_rgb();
+        let (width, height) = image.dimensions();
+        (image.into_raw().iter().map(|value| *value as f32 / 255.0).collect(), width, height)
+    };
+    let mut texture = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB16F as i32, width as i32, height as i32, 0, gl::RGB, gl::FLOAT, pixels.as_ptr() as *const gl::types::GLvoid);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    }
+    Ok(texture)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Vector4};
+
+    #[test]
+    fn capture_views_face_the_cube_map_faces() {
+        let faces = [
+            Vector3::new(1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0),
+        ];
+        for (view, face) in capture_views().iter().zip(faces.iter()) {
+            //Each face direction ends up straight ahead of the camera
+            let forward = (view * face.extend(0.0)).truncate();
+            assert!((forward - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+            assert_eq!(view * Vector4::new(0.0, 0.0, 0.0, 1.0), Vector4::new(0.0, 0.0, 0.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn changing_the_sky_regenerates_the_maps() {
+        let mut environment = Environment::new(Sky::default_gradient());
+        environment.failed = true;
+        environment.set_sky(Sky::Equirectangular("sky.hdr".to_string()));
+        assert!(!environment.failed && !environment.is_ready());
+        assert_eq!(environment.get_sky(), &Sky::Equirectangular("sky.hdr".to_string()));
+    }
+}
@@ -0,0 +1,38 @@
+use crate::ecs::Entity;
+use crate::graphics::inputs::{Key, MouseButton, ButtonState};
+use crate::graphics::hud::WidgetId;
+
+
+/// Sent by the engine when the window changes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WindowEvent {
+    Resized { width: f64, height: f64 },
+    Focused(bool),
+    CloseRequested,
+}
+
+/// Sent by the engine for every raw input, in addition to the state kept by `InputSystem`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputEvent {
+    Key { key: Key, state: ButtonState },
+    MouseButton { button: MouseButton, state: ButtonState },
+    MouseMotion { x_delta: f64, y_delta: f64 },
+    MouseWheel { x_delta: f32, y_delta: f32 },
+}
+
+/// Sent by the HUD when a widget is clicked, hovered or focused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WidgetEvent {
+    Clicked(WidgetId),
+    HoverStarted(WidgetId),
+    HoverEnded(WidgetId),
+    Focused(WidgetId),
+    Unfocused(WidgetId),
+}
+
+/// Sent by the engine when entities are created or removed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EntityEvent {
+    Spawned(Entity),
+    Despawned(Entity),
+}
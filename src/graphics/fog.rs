@@ -0,0 +1,178 @@
+use cgmath::{InnerSpace, Vector3};
+use serde::{Serialize, Deserialize};
+use crate::graphics::shader::FOG_UNIFORMS_BINDING;
+
+
+/// `Fog` uniform block and the `applyFog(color, fragPos, cameraPos)` function, inserted in place of an
+/// `#include "fog"` line by `include_fog`. The block is filled every frame from the scene fog.
+pub const FOG_GLSL: &str = r#"
+        layout (std140) uniform Fog {
+            vec4 fogColor;
+            // x: density, y: start, z: end, w: mode (0 none, 1 linear, 2 exponential, 3 exponential squared, 4 height)
+            vec4 fogParams;
+            // x: base height, y: falloff
+            vec4 fogHeight;
+        };
+
+        float fogFactor(vec3 fragPos, vec3 cameraPos)
+        {
+            int mode = int(fogParams.w + 0.5);
+            float distance = length(fragPos - cameraPos);
+            if (mode == 1)
+                return clamp((distance - fogParams.y) / max(fogParams.z - fogParams.y, 0.0001), 0.0, 1.0);
+            if (mode == 2)
+                return 1.0 - exp(-fogParams.x * distance);
+            if (mode == 3)
+                return 1.0 - exp(-pow(fogParams.x * distance, 2.0));
+            if (mode == 4) {
+                float falloff = max(fogHeight.y, 0.0001);
+                float deltaY = fragPos.y - cameraPos.y;
+                float cameraDensity = exp(-falloff * (cameraPos.y - fogHeight.x));
+                float integral = abs(falloff * deltaY) > 0.0001 ? (1.0 - exp(-falloff * deltaY)) / (falloff * deltaY) : 1.0;
+                return clamp(1.0 - exp(-fogParams.x * distance * cameraDensity * integral), 0.0, 1.0);
+            }
+            return 0.0;
+        }
+
+        vec3 applyFog(vec3 color, vec3 fragPos, vec3 cameraPos)
+        {
+            return mix(color, fogColor.rgb, fogFactor(fragPos, cameraPos));
+        }
+    "#;
+
+/// Replaces the `#include "fog"` line of a shader source by `FOG_GLSL`.
+pub fn include_fog(source: &str) -> String {
+    source.replace("#include \"fog\"", FOG_GLSL)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FogMode {
+    /// No fog before `start`, opaque fog after `end`.
+    Linear { start: f32, end: f32 },
+    Exponential { density: f32 },
+    ExponentialSquared { density: f32 },
+    /// Exponential fog getting thinner with the height above `base`, at a rate of `falloff`.
+    Height { density: f32, base: f32, falloff: f32 },
+}
+
+/// Scene fog applied by the built-in materials.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vector3<f32>,
+}
+
+impl Fog {
+    pub fn new(mode: FogMode, color: Vector3<f32>) -> Self {
+        Self { mode, color }
+    }
+
+    /// Fog ending at the far plane, hiding where the geometry stops.
+    pub fn linear(start: f32, end: f32, color: Vector3<f32>) -> Self {
+        Self::new(FogMode::Linear { start, end }, color)
+    }
+
+    /// Amount of fog between 0 and 1 covering `position` seen from `camera`, same as `fogFactor` in `FOG_GLSL`.
+    pub fn factor(&self, position: Vector3<f32>, camera: Vector3<f32>) -> f32 {
+        let distance = (position - camera).magnitude();
+        match self.mode {
+            FogMode::Linear { start, end } => ((distance - start) / (end - start).max(0.0001)).clamp(0.0, 1.0),
+            FogMode::Exponential { density } => 1.0 - (-density * distance).exp(),
+            FogMode::ExponentialSquared { density } => 1.0 - (-(density * distance).powi(2)).exp(),
+            FogMode::Height { density, base, falloff } => {
+                let falloff = falloff.max(0.0001);
+                let delta_y = position.y - camera.y;
+                let camera_density = (-falloff * (camera.y - base)).exp();
+                let integral = if (falloff * delta_y).abs() > 0.0001 { (1.0 - (-falloff * delta_y).exp()) / (falloff * delta_y) } else { 1.0 };
+                (1.0 - (-density * distance * camera_density * integral).exp()).clamp(0.0, 1.0)
+            },
+        }
+    }
+
+    /// Values of the `fogParams` and `fogHeight` vectors of the uniform block.
+    fn params(&self) -> ([f32; 4], [f32; 4]) {
+        match self.mode {
+            FogMode::Linear { start, end } => ([0.0, start, end, 1.0], [0.0; 4]),
+            FogMode::Exponential { density } => ([density, 0.0, 0.0, 2.0], [0.0; 4]),
+            FogMode::ExponentialSquared { density } => ([density, 0.0, 0.0, 3.0], [0.0; 4]),
+            FogMode::Height { density, base, falloff } => ([density, 0.0, 0.0, 4.0], [base, falloff, 0.0, 0.0]),
+        }
+    }
+}
+
+/// Layout of the std140 `Fog` uniform block.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct FogData {
+    color: [f32; 4],
+    params: [f32; 4],
+    height: [f32; 4],
+}
+
+/// Uniform buffer holding the scene fog, bound for every shader declaring the `Fog` block.
+#[derive(Default)]
+pub struct FogUniforms {
+    ubo: gl::types::GLuint,
+}
+
+impl FogUniforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn upload(&mut self, fog: Option<&Fog>) {
+        let data = match fog {
+            Some(fog) => {
+                let (params, height) = fog.params();
+                FogData { color: fog.color.extend(1.0).into(), params, height }
+            },
+            None => FogData { color: [0.0; 4], params: [0.0; 4], height: [0.0; 4] },
+        };
+        let size = std::mem::size_of::<FogData>() as gl::types::GLsizeiptr;
+        unsafe {
+            if self.ubo == 0 {
+                gl::GenBuffers(1, &mut self.ubo);
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+                gl::BufferData(gl::UNIFORM_BUFFER, size, std::ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, FOG_UNIFORMS_BINDING, self.ubo);
+            }
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size, &data as *const FogData as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fog_modes() {
+        let camera = Vector3::new(0.0, 10.0, 0.0);
+        let at = |distance: f32| Vector3::new(0.0, 10.0, -distance);
+        let color = Vector3::new(0.5, 0.5, 0.5);
+
+        let linear = Fog::linear(10.0, 20.0, color);
+        assert_eq!((linear.factor(at(5.0), camera), linear.factor(at(15.0), camera), linear.factor(at(50.0), camera)), (0.0, 0.5, 1.0));
+
+        let exponential = Fog::new(FogMode::Exponential { density: 0.1 }, color);
+        let squared = Fog::new(FogMode::ExponentialSquared { density: 0.1 }, color);
+        assert!(exponential.factor(at(5.0), camera) < exponential.factor(at(10.0), camera));
+        assert!(squared.factor(at(5.0), camera) < exponential.factor(at(5.0), camera));
+
+        //Height fog is thicker in the valley than at the same distance on the peaks
+        let height = Fog::new(FogMode::Height { density: 0.1, base: 0.0, falloff: 0.5 }, color);
+        let low = height.factor(Vector3::new(0.0, 0.0, -10.0), Vector3::new(0.0, 0.0, 0.0));
+        let high = height.factor(Vector3::new(0.0, 10.0, -10.0), camera);
+        assert!(low > high && high > 0.0);
+    }
+
+    #[test]
+    fn include_replaces_the_directive() {
+        let source = include_fog("#version 330 core\n#include \"fog\"\nvoid main() {}");
+        assert!(source.contains("vec3 applyFog") && !source.contains("#include"));
+    }
+}
@@ -0,0 +1,205 @@
+use crate::graphics::ComponentStorageManager;
+use crate::graphics::inputs::InputSystem;
+use crate::graphics::commands::Commands;
+use crate::graphics::debug_draw::DebugDraw;
+use crate::graphics::text::TextDraw;
+use crate::graphics::hud::Hud;
+
+
+pub struct StateData<'a> {
+    pub storage: &'a mut ComponentStorageManager,
+    pub input: &'a InputSystem,
+    pub commands: &'a mut Commands,
+}
+
+impl<'a> StateData<'a> {
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        self.storage.get_mut_debug_draw()
+    }
+
+    pub fn text(&mut self) -> &mut TextDraw {
+        self.storage.get_mut_text()
+    }
+
+    pub fn ui(&self) -> &egui::Context {
+        self.storage.get_ui().get_context()
+    }
+
+    pub fn hud(&mut self) -> &mut Hud {
+        self.storage.get_mut_hud()
+    }
+}
+
+/// Change of the game state stack, returned by `GameState::on_update` or queued with `Commands`.
+pub enum Transition {
+    None,
+    /// Pauses the current state and enters the new one on top of it.
+    Push(Box<dyn GameState>),
+    /// Exits the current state and resumes the one below.
+    Pop,
+    /// Exits the current state and enters the new one in its place.
+    Switch(Box<dyn GameState>),
+    /// Exits every state and stops the engine.
+    Quit,
+}
+
+/// Engine-wide state such as a menu, the game itself or a pause screen. Only the top of the stack is updated.
+pub trait GameState {
+    fn on_enter(&mut self, _data: StateData) {}
+    fn on_exit(&mut self, _data: StateData) {}
+    /// Called when another state is pushed on top of this one.
+    fn on_pause(&mut self, _data: StateData) {}
+    /// Called when this state is back on top of the stack.
+    fn on_resume(&mut self, _data: StateData) {}
+    fn on_update(&mut self, _data: StateData, _delta: f32) -> Transition {
+        Transition::None
+    }
+    /// Whether the entity states keep running while this state is on top, e.g. false for a pause screen.
+    fn updates_entities(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Default)]
+pub struct GameStateStack {
+    stack: Vec<Box<dyn GameState>>,
+    pending: Vec<Transition>,
+    quit: bool,
+}
+
+impl GameStateStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a transition, it is applied on the next update.
+    pub fn request(&mut self, transition: Transition) {
+        self.pending.push(transition);
+    }
+
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn updates_entities(&self) -> bool {
+        self.stack.last().is_none_or(|state| state.updates_entities())
+    }
+
+    /// Applies the pending transitions then updates the state on top of the stack.
+    pub fn update(&mut self, storage: &mut ComponentStorageManager, input: &InputSystem, commands: &mut Commands, delta: f32) {
+        for transition in std::mem::take(&mut self.pending) {
+            self.apply(transition, storage, input, commands);
+        }
+        let transition = match self.stack.last_mut() {
+            Some(state) => state.on_update(StateData { storage, input, commands }, delta),
+            None => Transition::None,
+        };
+        self.apply(transition, storage, input, commands);
+    }
+
+    fn apply(&mut self, transition: Transition, storage: &mut ComponentStorageManager, input: &InputSystem, commands: &mut Commands) {
+        if self.quit {
+            return;
+        }
+        match transition {
+            Transition::None => (),
+            Transition::Push(mut state) => {
+                if let Some(current) = self.stack.last_mut() {
+                    current.on_pause(StateData { storage, input, commands });
+                }
+                state.on_enter(StateData { storage, input, commands });
+                self.stack.push(state);
+            },
+            Transition::Pop => {
+                if let Some(mut current) = self.stack.pop() {
+                    current.on_exit(StateData { storage, input, commands });
+                }
+                if let Some(current) = self.stack.last_mut() {
+                    current.on_resume(StateData { storage, input, commands });
+                }
+            },
+            Transition::Switch(mut state) => {
+                if let Some(mut current) = self.stack.pop() {
+                    current.on_exit(StateData { storage, input, commands });
+                }
+                state.on_enter(StateData { storage, input, commands });
+                self.stack.push(state);
+            },
+            Transition::Quit => {
+                while let Some(mut current) = self.stack.pop() {
+                    current.on_exit(StateData { storage, input, commands });
+                }
+                self.quit = true;
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    struct Recorder {
+        name: &'static str,
+        log: Log,
+        next: Option<Transition>,
+    }
+
+    impl Recorder {
+        fn new(name: &'static str, log: &Log) -> Self {
+            Self { name, log: log.clone(), next: None }
+        }
+
+        fn record(&self, hook: &str) {
+            self.log.lock().unwrap().push(format!("{} {}", self.name, hook));
+        }
+    }
+
+    impl GameState for Recorder {
+        fn on_enter(&mut self, _data: StateData) { self.record("enter"); }
+        fn on_exit(&mut self, _data: StateData) { self.record("exit"); }
+        fn on_pause(&mut self, _data: StateData) { self.record("pause"); }
+        fn on_resume(&mut self, _data: StateData) { self.record("resume"); }
+        fn on_update(&mut self, _data: StateData, _delta: f32) -> Transition {
+            self.record("update");
+            self.next.take().unwrap_or(Transition::None)
+        }
+    }
+
+    #[test]
+    fn push_pop_and_quit() {
+        let log = Log::default();
+        let mut storage = ComponentStorageManager::new();
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        let mut states = GameStateStack::new();
+
+        let mut menu = Recorder::new("menu", &log);
+        menu.next = Some(Transition::Push(Box::new(Recorder::new("pause", &log))));
+        states.request(Transition::Push(Box::new(menu)));
+        states.update(&mut storage, &input, &mut commands, 0.0);
+        assert_eq!(states.len(), 2);
+        states.request(Transition::Pop);
+        states.update(&mut storage, &input, &mut commands, 0.0);
+        states.request(Transition::Quit);
+        states.update(&mut storage, &input, &mut commands, 0.0);
+        assert!(states.has_quit() && states.is_empty());
+        assert_eq!(*log.lock().unwrap(), vec![
+            "menu enter", "menu update", "menu pause", "pause enter",
+            "pause exit", "menu resume", "menu update",
+            "menu exit",
+        ]);
+    }
+}
@@ -0,0 +1,1197 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use cgmath::{Vector2, Vector4};
+use glutin::{ElementState, KeyboardInput};
+use crate::ecs::EventBus;
+use crate::graphics::events::WidgetEvent;
+use crate::graphics::inputs::Key;
+use crate::graphics::render_state::{BlendMode, RenderState};
+use crate::graphics::shader::{Shader, Texture};
+use crate::graphics::text::{self, Font, TextAlign, TextStyle};
+
+
+/// Handle to a widget. Like `Entity`, the generation is bumped every time a slot is reused,
+/// so the id of a removed widget never matches a new one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WidgetId {
+    index: u32,
+    generation: u32,
+}
+
+impl fmt::Display for WidgetId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+const ROOT: WidgetId = WidgetId { index: 0, generation: 0 };
+/// Width of the outline drawn around the focused button.
+const FOCUS_OUTLINE: f32 = 2.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub position: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+
+impl Rect {
+    pub fn new(position: Vector2<f32>, size: Vector2<f32>) -> Self {
+        Self { position, size }
+    }
+
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        point.x >= self.position.x && point.y >= self.position.y
+            && point.x < self.position.x + self.size.x && point.y < self.position.y + self.size.y
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Size {
+    /// Size of the content, e.g. the text of a label or the children of a panel.
+    Auto,
+    Pixels(f32),
+    /// Fraction of the inner size of the parent.
+    Relative(f32),
+}
+
+impl Size {
+    fn resolve(self, parent: f32, content: f32) -> f32 {
+        match self {
+            Size::Auto => content,
+            Size::Pixels(pixels) => pixels,
+            Size::Relative(fraction) => parent * fraction,
+        }
+    }
+}
+
+/// Axis along which the children of a widget are placed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Row,
+    Column,
+}
+
+/// Placement of the children along the direction of their parent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Justify {
+    Start,
+    Center,
+    End,
+    SpaceBetween,
+}
+
+/// Placement of the children across the direction of their parent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    /// Children without a size across the direction fill their parent.
+    Stretch,
+}
+
+/// Point of the parent an anchored widget is attached to, outside of the layout of its siblings.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    fn factors(self) -> Vector2<f32> {
+        match self {
+            Anchor::TopLeft => Vector2::new(0.0, 0.0),
+            Anchor::Top => Vector2::new(0.5, 0.0),
+            Anchor::TopRight => Vector2::new(1.0, 0.0),
+            Anchor::Left => Vector2::new(0.0, 0.5),
+            Anchor::Center => Vector2::new(0.5, 0.5),
+            Anchor::Right => Vector2::new(1.0, 0.5),
+            Anchor::BottomLeft => Vector2::new(0.0, 1.0),
+            Anchor::Bottom => Vector2::new(0.5, 1.0),
+            Anchor::BottomRight => Vector2::new(1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Layout {
+    pub width: Size,
+    pub height: Size,
+    /// Direction of the children.
+    pub direction: Direction,
+    pub justify: Justify,
+    pub align: Align,
+    pub padding: f32,
+    /// Space between the children.
+    pub gap: f32,
+    /// Share of the free space of the parent given to the widget along the parent direction.
+    pub grow: f32,
+    pub anchor: Option<Anchor>,
+    /// Distance from the anchored edges, or translation for the centered axes.
+    pub offset: Vector2<f32>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            width: Size::Auto,
+            height: Size::Auto,
+            direction: Direction::Column,
+            justify: Justify::Start,
+            align: Align::Start,
+            padding: 0.0,
+            gap: 0.0,
+            grow: 0.0,
+            anchor: None,
+            offset: Vector2::new(0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    /// Container drawn with the panel color.
+    Panel,
+    /// Invisible container, only used for layout.
+    Group,
+    Label(String),
+    Button(String),
+    /// Images have no size of their own, they need a width and a height.
+    Image(Texture),
+    /// Progress between 0 and 1.
+    ProgressBar(f32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    pub layout: Layout,
+    /// Replaces the theme color: background of panels and buttons, text of labels, tint of images and fill of progress bars.
+    pub color: Option<Vector4<f32>>,
+    /// Hidden widgets and their children are neither laid out, drawn nor interacted with.
+    pub visible: bool,
+    name: Option<String>,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    rect: Rect,
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind) -> Self {
+        Self {
+            kind,
+            layout: Layout::default(),
+            color: None,
+            visible: true,
+            name: None,
+            parent: None,
+            children: Vec::new(),
+            rect: Rect::new(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0)),
+        }
+    }
+
+    pub fn panel() -> Self {
+        Self::new(WidgetKind::Panel)
+    }
+
+    pub fn group() -> Self {
+        Self::new(WidgetKind::Group)
+    }
+
+    pub fn label(text: &str) -> Self {
+        Self::new(WidgetKind::Label(text.to_string()))
+    }
+
+    pub fn button(text: &str) -> Self {
+        Self::new(WidgetKind::Button(text.to_string())).with_padding(8.0)
+    }
+
+    pub fn image(texture: Texture) -> Self {
+        Self::new(WidgetKind::Image(texture))
+    }
+
+    pub fn progress_bar(progress: f32) -> Self {
+        Self::new(WidgetKind::ProgressBar(progress.clamp(0.0, 1.0)))
+    }
+
+    /// Name used to find the widget with `Hud::find`.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_size(mut self, width: Size, height: Size) -> Self {
+        self.layout.width = width;
+        self.layout.height = height;
+        self
+    }
+
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.layout.direction = direction;
+        self
+    }
+
+    pub fn with_justify(mut self, justify: Justify) -> Self {
+        self.layout.justify = justify;
+        self
+    }
+
+    pub fn with_align(mut self, align: Align) -> Self {
+        self.layout.align = align;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: f32) -> Self {
+        self.layout.padding = padding;
+        self
+    }
+
+    pub fn with_gap(mut self, gap: f32) -> Self {
+        self.layout.gap = gap;
+        self
+    }
+
+    pub fn with_grow(mut self, grow: f32) -> Self {
+        self.layout.grow = grow;
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: Anchor, offset: Vector2<f32>) -> Self {
+        self.layout.anchor = Some(anchor);
+        self.layout.offset = offset;
+        self
+    }
+
+    pub fn with_color(mut self, color: Vector4<f32>) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_visible(mut self, visible: bool) -> Self {
+        self.visible = visible;
+        self
+    }
+
+    /// Text of labels and buttons, ignored by the other widgets.
+    pub fn set_text(&mut self, new_text: &str) {
+        if let WidgetKind::Label(text) | WidgetKind::Button(text) = &mut self.kind {
+            *text = new_text.to_string();
+        }
+    }
+
+    /// Progress of progress bars, ignored by the other widgets.
+    pub fn set_progress(&mut self, new_progress: f32) {
+        if let WidgetKind::ProgressBar(progress) = &mut self.kind {
+            *progress = new_progress.clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn get_parent(&self) -> Option<WidgetId> {
+        self.parent
+    }
+
+    pub fn get_children(&self) -> &[WidgetId] {
+        &self.children
+    }
+
+    /// Area of the widget after the last layout, in reference pixels from the top left corner of the window.
+    pub fn get_rect(&self) -> Rect {
+        self.rect
+    }
+
+    /// Widgets the pointer cannot click through.
+    fn blocks_pointer(&self) -> bool {
+        !matches!(self.kind, WidgetKind::Group | WidgetKind::Label(_))
+    }
+}
+
+/// Colors and font shared by the widgets.
+#[derive(Clone)]
+pub struct Theme {
+    /// Labels and buttons have no text without a font.
+    pub font: Option<Arc<Font>>,
+    pub text_size: f32,
+    pub text_color: Vector4<f32>,
+    pub panel_color: Vector4<f32>,
+    pub button_color: Vector4<f32>,
+    pub button_hovered_color: Vector4<f32>,
+    pub button_pressed_color: Vector4<f32>,
+    pub focus_color: Vector4<f32>,
+    pub progress_background_color: Vector4<f32>,
+    pub progress_color: Vector4<f32>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            font: None,
+            text_size: 20.0,
+            text_color: Vector4::new(0.95, 0.95, 0.95, 1.0),
+            panel_color: Vector4::new(0.1, 0.1, 0.12, 0.8),
+            button_color: Vector4::new(0.25, 0.27, 0.32, 1.0),
+            button_hovered_color: Vector4::new(0.33, 0.36, 0.43, 1.0),
+            button_pressed_color: Vector4::new(0.18, 0.2, 0.24, 1.0),
+            focus_color: Vector4::new(0.95, 0.75, 0.2, 1.0),
+            progress_background_color: Vector4::new(0.05, 0.05, 0.05, 0.8),
+            progress_color: Vector4::new(0.3, 0.75, 0.35, 1.0),
+        }
+    }
+}
+
+/// Retained widget tree drawn over the scene, e.g. `hud.add(hud.root(), Widget::button("Pause").with_name("pause"))`.
+/// Widgets are laid out in reference pixels, scaled to the window when a reference resolution is set.
+/// Clicks, hovers and focus changes are sent as `WidgetEvent`.
+pub struct Hud {
+    /// Widget of each slot with the generation of the slot.
+    widgets: Vec<(u32, Option<Widget>)>,
+    free: Vec<u32>,
+    theme: Theme,
+    reference_resolution: Option<Vector2<f32>>,
+    /// Window size in logical pixels.
+    window: Vector2<f32>,
+    cursor: Option<Vector2<f32>>,
+    hovered: Option<WidgetId>,
+    pressed: Option<WidgetId>,
+    focused: Option<WidgetId>,
+    pending: Vec<WidgetEvent>,
+}
+
+impl Default for Hud {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hud {
+    pub fn new() -> Self {
+        Self {
+            widgets: vec![(0, Some(Widget::group()))],
+            free: Vec::new(),
+            theme: Theme::default(),
+            reference_resolution: None,
+            window: Vector2::new(0.0, 0.0),
+            cursor: None,
+            hovered: None,
+            pressed: None,
+            focused: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Group covering the window, parent of the top level widgets.
+    pub fn root(&self) -> WidgetId {
+        ROOT
+    }
+
+    /// Adds `widget` as the last child of `parent`. Panics if `parent` was removed.
+    pub fn add(&mut self, parent: WidgetId, mut widget: Widget) -> WidgetId {
+        assert!(self.get(parent).is_some(), "Widget {} does not exist", parent);
+        widget.parent = Some(parent);
+        widget.children.clear();
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.widgets[index as usize];
+                slot.1 = Some(widget);
+                WidgetId { index, generation: slot.0 }
+            },
+            None => {
+                self.widgets.push((0, Some(widget)));
+                WidgetId { index: self.widgets.len() as u32 - 1, generation: 0 }
+            },
+        };
+        self.widget_mut(parent).children.push(id);
+        id
+    }
+
+    /// Removes the widget and its children. Returns false if it did not exist, the root cannot be removed.
+    pub fn remove(&mut self, id: WidgetId) -> bool {
+        if id == ROOT || self.get(id).is_none() {
+            return false;
+        }
+        let slot = &mut self.widgets[id.index as usize];
+        let widget = slot.1.take().unwrap();
+        slot.0 += 1;
+        if let Some(parent) = widget.parent.and_then(|parent| self.get_mut(parent)) {
+            parent.children.retain(|child| *child != id);
+        }
+        for child in widget.children {
+            self.remove(child);
+        }
+        for state in [&mut self.hovered, &mut self.pressed, &mut self.focused] {
+            if *state == Some(id) {
+                *state = None;
+            }
+        }
+        self.free.push(id.index);
+        true
+    }
+
+    pub fn get(&self, id: WidgetId) -> Option<&Widget> {
+        self.widgets.get(id.index as usize)
+            .filter(|(generation, _)| *generation == id.generation)
+            .and_then(|(_, widget)| widget.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: WidgetId) -> Option<&mut Widget> {
+        self.widgets.get_mut(id.index as usize)
+            .filter(|(generation, _)| *generation == id.generation)
+            .and_then(|(_, widget)| widget.as_mut())
+    }
+
+    /// Widget of an id known to be alive.
+    fn widget(&self, id: WidgetId) -> &Widget {
+        self.get(id).expect("Widget removed")
+    }
+
+    fn widget_mut(&mut self, id: WidgetId) -> &mut Widget {
+        self.get_mut(id).expect("Widget removed")
+    }
+
+    pub fn find(&self, name: &str) -> Option<WidgetId> {
+        self.widgets.iter().enumerate()
+            .find(|(_, (_, widget))| widget.as_ref().is_some_and(|widget| widget.get_name() == Some(name)))
+            .map(|(index, (generation, _))| WidgetId { index: index as u32, generation: *generation })
+    }
+
+    pub fn get_theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    pub fn get_mut_theme(&mut self) -> &mut Theme {
+        &mut self.theme
+    }
+
+    /// Resolution the widgets are designed for, the whole HUD is scaled to fit the window in it. `None` uses window pixels.
+    pub fn set_reference_resolution(&mut self, resolution: Option<(f32, f32)>) {
+        self.reference_resolution = resolution.map(|(width, height)| Vector2::new(width, height));
+    }
+
+    /// Window pixels per reference pixel.
+    pub fn get_scale(&self) -> f32 {
+        match self.reference_resolution {
+            Some(reference) if reference.x > 0.0 && reference.y > 0.0 && self.window.x > 0.0 => {
+                (self.window.x / reference.x).min(self.window.y / reference.y)
+            },
+            _ => 1.0,
+        }
+    }
+
+    /// Size of the root group in reference pixels.
+    pub fn get_size(&self) -> Vector2<f32> {
+        self.window / self.get_scale()
+    }
+
+    pub fn get_hovered(&self) -> Option<WidgetId> {
+        self.hovered
+    }
+
+    pub fn get_focused(&self) -> Option<WidgetId> {
+        self.focused
+    }
+
+    /// Gives the keyboard focus to a visible button, e.g. when opening a menu. Tab and Shift+Tab then move it, Return and Space click.
+    /// Mouse clicks never give the focus, it is dropped when the button or one of its parents is hidden.
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        let id = id.filter(|id| matches!(self.get(*id).map(|widget| &widget.kind), Some(WidgetKind::Button(_))) && self.is_shown(*id));
+        if self.focused == id {
+            return;
+        }
+        if let Some(previous) = self.focused {
+            self.pending.push(WidgetEvent::Unfocused(previous));
+        }
+        if let Some(id) = id {
+            self.pending.push(WidgetEvent::Focused(id));
+        }
+        self.focused = id;
+    }
+
+    /// Position of the cursor in logical pixels from the top left corner of the window.
+    pub(crate) fn set_cursor_position(&mut self, x: f32, y: f32) {
+        self.cursor = Some(Vector2::new(x, y));
+    }
+
+    pub(crate) fn set_cursor_left(&mut self) {
+        self.cursor = None;
+    }
+
+    /// Returns true when the press is over a widget. Releases are never captured so no button stays pressed.
+    pub(crate) fn set_mouse_button_event(&mut self, button: glutin::MouseButton, state: ElementState) -> bool {
+        if button != glutin::MouseButton::Left {
+            return false;
+        }
+        let target = self.cursor.and_then(|cursor| self.widget_at(cursor / self.get_scale()));
+        match state {
+            ElementState::Pressed => {
+                self.pressed = target;
+                target.is_some()
+            },
+            ElementState::Released => {
+                if let Some(pressed) = self.pressed.take() {
+                    if target == Some(pressed) {
+                        self.pending.push(WidgetEvent::Clicked(pressed));
+                    }
+                }
+                false
+            },
+        }
+    }
+
+    /// Keyboard navigation between the buttons once one of them has the focus. Returns true when the key is used.
+    pub(crate) fn set_key_event(&mut self, event: KeyboardInput) -> bool {
+        self.drop_hidden_focus();
+        let focused = match self.focused {
+            Some(focused) if event.state == ElementState::Pressed => focused,
+            _ => return false,
+        };
+        match event.virtual_keycode {
+            Some(Key::Tab) => {
+                let buttons: Vec<WidgetId> = self.draw_order().into_iter()
+                    .filter(|id| matches!(self.widget(*id).kind, WidgetKind::Button(_)))
+                    .collect();
+                if let Some(index) = buttons.iter().position(|id| *id == focused) {
+                    let next = if event.modifiers.shift { index + buttons.len() - 1 } else { index + 1 };
+                    self.set_focus(Some(buttons[next % buttons.len()]));
+                }
+                true
+            },
+            Some(Key::Return) | Some(Key::NumpadEnter) | Some(Key::Space) => {
+                self.pending.push(WidgetEvent::Clicked(focused));
+                true
+            },
+            Some(Key::Escape) => {
+                self.set_focus(None);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Lays the widgets out for a window of `width` by `height` logical pixels and sends the events of the frame.
+    pub(crate) fn update(&mut self, width: f32, height: f32, bus: &mut EventBus) {
+        self.window = Vector2::new(width, height);
+        self.layout();
+        self.drop_hidden_focus();
+        let hovered = self.cursor.and_then(|cursor| self.widget_at(cursor / self.get_scale()));
+        if hovered != self.hovered {
+            if let Some(previous) = self.hovered {
+                self.pending.push(WidgetEvent::HoverEnded(previous));
+            }
+            if let Some(hovered) = hovered {
+                self.pending.push(WidgetEvent::HoverStarted(hovered));
+            }
+            self.hovered = hovered;
+        }
+        for event in self.pending.drain(..) {
+            bus.send(event);
+        }
+    }
+
+    /// True if the widget and all its parents are visible.
+    fn is_shown(&self, id: WidgetId) -> bool {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            match self.get(id) {
+                Some(widget) if widget.visible => current = widget.parent,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn drop_hidden_focus(&mut self) {
+        if self.focused.is_some_and(|focused| !self.is_shown(focused)) {
+            self.set_focus(None);
+        }
+    }
+
+    /// Visible widgets, parents before their children.
+    fn draw_order(&self) -> Vec<WidgetId> {
+        let mut order = Vec::new();
+        let mut stack = vec![ROOT];
+        while let Some(id) = stack.pop() {
+            let widget = self.widget(id);
+            if !widget.visible {
+                continue;
+            }
+            order.push(id);
+            stack.extend(widget.children.iter().rev());
+        }
+        order
+    }
+
+    /// Topmost widget blocking the pointer at `point`, in reference pixels.
+    fn widget_at(&self, point: Vector2<f32>) -> Option<WidgetId> {
+        self.draw_order().into_iter().rev().find(|id| {
+            let widget = self.widget(*id);
+            widget.blocks_pointer() && widget.rect.contains(point)
+        })
+    }
+
+    fn text_size(&self, text: &str, max_width: Option<f32>) -> Vector2<f32> {
+        match &self.theme.font {
+            Some(font) => {
+                let mut style = TextStyle::new(self.theme.text_size);
+                style.max_width = max_width;
+                font.measure(text, &style)
+            },
+            None => Vector2::new(0.0, 0.0),
+        }
+    }
+
+    /// Size of the widget when its size is `Auto`.
+    fn content_size(&self, id: WidgetId) -> Vector2<f32> {
+        let widget = self.widget(id);
+        let layout = &widget.layout;
+        let content = match &widget.kind {
+            WidgetKind::Label(text) | WidgetKind::Button(text) => match layout.width {
+                Size::Pixels(width) => self.text_size(text, Some((width - 2.0 * layout.padding).max(0.0))),
+                _ => self.text_size(text, None),
+            },
+            WidgetKind::Panel | WidgetKind::Group => {
+                let sizes: Vec<Vector2<f32>> = widget.children.iter()
+                    .filter(|child| self.get(**child).is_some_and(|child| child.visible && child.layout.anchor.is_none()))
+                    .map(|child| self.preferred_size(*child))
+                    .collect();
+                let gaps = layout.gap * sizes.len().saturating_sub(1) as f32;
+                match layout.direction {
+                    Direction::Row => Vector2::new(sizes.iter().map(|size| size.x).sum::<f32>() + gaps, sizes.iter().map(|size| size.y).fold(0.0, f32::max)),
+                    Direction::Column => Vector2::new(sizes.iter().map(|size| size.x).fold(0.0, f32::max), sizes.iter().map(|size| size.y).sum::<f32>() + gaps),
+                }
+            },
+            WidgetKind::Image(_) | WidgetKind::ProgressBar(_) => Vector2::new(0.0, 0.0),
+        };
+        content + Vector2::new(2.0 * layout.padding, 2.0 * layout.padding)
+    }
+
+    /// Size of the widget without a parent size to resolve relative sizes.
+    fn preferred_size(&self, id: WidgetId) -> Vector2<f32> {
+        let layout = &self.widget(id).layout;
+        let content = self.content_size(id);
+        let resolve = |size: Size, content: f32| if let Size::Pixels(pixels) = size { pixels } else { content };
+        Vector2::new(resolve(layout.width, content.x), resolve(layout.height, content.y))
+    }
+
+    fn resolved_size(&self, id: WidgetId, parent: Vector2<f32>) -> Vector2<f32> {
+        let layout = &self.widget(id).layout;
+        let content = self.content_size(id);
+        Vector2::new(layout.width.resolve(parent.x, content.x), layout.height.resolve(parent.y, content.y))
+    }
+
+    pub(crate) fn layout(&mut self) {
+        let size = self.get_size();
+        self.arrange(ROOT, Rect::new(Vector2::new(0.0, 0.0), size));
+    }
+
+    fn arrange(&mut self, id: WidgetId, rect: Rect) {
+        let widget = self.widget_mut(id);
+        widget.rect = rect;
+        let layout = widget.layout;
+        let children = widget.children.clone();
+        let padding = Vector2::new(layout.padding, layout.padding);
+        let inner = Rect::new(rect.position + padding, Vector2::new((rect.size.x - 2.0 * padding.x).max(0.0), (rect.size.y - 2.0 * padding.y).max(0.0)));
+        let row = layout.direction == Direction::Row;
+        let main = |vector: Vector2<f32>| if row { vector.x } else { vector.y };
+        let cross = |vector: Vector2<f32>| if row { vector.y } else { vector.x };
+
+        let (flow, anchored): (Vec<WidgetId>, Vec<WidgetId>) = children.into_iter()
+            .filter(|child| self.widget(*child).visible)
+            .partition(|child| self.widget(*child).layout.anchor.is_none());
+
+        let mut sizes: Vec<Vector2<f32>> = flow.iter().map(|child| {
+            let mut size = self.resolved_size(*child, inner.size);
+            let child_layout = &self.widget(*child).layout;
+            let cross_size = if row { child_layout.height } else { child_layout.width };
+            if layout.align == Align::Stretch && cross_size == Size::Auto {
+                if row { size.y = inner.size.y } else { size.x = inner.size.x }
+            }
+            size
+        }).collect();
+        let gaps = layout.gap * flow.len().saturating_sub(1) as f32;
+        let mut free = main(inner.size) - sizes.iter().map(|size| main(*size)).sum::<f32>() - gaps;
+        let total_grow: f32 = flow.iter().map(|child| self.widget(*child).layout.grow).sum();
+        if free > 0.0 && total_grow > 0.0 {
+            for (child, size) in flow.iter().zip(sizes.iter_mut()) {
+                let grown = free * self.widget(*child).layout.grow / total_grow;
+                if row { size.x += grown } else { size.y += grown }
+            }
+            free = 0.0;
+        }
+        let (mut pen, spacing) = match layout.justify {
+            Justify::Start => (0.0, layout.gap),
+            Justify::Center => (free / 2.0, layout.gap),
+            Justify::End => (free, layout.gap),
+            Justify::SpaceBetween if flow.len() > 1 => (0.0, layout.gap + free.max(0.0) / (flow.len() - 1) as f32),
+            Justify::SpaceBetween => (0.0, layout.gap),
+        };
+        for (child, size) in flow.into_iter().zip(sizes) {
+            let cross_offset = match layout.align {
+                Align::Start | Align::Stretch => 0.0,
+                Align::Center => (cross(inner.size) - cross(size)) / 2.0,
+                Align::End => cross(inner.size) - cross(size),
+            };
+            let offset = if row { Vector2::new(pen, cross_offset) } else { Vector2::new(cross_offset, pen) };
+            self.arrange(child, Rect::new(inner.position + offset, size));
+            pen += main(size) + spacing;
+        }
+
+        for child in anchored {
+            let size = self.resolved_size(child, inner.size);
+            let child_layout = self.widget(child).layout;
+            let factors = child_layout.anchor.unwrap().factors();
+            //The offset moves away from the anchored edges
+            let direction = |factor: f32| if factor == 1.0 { -1.0 } else { 1.0 };
+            let position = Vector2::new(
+                inner.position.x + (inner.size.x - size.x) * factors.x + child_layout.offset.x * direction(factors.x),
+                inner.position.y + (inner.size.y - size.y) * factors.y + child_layout.offset.y * direction(factors.y),
+            );
+            self.arrange(child, Rect::new(position, size));
+        }
+    }
+
+    fn push_text(&self, quads: &mut Vec<HudQuad>, text: &str, area: Rect, align: TextAlign, color: Vector4<f32>, centered: bool) {
+        let font = match &self.theme.font {
+            Some(font) => font,
+            None => return,
+        };
+        let style = TextStyle::new(self.theme.text_size).with_align(align).with_max_width(area.size.x);
+        let layout = font.layout(text, &style);
+        let top = if centered { (area.size.y - layout.size.y) / 2.0 } else { 0.0 };
+        for glyph in layout.quads {
+            let origin = area.position + Vector2::new(0.0, top);
+            quads.push(HudQuad {
+                min: origin + glyph.min,
+                max: origin + glyph.max,
+                uv_min: glyph.uv_min,
+                uv_max: glyph.uv_max,
+                color,
+                texture: QuadTexture::Font(font.clone()),
+            });
+        }
+    }
+
+    /// Quads of the visible widgets in drawing order, in reference pixels.
+    fn build_quads(&self) -> Vec<HudQuad> {
+        let theme = &self.theme;
+        let mut quads = Vec::new();
+        for id in self.draw_order() {
+            let widget = self.widget(id);
+            let rect = widget.rect;
+            let padding = Vector2::new(widget.layout.padding, widget.layout.padding);
+            let inner = Rect::new(rect.position + padding, rect.size - padding * 2.0);
+            match &widget.kind {
+                WidgetKind::Panel => quads.push(HudQuad::solid(rect, widget.color.unwrap_or(theme.panel_color))),
+                WidgetKind::Group => (),
+                WidgetKind::Label(text) => {
+                    let max_width = if widget.layout.width == Size::Auto { rect.size.x.max(self.text_size(text, None).x) } else { inner.size.x };
+                    self.push_text(&mut quads, text, Rect::new(inner.position, Vector2::new(max_width, inner.size.y)), TextAlign::Left, widget.color.unwrap_or(theme.text_color), false);
+                },
+                WidgetKind::Button(text) => {
+                    let color = if self.pressed == Some(id) && self.hovered == Some(id) {
+                        theme.button_pressed_color
+                    } else if self.hovered == Some(id) {
+                        theme.button_hovered_color
+                    } else {
+                        widget.color.unwrap_or(theme.button_color)
+                    };
+                    quads.push(HudQuad::solid(rect, color));
+                    if self.focused == Some(id) {
+                        let (position, size) = (rect.position, rect.size);
+                        for (offset, extent) in [
+                            (Vector2::new(0.0, 0.0), Vector2::new(size.x, FOCUS_OUTLINE)),
+                            (Vector2::new(0.0, size.y - FOCUS_OUTLINE), Vector2::new(size.x, FOCUS_OUTLINE)),
+                            (Vector2::new(0.0, 0.0), Vector2::new(FOCUS_OUTLINE, size.y)),
+                            (Vector2::new(size.x - FOCUS_OUTLINE, 0.0), Vector2::new(FOCUS_OUTLINE, size.y)),
+                        ] {
+                            quads.push(HudQuad::solid(Rect::new(position + offset, extent), theme.focus_color));
+                        }
+                    }
+                    self.push_text(&mut quads, text, inner, TextAlign::Center, theme.text_color, true);
+                },
+                WidgetKind::Image(texture) => {
+                    let mut quad = HudQuad::solid(rect, widget.color.unwrap_or(Vector4::new(1.0, 1.0, 1.0, 1.0)));
+                    quad.texture = QuadTexture::Image(*texture);
+                    quads.push(quad);
+                },
+                WidgetKind::ProgressBar(progress) => {
+                    quads.push(HudQuad::solid(rect, theme.progress_background_color));
+                    let fill = Rect::new(inner.position, Vector2::new(inner.size.x * progress, inner.size.y));
+                    quads.push(HudQuad::solid(fill, widget.color.unwrap_or(theme.progress_color)));
+                },
+            }
+        }
+        quads
+    }
+}
+
+#[derive(Clone)]
+enum QuadTexture {
+    White,
+    Image(Texture),
+    Font(Arc<Font>),
+}
+
+impl QuadTexture {
+    fn same_batch(&self, other: &QuadTexture) -> bool {
+        match (self, other) {
+            (QuadTexture::White, QuadTexture::White) => true,
+            (QuadTexture::Image(first), QuadTexture::Image(second)) => first == second,
+            (QuadTexture::Font(first), QuadTexture::Font(second)) => first.get_id() == second.get_id(),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct HudQuad {
+    min: Vector2<f32>,
+    max: Vector2<f32>,
+    uv_min: Vector2<f32>,
+    uv_max: Vector2<f32>,
+    color: Vector4<f32>,
+    texture: QuadTexture,
+}
+
+impl HudQuad {
+    fn solid(rect: Rect, color: Vector4<f32>) -> Self {
+        Self {
+            min: rect.position,
+            max: rect.position + rect.size,
+            uv_min: Vector2::new(0.0, 0.0),
+            uv_max: Vector2::new(1.0, 1.0),
+            color,
+            texture: QuadTexture::White,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+struct HudVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+    /// 1 for the glyphs sampling a distance field atlas.
+    sdf: f32,
+}
+
+/// Draws the HUD with one draw call per run of quads sharing a texture.
+pub(crate) struct HudRenderer {
+    shader: Shader,
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+    white: Texture,
+    fonts: HashMap<usize, gl::types::GLuint>,
+}
+
+impl HudRenderer {
+    pub(crate) fn new() -> Self {
+        let vert_source = r#"
+        #version 330 core
+        layout (location = 0) in vec2 aPos;
+        layout (location = 1) in vec2 aTexCoord;
+        layout (location = 2) in vec4 aColor;
+        layout (location = 3) in float aSdf;
+
+        out vec2 TexCoord;
+        out vec4 ourColor;
+        out float sdf;
+
+        uniform mat4 projection;
+
+        void main()
+        {
+            gl_Position = projection * vec4(aPos, 0.0, 1.0);
+            TexCoord = aTexCoord;
+            ourColor = aColor;
+            sdf = aSdf;
+        }
+    "#;
+        let frag_source = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        in vec2 TexCoord;
+        in vec4 ourColor;
+        in float sdf;
+
+        uniform sampler2D hudTexture;
+
+        void main()
+        {
+            vec4 texel = texture(hudTexture, TexCoord);
+            if (sdf > 0.5) {
+                float width = max(fwidth(texel.r), 0.0001);
+                FragColor = vec4(ourColor.rgb, ourColor.a * smoothstep(0.5 - width, 0.5 + width, texel.r));
+            } else {
+                FragColor = ourColor * texel;
+            }
+        }
+    "#;
+        let mut renderer = Self {
+            shader: Shader::new(vert_source, frag_source),
+            vao: 0,
+            vbo: 0,
+            white: Texture::new_empty(),
+            fonts: HashMap::new(),
+        };
+        let stride = std::mem::size_of::<HudVertex>() as gl::types::GLint;
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::BindVertexArray(renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 2]>() as *const gl::types::GLvoid);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 4]>() as *const gl::types::GLvoid);
+            gl::EnableVertexAttribArray(3);
+            gl::VertexAttribPointer(3, 1, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 8]>() as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+        renderer
+    }
+
+    fn bind(&mut self, texture: &QuadTexture) {
+        match texture {
+            QuadTexture::White => self.white.bind(),
+            QuadTexture::Image(texture) => texture.bind(),
+            QuadTexture::Font(font) => {
+                let atlas = *self.fonts.entry(font.get_id()).or_insert_with(|| text::create_atlas_texture(font));
+                unsafe { gl::BindTexture(gl::TEXTURE_2D, atlas) };
+            },
+        }
+    }
+
+    /// Lays the HUD out and draws it over the bound framebuffer, sized like the window.
+    pub(crate) fn draw(&mut self, hud: &mut Hud) {
+        hud.layout();
+        let quads = hud.build_quads();
+        if quads.is_empty() {
+            return;
+        }
+        let mut vertices = Vec::with_capacity(quads.len() * 6);
+        let mut batches: Vec<(QuadTexture, usize)> = Vec::new();
+        for quad in quads.iter() {
+            let sdf = if let QuadTexture::Font(_) = quad.texture { 1.0 } else { 0.0 };
+            let color: [f32; 4] = quad.color.into();
+            let corner = |x: f32, y: f32, u: f32, v: f32| HudVertex { position: [x, y], uv: [u, v], color, sdf };
+            let top_left = corner(quad.min.x, quad.min.y, quad.uv_min.x, quad.uv_min.y);
+            let top_right = corner(quad.max.x, quad.min.y, quad.uv_max.x, quad.uv_min.y);
+            let bottom_left = corner(quad.min.x, quad.max.y, quad.uv_min.x, quad.uv_max.y);
+            let bottom_right = corner(quad.max.x, quad.max.y, quad.uv_max.x, quad.uv_max.y);
+            vertices.extend_from_slice(&[top_left, bottom_left, bottom_right, top_left, bottom_right, top_right]);
+            match batches.last_mut() {
+                Some((texture, count)) if texture.same_batch(&quad.texture) => *count += 6,
+                _ => batches.push((quad.texture.clone(), 6)),
+            }
+        }
+
+        let size = hud.get_size();
+        let state = RenderState { depth_test: false, ..RenderState::transparent(BlendMode::Alpha) };
+        state.apply(None);
+        self.shader.use_program();
+        self.shader.set_mat4("projection", cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0));
+        self.shader.set_int("hudTexture", 0);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(vertices.as_slice()) as gl::types::GLsizeiptr, vertices.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+        }
+        let mut first = 0;
+        for (texture, count) in batches {
+            self.bind(&texture);
+            unsafe { gl::DrawArrays(gl::TRIANGLES, first as i32, count as i32) };
+            first += count;
+        }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+        RenderState::opaque().apply(Some(&state));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::EventReader;
+    use glutin::ModifiersState;
+
+    fn sized(widget: Widget, width: f32, height: f32) -> Widget {
+        widget.with_size(Size::Pixels(width), Size::Pixels(height))
+    }
+
+    #[test]
+    fn column_layout_with_padding_gap_and_grow() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        let panel = hud.add(hud.root(), sized(Widget::panel(), 200.0, 300.0).with_padding(10.0).with_gap(5.0).with_align(Align::Stretch));
+        let first = hud.add(panel, Widget::progress_bar(0.5).with_size(Size::Auto, Size::Pixels(20.0)));
+        let second = hud.add(panel, Widget::group().with_grow(1.0));
+        let third = hud.add(panel, sized(Widget::panel(), 50.0, 40.0));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(hud.get(first).unwrap().get_rect(), Rect::new(Vector2::new(10.0, 10.0), Vector2::new(180.0, 20.0)));
+        //The group takes the space left: 280 - 20 - 40 - 2 gaps
+        assert_eq!(hud.get(second).unwrap().get_rect(), Rect::new(Vector2::new(10.0, 35.0), Vector2::new(180.0, 210.0)));
+        assert_eq!(hud.get(third).unwrap().get_rect(), Rect::new(Vector2::new(10.0, 250.0), Vector2::new(50.0, 40.0)));
+    }
+
+    #[test]
+    fn row_layout_justify_and_auto_size() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        let row = hud.add(hud.root(), Widget::group().with_direction(Direction::Row).with_gap(10.0).with_align(Align::Center));
+        hud.add(row, sized(Widget::panel(), 30.0, 10.0));
+        let tall = hud.add(row, sized(Widget::panel(), 20.0, 50.0));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(hud.get(row).unwrap().get_rect().size, Vector2::new(60.0, 50.0));
+
+        let spread = hud.add(hud.root(), sized(Widget::group(), 100.0, 10.0).with_direction(Direction::Row).with_justify(Justify::SpaceBetween));
+        hud.add(spread, sized(Widget::panel(), 10.0, 10.0));
+        let last = hud.add(spread, sized(Widget::panel(), 10.0, 10.0));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(hud.get(last).unwrap().get_rect().position, Vector2::new(90.0, 50.0));
+        assert_eq!(hud.get(tall).unwrap().get_rect().position, Vector2::new(40.0, 0.0));
+    }
+
+    #[test]
+    fn anchors_and_resolution_scaling() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        hud.set_reference_resolution(Some((1280.0, 720.0)));
+        let corner = hud.add(hud.root(), sized(Widget::panel(), 100.0, 50.0).with_anchor(Anchor::BottomRight, Vector2::new(10.0, 20.0)));
+        let centered = hud.add(hud.root(), sized(Widget::panel(), 100.0, 50.0).with_anchor(Anchor::Center, Vector2::new(0.0, 0.0)));
+        let half = hud.add(hud.root(), Widget::panel().with_size(Size::Relative(0.5), Size::Pixels(10.0)).with_anchor(Anchor::Top, Vector2::new(0.0, 0.0)));
+        //Twice the reference width, only 1.5 times its height
+        hud.update(2560.0, 1080.0, &mut bus);
+        assert_eq!(hud.get_scale(), 1.5);
+        let size = hud.get_size();
+        let position = hud.get(corner).unwrap().get_rect().position;
+        assert!((position.x - (size.x - 110.0)).abs() < 1e-3 && position.y == 650.0);
+        assert_eq!(hud.get(centered).unwrap().get_rect().position, Vector2::new((size.x - 100.0) / 2.0, 335.0));
+        assert_eq!(hud.get(half).unwrap().get_rect().size.x, size.x / 2.0);
+    }
+
+    #[test]
+    fn labels_and_buttons_fit_their_text() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        hud.get_mut_theme().font = Some(Arc::new(Font::monospace()));
+        hud.get_mut_theme().text_size = 48.0;
+        let label = hud.add(hud.root(), Widget::label("abc"));
+        let button = hud.add(hud.root(), Widget::button("ok"));
+        let wrapped = hud.add(hud.root(), Widget::label("ab cd").with_size(Size::Pixels(60.0), Size::Auto));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(hud.get(label).unwrap().get_rect().size, Vector2::new(72.0, 48.0));
+        assert_eq!(hud.get(button).unwrap().get_rect().size, Vector2::new(48.0 + 16.0, 48.0 + 16.0));
+        assert_eq!(hud.get(wrapped).unwrap().get_rect().size, Vector2::new(60.0, 96.0));
+        //One quad per glyph and one for the button background
+        let quads = hud.build_quads();
+        assert_eq!(quads.len(), 3 + 1 + 2 + 4);
+        hud.get_mut(label).unwrap().set_text("a");
+        hud.layout();
+        assert_eq!(hud.get(label).unwrap().get_rect().size.x, 24.0);
+    }
+
+    #[test]
+    fn clicks_hovers_and_focus_are_sent_as_events() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        let mut reader = EventReader::<WidgetEvent>::new();
+        let menu = hud.add(hud.root(), Widget::group().with_direction(Direction::Row));
+        let play = hud.add(menu, sized(Widget::button("play"), 100.0, 40.0));
+        let quit = hud.add(menu, sized(Widget::button("quit"), 100.0, 40.0));
+        hud.update(800.0, 600.0, &mut bus);
+
+        hud.set_cursor_position(50.0, 20.0);
+        hud.update(800.0, 600.0, &mut bus);
+        assert!(hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        assert!(!hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Released));
+        hud.update(800.0, 600.0, &mut bus);
+        let events: Vec<WidgetEvent> = bus.read(&mut reader).copied().collect();
+        assert_eq!(events, vec![WidgetEvent::HoverStarted(play), WidgetEvent::Clicked(play)]);
+
+        //Pressing over a button and releasing elsewhere does not click
+        assert!(hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        hud.set_cursor_position(500.0, 500.0);
+        assert!(!hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Released));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(bus.read(&mut reader).copied().collect::<Vec<_>>(), vec![WidgetEvent::HoverEnded(play)]);
+        assert!(!hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        assert_eq!(hud.get_focused(), None);
+
+        let key = |key, shift| KeyboardInput { scancode: 0, state: ElementState::Pressed, virtual_keycode: Some(key), modifiers: ModifiersState { shift, ..ModifiersState::default() } };
+        assert!(!hud.set_key_event(key(Key::Tab, false)));
+        hud.set_focus(Some(play));
+        assert!(hud.set_key_event(key(Key::Tab, false)));
+        assert_eq!(hud.get_focused(), Some(quit));
+        assert!(hud.set_key_event(key(Key::Tab, true)));
+        assert!(hud.set_key_event(key(Key::Return, false)));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(bus.read(&mut reader).last(), Some(&WidgetEvent::Clicked(play)));
+    }
+
+    #[test]
+    fn mouse_clicks_do_not_take_the_keyboard() {
+        let mut hud = Hud::new();
+        let mut bus = EventBus::new();
+        let mut reader = EventReader::<WidgetEvent>::new();
+        let menu = hud.add(hud.root(), Widget::group());
+        let pause = hud.add(menu, sized(Widget::button("pause"), 100.0, 40.0));
+        hud.update(800.0, 600.0, &mut bus);
+        let key = |key| KeyboardInput { scancode: 0, state: ElementState::Pressed, virtual_keycode: Some(key), modifiers: ModifiersState::default() };
+
+        hud.set_cursor_position(50.0, 20.0);
+        assert!(hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        hud.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Released);
+        assert_eq!(hud.get_focused(), None);
+        assert!(!hud.set_key_event(key(Key::Space)));
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(bus.read(&mut reader).filter(|event| **event == WidgetEvent::Clicked(pause)).count(), 1);
+
+        //Hiding a parent of the focused button drops the focus
+        hud.set_focus(Some(pause));
+        hud.get_mut(menu).unwrap().visible = false;
+        assert!(!hud.set_key_event(key(Key::Return)));
+        assert_eq!(hud.get_focused(), None);
+        hud.set_focus(Some(pause));
+        assert_eq!(hud.get_focused(), None);
+        hud.update(800.0, 600.0, &mut bus);
+        assert_eq!(bus.read(&mut reader).copied().collect::<Vec<_>>(), vec![WidgetEvent::Focused(pause), WidgetEvent::Unfocused(pause), WidgetEvent::HoverEnded(pause)]);
+    }
+
+    #[test]
+    fn removing_a_widget_removes_its_children() {
+        let mut hud = Hud::new();
+        let panel = hud.add(hud.root(), Widget::panel().with_name("pause"));
+        let button = hud.add(panel, Widget::button("resume"));
+        hud.set_focus(Some(button));
+        assert_eq!(hud.find("pause"), Some(panel));
+        assert!(hud.remove(panel));
+        assert!(hud.get(button).is_none() && hud.get_focused().is_none());
+        assert!(hud.get(hud.root()).unwrap().get_children().is_empty());
+        assert!(!hud.remove(hud.root()) && !hud.remove(panel));
+        //The slot is reused but the removed ids stay invalid
+        let label = hud.add(hud.root(), Widget::label(""));
+        assert_ne!(label, panel);
+        assert!(hud.get(panel).is_none() && hud.get(label).is_some());
+        assert!(!hud.remove(panel));
+    }
+}
@@ -3,13 +3,24 @@ pub use glutin::VirtualKeyCode as Key;
 use std::collections::HashMap;
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum MouseButton {
     LEFT,
     RIGHT,
     MIDDLE
 }
 
+impl MouseButton {
+    pub fn from_glutin(button: glutin::MouseButton) -> Option<Self> {
+        match button {
+            glutin::MouseButton::Left => Some(MouseButton::LEFT),
+            glutin::MouseButton::Right => Some(MouseButton::RIGHT),
+            glutin::MouseButton::Middle => Some(MouseButton::MIDDLE),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ButtonState {
     PRESSED,
@@ -26,6 +37,15 @@ impl ButtonState {
     }
 }
 
+impl From<ElementState> for ButtonState {
+    fn from(state: ElementState) -> Self {
+        match state {
+            ElementState::Pressed => ButtonState::PRESSED,
+            ElementState::Released => ButtonState::RELEASED,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Input<'a> {
     keys: &'a HashMap<Key, ButtonState>,
@@ -105,6 +125,10 @@ impl InputSystem {
         }
     }
 
+    pub fn pressed_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.keys_state.iter().filter(|(_, state)| **state == ButtonState::PRESSED).map(|(key, _)| *key)
+    }
+
     pub fn is_mouse_button_pressed(&self, button: MouseButton) -> ButtonState {
         match button {
             MouseButton::LEFT => self.mouse_left,
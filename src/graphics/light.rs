@@ -1,6 +1,8 @@
 pub use cgmath::prelude::*;
 pub use cgmath::{Vector3, Matrix4, Point3, Quaternion, Euler, Deg};
+use crate::graphics::shader::LIGHT_UNIFORMS_BINDING;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Light {
     pub position: Vector3<f32>,
     pub color: Vector3<f32>
@@ -22,4 +24,207 @@ impl Light {
         let rotation = Quaternion::from(Euler::new(Deg(angles.x), Deg(angles.y), Deg(angles.z)));
         self.position = rotation.rotate_vector(self.position - center);
     }
-}
\ No newline at end of file
+}
+
+/// Most point lights shaded by the forward path, the ones nearest to the camera are kept.
+pub const MAX_FORWARD_LIGHTS: usize = 16;
+
+/// Lighting functions shared by the forward materials and the deferred passes, inserted in place of an
+/// `#include "lights"` line by `include_lights`. The `Frame` block must be declared before.
+pub const LIGHTS_GLSL: &str = r#"
+        layout (std140) uniform Lights {
+            // x: number of point lights
+            vec4 pointLightCount;
+            // xyz: position, w: radius
+            vec4 pointLightPositions[16];
+            // rgb: color times intensity
+            vec4 pointLightColors[16];
+        };
+        uniform samplerCube irradianceMap;
+        uniform samplerCube prefilterMap;
+        uniform bool useEnvironment;
+        uniform float environmentIntensity;
+        uniform float maxReflectionLod;
+        uniform sampler2D aoMap;
+        uniform bool useAmbientOcclusion;
+
+        // screen-space ambient occlusion of the pixel, 1 when disabled
+        float ambientOcclusion()
+        {
+            return useAmbientOcclusion ? texelFetch(aoMap, ivec2(gl_FragCoord.xy), 0).r : 1.0;
+        }
+
+        // ambient, from the environment maps when there is one
+        vec3 ambientLight(vec3 norm, vec3 viewDir, float specularStrength)
+        {
+            if (!useEnvironment)
+                return 0.3 * lightColor.rgb * ambientOcclusion();
+            vec3 reflected = reflect(-viewDir, norm);
+            float fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(norm, viewDir), 0.0), 5.0);
+            vec3 reflection = textureLod(prefilterMap, reflected, 0.5 * maxReflectionLod).rgb;
+            return (texture(irradianceMap, norm).rgb + specularStrength * fresnel * reflection) * environmentIntensity * ambientOcclusion();
+        }
+
+        // diffuse and specular of a light coming from lightDir
+        vec3 directLight(vec3 lightDir, vec3 radiance, vec3 norm, vec3 viewDir, float specularStrength)
+        {
+            float diff = max(dot(norm, lightDir), 0.0);
+            vec3 reflectDir = reflect(-lightDir, norm);
+            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
+            return (diff + specularStrength * spec) * radiance;
+        }
+
+        float pointLightAttenuation(float distance, float radius)
+        {
+            float window = clamp(1.0 - pow(distance / radius, 4.0), 0.0, 1.0);
+            return window * window / (distance * distance + 1.0);
+        }
+
+        vec3 pointLight(vec4 position, vec3 color, vec3 fragPos, vec3 norm, vec3 viewDir, float specularStrength)
+        {
+            vec3 toLight = position.xyz - fragPos;
+            float attenuation = pointLightAttenuation(length(toLight), position.w);
+            return directLight(normalize(toLight), color * attenuation, norm, viewDir, specularStrength);
+        }
+
+        // ambient and scene light
+        vec3 sceneLight(vec3 fragPos, vec3 norm, vec3 viewDir, float specularStrength)
+        {
+            vec3 lightDir = normalize(lightPos.xyz - fragPos);
+            return ambientLight(norm, viewDir, specularStrength) + directLight(lightDir, lightColor.rgb, norm, viewDir, specularStrength);
+        }
+
+        // point lights of the Lights block
+        vec3 forwardPointLights(vec3 fragPos, vec3 norm, vec3 viewDir, float specularStrength)
+        {
+            vec3 result = vec3(0.0);
+            for (int i = 0; i < int(pointLightCount.x); i++)
+                result += pointLight(pointLightPositions[i], pointLightColors[i].rgb, fragPos, norm, viewDir, specularStrength);
+            return result;
+        }
+    "#;
+
+/// Replaces the `#include "lights"` line of a shader source by `LIGHTS_GLSL`.
+pub fn include_lights(source: &str) -> String {
+    source.replace("#include \"lights\"", LIGHTS_GLSL)
+}
+
+/// Light component lighting the entities within `radius` of its `Transform` translation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub radius: f32,
+}
+
+impl PointLight {
+    pub fn new(color: Vector3<f32>, intensity: f32, radius: f32) -> Self {
+        Self { color, intensity, radius }
+    }
+
+    /// Fraction of the intensity received at `distance`, 0 from the radius on, same as `pointLightAttenuation`.
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        let window = (1.0 - (distance / self.radius).powi(4)).clamp(0.0, 1.0);
+        window * window / (distance * distance + 1.0)
+    }
+}
+
+/// Point light placed in the world, as sent to the shaders.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct PointLightData {
+    /// xyz: position, w: radius
+    pub position: [f32; 4],
+    /// rgb: color times intensity
+    pub color: [f32; 4],
+}
+
+impl PointLightData {
+    pub fn new(light: &PointLight, position: Vector3<f32>) -> Self {
+        Self {
+            position: position.extend(light.radius).into(),
+            color: (light.color * light.intensity).extend(1.0).into(),
+        }
+    }
+
+    fn distance2(&self, point: Vector3<f32>) -> f32 {
+        (Vector3::new(self.position[0], self.position[1], self.position[2]) - point).magnitude2()
+    }
+}
+
+/// The `count` lights nearest to `camera`.
+pub fn nearest_lights(lights: &[PointLightData], camera: Vector3<f32>, count: usize) -> Vec<PointLightData> {
+    let mut nearest = lights.to_vec();
+    nearest.sort_by(|a, b| a.distance2(camera).total_cmp(&b.distance2(camera)));
+    nearest.truncate(count);
+    nearest
+}
+
+/// Layout of the std140 `Lights` uniform block.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct LightsData {
+    count: [f32; 4],
+    positions: [[f32; 4]; MAX_FORWARD_LIGHTS],
+    colors: [[f32; 4]; MAX_FORWARD_LIGHTS],
+}
+
+/// Uniform buffer holding the point lights shaded by the forward materials.
+#[derive(Default)]
+pub struct LightUniforms {
+    ubo: gl::types::GLuint,
+}
+
+impl LightUniforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn upload(&mut self, lights: &[PointLightData], camera: Vector3<f32>) {
+        let mut data = LightsData { count: [0.0; 4], positions: [[0.0; 4]; MAX_FORWARD_LIGHTS], colors: [[0.0; 4]; MAX_FORWARD_LIGHTS] };
+        let nearest = nearest_lights(lights, camera, MAX_FORWARD_LIGHTS);
+        data.count[0] = nearest.len() as f32;
+        for (i, light) in nearest.iter().enumerate() {
+            data.positions[i] = light.position;
+            data.colors[i] = light.color;
+        }
+        let size = std::mem::size_of::<LightsData>() as gl::types::GLsizeiptr;
+        unsafe {
+            if self.ubo == 0 {
+                gl::GenBuffers(1, &mut self.ubo);
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+                gl::BufferData(gl::UNIFORM_BUFFER, size, std::ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, LIGHT_UNIFORMS_BINDING, self.ubo);
+            }
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size, &data as *const LightsData as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attenuation_reaches_zero_at_the_radius() {
+        let light = PointLight::new(Vector3::new(1.0, 1.0, 1.0), 2.0, 5.0);
+        assert_eq!(light.attenuation(0.0), 1.0);
+        assert!(light.attenuation(1.0) > light.attenuation(2.0));
+        assert_eq!(light.attenuation(5.0), 0.0);
+        assert_eq!(light.attenuation(8.0), 0.0);
+    }
+
+    #[test]
+    fn nearest_lights_are_kept() {
+        let light = PointLight::new(Vector3::new(1.0, 1.0, 1.0), 1.0, 5.0);
+        let lights: Vec<PointLightData> = [9.0, 1.0, 4.0].iter()
+            .map(|x| PointLightData::new(&light, Vector3::new(*x, 0.0, 0.0)))
+            .collect();
+        let nearest = nearest_lights(&lights, Vector3::new(0.0, 0.0, 0.0), 2);
+        assert_eq!(nearest.iter().map(|light| light.position[0]).collect::<Vec<f32>>(), vec![1.0, 4.0]);
+        assert_eq!(nearest[0].position[3], 5.0);
+    }
+}
@@ -1,7 +1,8 @@
 use cgmath::Vector3;
 use cgmath::prelude::*;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug,Copy, Clone)]
+#[derive(Debug,Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Color {
     r: f32,
     g: f32,
@@ -18,9 +19,13 @@ impl Color {
             a: 1.0
         }
     }
+
+    pub fn new_rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Vertex {
     x: f32,
     y: f32,
@@ -35,10 +40,14 @@ impl Vertex {
             z: z
         }
     }
+
+    pub fn to_vector(&self) -> Vector3<f32> {
+        Vector3::new(self.x, self.y, self.z)
+    }
 }
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UV {
     u: f32,
     v: f32
@@ -312,6 +321,49 @@ impl Mesh {
         return vertices
     }
 
+    /// Vertex indices of each triangle, the positions are read three by three when the mesh has no indices.
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        if self.indices.is_empty() {
+            (0..self.positions.len() / 3).map(|i| [i * 3, i * 3 + 1, i * 3 + 2]).collect()
+        } else {
+            self.indices.chunks_exact(3).map(|t| [t[0] as usize, t[1] as usize, t[2] as usize]).collect()
+        }
+    }
+
+    /// Minimum and maximum corners of the local bounding box, `None` for an empty mesh.
+    pub fn bounds(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let first = self.positions.first()?.to_vector();
+        Some(self.positions.iter().fold((first, first), |(min, max), pos| {
+            (
+                Vector3::new(min.x.min(pos.x), min.y.min(pos.y), min.z.min(pos.z)),
+                Vector3::new(max.x.max(pos.x), max.y.max(pos.y), max.z.max(pos.z)),
+            )
+        }))
+    }
+
+    /// Per vertex tangents following the U direction of the texture coordinates, averaged over the triangles sharing the vertex.
+    pub fn calculate_tangents(&self) -> Vec<Vector3<f32>> {
+        let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); self.positions.len()];
+        if self.texture_coords.len() < self.positions.len() {
+            return tangents;
+        }
+        for [a, b, c] in self.triangles() {
+            let edge1 = self.positions[b].to_vector() - self.positions[a].to_vector();
+            let edge2 = self.positions[c].to_vector() - self.positions[a].to_vector();
+            let (du1, dv1) = (self.texture_coords[b].u - self.texture_coords[a].u, self.texture_coords[b].v - self.texture_coords[a].v);
+            let (du2, dv2) = (self.texture_coords[c].u - self.texture_coords[a].u, self.texture_coords[c].v - self.texture_coords[a].v);
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+            for index in [a, b, c] {
+                tangents[index] += tangent;
+            }
+        }
+        tangents.into_iter().map(|t| if t.magnitude2() > 0.0 { t.normalize() } else { t }).collect()
+    }
+
     pub fn get_vertices_data(&self) -> &Vec<f32> {
         &self.vertices
     }
@@ -407,4 +459,16 @@ mod tests {
         let indices: Vec<u32> = vec![0, 1, 2, 1, 3, 2];
         assert_eq!(mesh.indices, indices);
     }
+
+    #[test]
+    fn bounds_and_tangents() {
+        let mut mesh_builder = MeshBuilder::new();
+        mesh_builder.add_vertex(0.0, 0.0, 0.0).add_vertex(2.0, 0.0, 0.0).add_vertex(0.0, 1.0, -1.0);
+        mesh_builder.add_uv(UV::new(0.0, 0.0)).add_uv(UV::new(1.0, 0.0)).add_uv(UV::new(0.0, 1.0));
+        let mesh = mesh_builder.commit();
+        assert_eq!(mesh.bounds(), Some((Vector3::new(0.0, 0.0, -1.0), Vector3::new(2.0, 1.0, 0.0))));
+        assert_eq!(mesh.triangles(), vec![[0, 1, 2]]);
+        assert!(mesh.calculate_tangents().iter().all(|t| *t == Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(Mesh::new_empty().bounds(), None);
+    }
 }
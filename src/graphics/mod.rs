@@ -3,10 +3,11 @@ extern crate glutin;
 
 use glutin::dpi::*;
 use glutin::GlContext;
-use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
 use std::ffi::{CStr};
 use std::time::{Duration, Instant};
 use crate::server::debug::DebugSystem;
+use crate::ecs::{Entity, World, Component, EventBus};
 
 pub mod mesh;
 pub mod shader;
@@ -19,19 +20,58 @@ pub mod camera;
 pub mod inputs;
 pub mod renderer;
 pub mod light;
+pub mod schedule;
+pub mod events;
+pub mod commands;
+pub mod game_states;
+pub mod scripting;
+pub mod serialization;
+pub mod prefab;
+pub mod render_queue;
+pub mod render_state;
+pub mod debug_draw;
+pub mod post_process;
+pub mod environment;
+pub mod fog;
+pub mod deferred;
+pub mod ssao;
+pub mod text;
+pub mod ui;
+pub mod hud;
+pub mod sprite;
+pub mod tilemap;
 
 use self::transform::Transform;
 use self::mesh::*;
 use self::shader::*;
 use self::states::*;
 use self::primitives::*;
-use self::camera::Camera;
-use self::inputs::InputSystem;
-use self::renderer::RenderSystem;
+use self::camera::{Camera, Camera2D};
+use self::inputs::{InputSystem, MouseButton};
+use self::events::{WindowEvent, InputEvent, EntityEvent};
+use self::commands::{Commands, Command};
+use self::game_states::{GameState, GameStateStack, Transition};
+use self::scripting::ScriptEngine;
+use self::serialization::{SceneSerializer, SceneComponent};
+use self::prefab::{PrefabLibrary, PrefabInstance, Overrides};
+use self::renderer::{RenderSystem, RenderPath};
+use self::debug_draw::{DebugDraw, DebugView};
+use self::post_process::PostProcessStack;
+use self::environment::Environment;
+use self::fog::Fog;
+use self::ssao::Ssao;
+use self::text::TextDraw;
+use self::ui::Ui;
+use self::hud::Hud;
+use self::tilemap::Tilemap;
 use self::light::Light;
+use self::schedule::{Schedule, Stage, Step, EngineSystem, System, ReadOnlySystem, SystemDescriptor};
 
 
-pub type Index = u32;
+pub type Index = Entity;
+
+/// Seconds between two checks of the prefab files modification time.
+const PREFAB_RELOAD_INTERVAL: f32 = 1.0;
 
 #[derive(Copy, Clone)]
 pub struct SceneObject {
@@ -41,75 +81,89 @@ pub struct SceneObject {
 impl SceneObject {}
 
 pub struct ComponentStorageManager {
-    pub mesh_manager: HashMap<Index, Mesh>,
-    shader_manager: HashMap<Index, Shader>,
-    transform_manager: HashMap<Index, Transform>,
-    material_manager: HashMap<Index, Material>,
-    update_manager: HashMap<Index, Box<SceneUpdate>>,
+    world: World,
+    events: EventBus,
     camera: Camera,
+    camera_2d: Camera2D,
     light: Light,
+    debug_draw: DebugDraw,
+    text: TextDraw,
+    ui: Ui,
+    hud: Hud,
+    fog: Option<Fog>,
+    /// Clock of the tile animations.
+    tile_time: f32,
+}
+
+impl Deref for ComponentStorageManager {
+    type Target = World;
+
+    fn deref(&self) -> &World {
+        &self.world
+    }
+}
+
+impl DerefMut for ComponentStorageManager {
+    fn deref_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
 }
 
 impl ComponentStorageManager {
     fn new() -> ComponentStorageManager {
         ComponentStorageManager {
-            mesh_manager: HashMap::new(),
-            shader_manager: HashMap::new(),
-            transform_manager: HashMap::new(),
-            material_manager: HashMap::new(),
-            update_manager: HashMap::new(),
+            world: World::new(),
+            events: EventBus::new(),
             camera: Camera::default(),
+            camera_2d: Camera2D::default(),
             light: Light::default(),
+            debug_draw: DebugDraw::new(),
+            text: TextDraw::new(),
+            ui: Ui::new(),
+            hud: Hud::new(),
+            fog: None,
+            tile_time: 0.0,
         }
     }
 
+    fn despawn_entity(&mut self, id: Index) -> bool {
+        self.world.despawn(id)
+    }
+
     pub fn get_mesh(&self, id: Index) -> Result<&Mesh, String> {
-        match self.mesh_manager.get(&id) {
-            None => Err("Mesh doesn't exist!".to_string()),
-            Some(mesh) => Ok(mesh),
-        }
+        self.get::<Mesh>(id).ok_or_else(|| "Mesh doesn't exist!".to_string())
     }
 
     pub fn get_mut_mesh(&mut self, id: Index) -> Option<&mut Mesh> {
-        self.mesh_manager.get_mut(&id)
+        self.get_mut::<Mesh>(id)
     }
 
 
     pub fn get_shader(&self, id: Index) -> Result<&Shader, String> {
-        match self.shader_manager.get(&id) {
-            None => Err("Shader doesn't exist!".to_string()),
-            Some(shader) => Ok(shader),
-        }
+        self.get::<Shader>(id).ok_or_else(|| "Shader doesn't exist!".to_string())
     }
 
     pub fn get_transform(&self, id: Index) -> Result<&Transform, String> {
-        match self.transform_manager.get(&id) {
-            None => Err("Transform doesn't exist!".to_string()),
-            Some(transform) => Ok(transform),
-        }
+        self.get::<Transform>(id).ok_or_else(|| "Transform doesn't exist!".to_string())
     }
 
     pub fn get_mut_transform(&mut self, id: Index) -> Result<&mut Transform, String> {
-        match self.transform_manager.get_mut(&id) {
-            None => Err("Transform doesn't exist!".to_string()),
-            Some(transform) => Ok(transform),
-        }
+        self.get_mut::<Transform>(id).ok_or_else(|| "Transform doesn't exist!".to_string())
     }
 
     pub fn get_material(&self, id: Index) -> Result<&Material, String> {
-        match self.material_manager.get(&id) {
-            None => Err("Material doesn't exist!".to_string()),
-            Some(material) => Ok(material),
-        }
+        self.get::<Material>(id).ok_or_else(|| "Material doesn't exist!".to_string())
     }
 
-    pub fn get_update(&self, id: Index) -> Result<&Box<SceneUpdate>, String> {
-        match self.update_manager.get(&id) {
-            None => Err("Update doesn't exist!".to_string()),
-            Some(update) => Ok(update),
-        }
-    }
     
+    pub fn get_events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn get_mut_events(&mut self) -> &mut EventBus {
+        &mut self.events
+    }
+
     pub fn get_camera(&self) -> &Camera {
         &self.camera
     }
@@ -118,10 +172,69 @@ impl ComponentStorageManager {
         &mut self.camera
     }
 
+    pub fn get_camera_2d(&self) -> &Camera2D {
+        &self.camera_2d
+    }
+
+    pub fn get_mut_camera_2d(&mut self) -> &mut Camera2D {
+        &mut self.camera_2d
+    }
+
+    pub fn get_light(&self) -> &Light {
+        &self.light
+    }
+
     pub fn get_mut_light(&mut self) -> &mut Light {
         &mut self.light
     }
 
+    pub fn get_fog(&self) -> Option<&Fog> {
+        self.fog.as_ref()
+    }
+
+    pub fn get_mut_fog(&mut self) -> Option<&mut Fog> {
+        self.fog.as_mut()
+    }
+
+    /// Fog applied by the built-in materials, `None` disables it.
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.fog = fog;
+    }
+
+    pub fn get_debug_draw(&self) -> &DebugDraw {
+        &self.debug_draw
+    }
+
+    pub fn get_mut_debug_draw(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    pub fn get_text(&self) -> &TextDraw {
+        &self.text
+    }
+
+    pub fn get_mut_text(&mut self) -> &mut TextDraw {
+        &mut self.text
+    }
+
+    pub fn get_ui(&self) -> &Ui {
+        &self.ui
+    }
+
+    pub fn get_hud(&self) -> &Hud {
+        &self.hud
+    }
+
+    pub fn get_mut_hud(&mut self) -> &mut Hud {
+        &mut self.hud
+    }
+
+}
+
+/// Settings chosen when creating the engine.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct EngineConfig {
+    pub render_path: RenderPath,
 }
 
 pub struct Engine {
@@ -131,19 +244,37 @@ pub struct Engine {
     states_system: StateSystem,
     input_system: InputSystem,
     debug_system: Option<DebugSystem>,
-    entity_count: Index,
+    schedule: Schedule,
+    commands: Commands,
+    game_states: GameStateStack,
+    scripts: ScriptEngine,
+    scenes: SceneSerializer,
+    prefabs: PrefabLibrary,
+    since_reload_check: f32,
 }
 
 impl Engine {
     pub fn new() -> Engine {
+        Self::with_config(EngineConfig::default())
+    }
+
+    pub fn with_config(config: EngineConfig) -> Engine {
+        let mut render_system = RenderSystem::new();
+        render_system.set_render_path(config.render_path);
         Engine {
             window: Window::new(),
             storage: ComponentStorageManager::new(),
-            render_system: RenderSystem::new(),
+            render_system,
             states_system: StateSystem::new(),
             input_system: InputSystem::new(),
             debug_system: None,
-            entity_count: 0,
+            schedule: Schedule::new(),
+            commands: Commands::new(),
+            game_states: GameStateStack::new(),
+            scripts: ScriptEngine::new(),
+            scenes: SceneSerializer::new(),
+            prefabs: PrefabLibrary::new(),
+            since_reload_check: 0.0,
         }
     }
 
@@ -176,15 +307,31 @@ impl Engine {
 
             // frame time
             let start_frame = Instant::now();
+            self.storage.events.update();
             running = self.manage_events();
+            if let Some(size) = self.window.gl_window.get_inner_size() {
+                let dpi_factor = self.window.gl_window.get_hidpi_factor();
+                self.storage.ui.begin_frame(size.width as f32, size.height as f32, dpi_factor as f32);
+                self.storage.hud.update(size.width as f32, size.height as f32, &mut self.storage.events);
+            }
             
             let delta = (delta_time.as_millis() as f32) / 1000.0;
-            self.states_system.run_update_state(&mut self.storage, &self.input_system, delta);
-            self.render_system.render(&mut self.storage);
-            match &self.debug_system {
-                Some(debug) => debug.pop_task(&mut self.storage),
-                None => ()
+            //Shapes are aged at the start of the frame, so those of the Late stage were rendered once
+            self.storage.debug_draw.update(delta);
+            for stage in Stage::ALL.iter() {
+                self.run_stage(*stage, delta);
+            }
+            self.render_system.render_ui(&mut self.storage.ui);
+            if self.game_states.has_quit() {
+                running = false;
+            }
+            self.since_reload_check += delta;
+            if self.since_reload_check >= PREFAB_RELOAD_INTERVAL {
+                self.since_reload_check = 0.0;
+                self.reload_prefabs();
             }
+            self.storage.text.clear();
+            self.storage.clear_trackers();
             let frame_duration = start_frame.elapsed().as_millis();
             self.window.gl_window.swap_buffers().unwrap();
             if delta_time.subsec_micros() > 0 {
@@ -193,26 +340,137 @@ impl Engine {
         }
     }
 
+    fn run_stage(&mut self, stage: Stage, delta: f32) {
+        for step in self.schedule.plan(stage) {
+            match step {
+                Step::Engine(EngineSystem::States) => {
+                    self.game_states.update(&mut self.storage, &self.input_system, &mut self.commands, delta);
+                    if self.game_states.updates_entities() {
+                        self.states_system.run_update_state(&mut self.storage, &self.input_system, &mut self.commands, delta);
+                    }
+                },
+                Step::Engine(EngineSystem::Animation) => {
+                    sprite::update_animations(&mut self.storage, delta);
+                    tilemap::update_tilemaps(&mut self.storage, delta);
+                },
+                Step::Engine(EngineSystem::Render) => self.render_system.render(&mut self.storage),
+                Step::Engine(EngineSystem::Debug) => if let Some(debug) = &self.debug_system {
+                    debug.pop_task(&mut self.storage);
+                },
+                Step::Systems(indices) => self.schedule.run_systems(stage, &indices, &mut self.storage, &self.input_system, &mut self.commands, delta),
+            }
+        }
+        self.apply_commands();
+    }
+
+    /// Applies the commands queued by states and systems, including the ones queued while applying them.
+    fn apply_commands(&mut self) {
+        while !self.commands.is_empty() {
+            for command in self.commands.take() {
+                match command {
+                    Command::Build(id, builder) => if self.storage.is_alive(id) {
+                        builder.insert_components(&mut self.storage, id);
+                    },
+                    Command::Apply(apply) => apply(&mut self.storage),
+                    Command::AddStates(id, states) => if self.storage.is_alive(id) {
+                        self.states_system.add_boxed_entity_states(id, states);
+                    },
+                    Command::Despawn(id) => {
+                        self.despawn(id);
+                    },
+                    Command::Transition(transition) => self.game_states.request(transition),
+                }
+            }
+        }
+    }
+
+    /// Registers a system in `stage`, the label can be used by other systems to order themselves around it.
+    /// Engine systems are labelled "states" (update), "animation" and "render" (render) and "debug" (late).
+    pub fn add_system<S: System + 'static>(&mut self, stage: Stage, label: &str, system: S) -> &mut SystemDescriptor {
+        self.schedule.add_system(stage, label, system)
+    }
+
+    pub fn add_read_only_system<S: ReadOnlySystem + 'static>(&mut self, stage: Stage, label: &str, system: S) -> &mut SystemDescriptor {
+        self.schedule.add_read_only_system(stage, label, system)
+    }
+
+    /// Number of threads used to run read-only systems in parallel, 0 or 1 disables it.
+    pub fn set_system_threads(&mut self, threads: usize) {
+        self.schedule.set_threads(threads);
+    }
+
     fn manage_events(&mut self) -> bool {
         let events = &mut self.window.events_loop;
         let window = &mut self.window.gl_window;
         let inputs = &mut self.input_system;
+        let bus = &mut self.storage.events;
+        let ui = &mut self.storage.ui;
+        let hud = &mut self.storage.hud;
         let mut running = true;
 
         events.poll_events(|event| match event {
             glutin::Event::WindowEvent { event, .. } => match event {
-                glutin::WindowEvent::CloseRequested => running = false,
+                glutin::WindowEvent::CloseRequested => {
+                    running = false;
+                    bus.send(WindowEvent::CloseRequested);
+                }
                 glutin::WindowEvent::Resized(logical_size) => {
                     let dpi_factor = window.get_hidpi_factor();
-                    window.resize(logical_size.to_physical(dpi_factor));
+                    let physical_size = logical_size.to_physical(dpi_factor);
+                    window.resize(physical_size);
+                    unsafe { gl::Viewport(0, 0, physical_size.width as i32, physical_size.height as i32) };
+                    bus.send(WindowEvent::Resized { width: logical_size.width, height: logical_size.height });
+                }
+                glutin::WindowEvent::Focused(focused) => {
+                    ui.set_focused(focused);
+                    bus.send(WindowEvent::Focused(focused));
+                }
+                glutin::WindowEvent::KeyboardInput {input, ..} => {
+                    let captured = ui.set_key_event(input) || hud.set_key_event(input);
+                    if !captured {
+                        inputs.set_key_event(input);
+                        if let Some(key) = input.virtual_keycode {
+                            bus.send(InputEvent::Key { key, state: input.state.into() });
+                        }
+                    }
+                }
+                glutin::WindowEvent::ReceivedCharacter(character) => ui.set_character_event(character),
+                glutin::WindowEvent::CursorMoved { position, .. } => {
+                    ui.set_cursor_position(position.x as f32, position.y as f32);
+                    hud.set_cursor_position(position.x as f32, position.y as f32);
+                }
+                glutin::WindowEvent::CursorLeft { .. } => {
+                    ui.set_cursor_left();
+                    hud.set_cursor_left();
+                }
+                glutin::WindowEvent::MouseInput { button, state, .. } => {
+                    let captured = ui.set_mouse_button_event(button, state) || hud.set_mouse_button_event(button, state);
+                    if !captured {
+                        inputs.set_mouse_button_event(button, state);
+                        if let Some(button) = MouseButton::from_glutin(button) {
+                            bus.send(InputEvent::MouseButton { button, state: state.into() });
+                        }
+                    }
+                }
+                glutin::WindowEvent::MouseWheel { delta, ..} => {
+                    let (x_delta, y_delta) = match delta {
+                        glutin::MouseScrollDelta::LineDelta(x, y) => (x, y),
+                        glutin::MouseScrollDelta::PixelDelta(position) => (position.x as f32, position.y as f32),
+                    };
+                    if !ui.set_mouse_wheel_event(x_delta, y_delta) {
+                        bus.send(InputEvent::MouseWheel { x_delta, y_delta });
+                    }
                 }
-                glutin::WindowEvent::KeyboardInput {input, ..} => inputs.set_key_event(input),
-                glutin::WindowEvent::MouseInput { button, state, .. } => inputs.set_mouse_button_event(button, state),
-                glutin::WindowEvent::MouseWheel { delta, ..} => (),
                 _ => (),
             },
             glutin::Event::DeviceEvent { event, .. } => match event {
-                glutin::DeviceEvent::MouseMotion { delta } => inputs.set_mouse_move_event(delta.0, delta.1),
+                //Dragging a UI element does not move the camera
+                glutin::DeviceEvent::MouseMotion { delta } => if ui.is_using_pointer() {
+                    inputs.set_mouse_move_event(0.0, 0.0);
+                } else {
+                    inputs.set_mouse_move_event(delta.0, delta.1);
+                    bus.send(InputEvent::MouseMotion { x_delta: delta.0, y_delta: delta.1 });
+                }
                 _ => (),
             }
             _ => (),
@@ -229,44 +487,186 @@ impl Engine {
     }
 
     pub fn create_scene_object(&mut self) -> SceneObject {
-        self.entity_count += 1;
+        let id = self.storage.spawn();
+        self.storage.events.send(EntityEvent::Spawned(id));
         SceneObject {
-            id: self.entity_count,
+            id,
         }
     }
 
+    /// Removes the entity and all its components. Returns false if the entity was already despawned.
+    pub fn despawn(&mut self, id: Index) -> bool {
+        if !self.storage.is_alive(id) {
+            return false;
+        }
+        if let Some(instance) = self.storage.get::<PrefabInstance>(id) {
+            for child in instance.children.clone() {
+                self.despawn(child);
+            }
+        }
+        self.states_system.remove_entity_states(id, &mut self.storage, &self.input_system, &mut self.commands);
+        self.render_system.remove_object(&id);
+        self.storage.events.send(EntityEvent::Despawned(id));
+        self.storage.despawn_entity(id)
+    }
+
+    pub fn is_alive(&self, id: Index) -> bool {
+        self.storage.is_alive(id)
+    }
+
     pub fn create_and_add_mesh(&mut self, id: Index) {
         let mesh = Mesh::new_empty();
-        self.storage.mesh_manager.insert(id, mesh);
+        self.storage.insert(id, mesh);
     }
 
     pub fn add_mesh(&mut self, id: Index, mesh: Mesh) {
-        println!("Mesh manager before update: {} objects", self.storage.mesh_manager.len());
-        self.storage.mesh_manager.insert(id, mesh);
-        println!("Mesh manager updated: {} objects", self.storage.mesh_manager.len());
+        println!("Mesh manager before update: {} objects", self.storage.count::<Mesh>());
+        self.storage.insert(id, mesh);
+        println!("Mesh manager updated: {} objects", self.storage.count::<Mesh>());
     }
 
     pub fn add_shader(&mut self, id: Index, shader: Shader) {
-        self.storage.shader_manager.insert(id, shader);
+        self.storage.insert(id, shader);
     }
 
     pub fn add_transform(&mut self, id: Index, transform: Transform) {
-        self.storage.transform_manager.insert(id, transform);
+        self.storage.insert(id, transform);
+    }
+
+    pub fn add_component<T: Component>(&mut self, id: Index, component: T) {
+        self.storage.insert(id, component);
     }
 
     pub fn add_states<T: EntityState>(&mut self, id: Index, states: T)
     where
         T: 'static,
     {
-        self.states_system.set_entity_states(id, states);
+        self.states_system.add_entity_states(id, states);
     }
 
-    pub fn add_material(&mut self, id: Index, material: Material) {
-        self.storage.material_manager.insert(id, material);
+    /// Attaches the Rhai script at `path` to the entity, the script is reloaded when the file changes.
+    pub fn add_script(&mut self, id: Index, path: &str) -> Result<(), String> {
+        let script = self.scripts.load(path)?;
+        self.states_system.add_entity_states(id, script);
+        Ok(())
+    }
+
+    pub fn get_script_engine(&self) -> &ScriptEngine {
+        &self.scripts
+    }
+
+    /// Saves every entity to a RON or JSON scene file, depending on the extension.
+    /// Prefab instances are saved as their prefab and overrides, they are spawned again from the prefab on load.
+    pub fn save_scene(&self, path: &str) -> Result<(), String> {
+        self.scenes.save_to_file(&self.storage, path)
+    }
+
+    /// Spawns the entities of a scene file saved by `save_scene`.
+    pub fn load_scene(&mut self, path: &str) -> Result<Vec<Index>, String> {
+        self.scenes.load_from_file(&mut self.prefabs, &mut self.storage, path)
+    }
+
+    pub fn register_scene_component<T: SceneComponent>(&mut self) {
+        self.scenes.register_component::<T>();
+    }
+
+    /// Makes the mesh available to scene files under `name`, entities using it get a `MeshAsset` component.
+    pub fn register_mesh(&mut self, name: &str, mesh: Mesh) {
+        self.scenes.register_mesh(name, mesh);
+    }
+
+    pub fn register_material(&mut self, name: &str, material: Material) {
+        self.scenes.register_material(name, material);
+    }
+
+    /// Makes the texture available to the sprites of scene files under `name`, entities using it get a `TextureAsset` component.
+    pub fn register_texture(&mut self, name: &str, texture: Texture) {
+        self.scenes.register_texture(name, texture);
     }
 
-    pub fn add_update(&mut self, id: Index, update: Box<SceneUpdate>) {
-        self.storage.update_manager.insert(id, update);
+    /// Makes the tilemap available to scene files under `name`, entities using it get a `TilemapAsset` component.
+    pub fn register_tilemap(&mut self, name: &str, tilemap: Tilemap) {
+        self.scenes.register_tilemap(name, tilemap);
+    }
+
+    /// Spawns the prefab file at `path`, the instance is updated when the prefab file changes.
+    pub fn instantiate_prefab(&mut self, path: &str, overrides: Overrides) -> Result<Index, String> {
+        self.prefabs.instantiate(path, overrides, &mut self.scenes, &mut self.storage)
+    }
+
+    /// Spawns again the instances of the prefab files modified since they were loaded.
+    pub fn reload_prefabs(&mut self) {
+        for root in self.prefabs.changed_instances(&self.storage) {
+            //A prefab file being saved may not parse yet, the previous children are kept until it is saved again
+            let (node, dependencies) = match self.prefabs.expand_instance(root, &self.storage) {
+                Ok(expanded) => expanded,
+                Err(err) => {
+                    println!("Prefab instance {} not reloaded: {}", root, err);
+                    continue;
+                },
+            };
+            let children = self.storage.get::<PrefabInstance>(root).map(|instance| instance.children.clone()).unwrap_or_default();
+            for child in children {
+                self.despawn(child);
+            }
+            if let Err(err) = PrefabLibrary::spawn_instance(root, node, dependencies, &mut self.scenes, &mut self.storage) {
+                println!("Prefab instance {} not reloaded: {}", root, err);
+            }
+            if !self.storage.contains::<Mesh>(root) {
+                self.render_system.remove_object(&root);
+            }
+        }
+    }
+
+    /// Effects applied to the HDR scene before it reaches the window, empty by default.
+    pub fn get_mut_post_process(&mut self) -> &mut PostProcessStack {
+        self.render_system.get_mut_post_process()
+    }
+
+    /// Replaces the clear color by the environment sky, which also lights the lit materials. `None` removes it.
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.render_system.set_environment(environment);
+    }
+
+    pub fn get_mut_environment(&mut self) -> Option<&mut Environment> {
+        self.render_system.get_mut_environment()
+    }
+
+    pub fn set_fog(&mut self, fog: Option<Fog>) {
+        self.storage.set_fog(fog);
+    }
+
+    /// Camera of the sprites, see `Camera2D`.
+    pub fn get_mut_camera_2d(&mut self) -> &mut Camera2D {
+        self.storage.get_mut_camera_2d()
+    }
+
+    /// Widgets drawn over the scene, see `Hud`.
+    pub fn get_mut_hud(&mut self) -> &mut Hud {
+        self.storage.get_mut_hud()
+    }
+
+    /// Ambient occlusion settings, disabled by default.
+    pub fn get_mut_ssao(&mut self) -> &mut Ssao {
+        self.render_system.get_mut_ssao()
+    }
+
+    /// Switches between forward and deferred lighting, see `RenderPath`.
+    pub fn set_render_path(&mut self, render_path: RenderPath) {
+        self.render_system.set_render_path(render_path);
+    }
+
+    pub fn toggle_debug_view(&mut self, view: DebugView) {
+        self.storage.debug_draw.toggle_view(view);
+    }
+
+    /// Enters `state` on top of the game state stack at the start of the next update.
+    pub fn push_state<T: GameState + 'static>(&mut self, state: T) {
+        self.game_states.request(Transition::Push(Box::new(state)));
+    }
+
+    pub fn add_material(&mut self, id: Index, material: Material) {
+        self.storage.insert(id, material);
     }
 
     pub fn get_mesh(&self, id: Index) -> &Mesh {
@@ -284,10 +684,6 @@ impl Engine {
     pub fn get_material(&self, id: Index) -> &Material {
         self.storage.get_material(id).unwrap()
     }
-
-    pub fn get_update(&self, id: Index) -> &Box<SceneUpdate> {
-        self.storage.get_update(id).unwrap()
-    }
 }
 
 struct Window {
@@ -0,0 +1,738 @@
+extern crate image;
+
+use std::any::Any;
+use cgmath::Vector2;
+use crate::graphics::shader::Shader;
+
+
+/// Vertex shader of the full-screen passes, the triangle is generated from `gl_VertexID`.
+pub(crate) const FULLSCREEN_VERT: &str = r#"
+        #version 330 core
+        out vec2 TexCoord;
+
+        void main()
+        {
+            TexCoord = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+            gl_Position = vec4(TexCoord * 2.0 - 1.0, 0.0, 1.0);
+        }
+    "#;
+
+/// Declarations shared by the fragment shaders of the effects, prepended to `PostEffect::fragment_source`.
+const POST_HEADER: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec2 TexCoord;
+
+        uniform sampler2D screenTexture;
+        uniform vec2 texelSize;
+    "#;
+
+const COPY_FRAG: &str = r#"
+        void main()
+        {
+            FragColor = texture(screenTexture, TexCoord);
+        }
+    "#;
+
+/// Full-screen pass of the post-process stack, reading the output of the previous pass from `screenTexture`.
+pub trait PostEffect: Any {
+    fn name(&self) -> &str;
+    /// Body of the fragment shader, `TexCoord`, `screenTexture`, `texelSize` and `FragColor` are already declared.
+    fn fragment_source(&self) -> String;
+    /// Called with the pass shader bound, before drawing.
+    fn set_uniforms(&self, _shader: &Shader) {}
+    /// Called before the pass with the texture it reads, effects needing extra passes render them here.
+    fn prepare(&mut self, _input: gl::types::GLuint, _width: i32, _height: i32) {}
+}
+
+/// Offscreen framebuffer with a floating point color texture.
+pub struct RenderTarget {
+    fbo: gl::types::GLuint,
+    color: gl::types::GLuint,
+    depth: Option<gl::types::GLuint>,
+    width: i32,
+    height: i32,
+}
+
+impl RenderTarget {
+    pub fn new(width: i32, height: i32, with_depth: bool) -> Self {
+        let mut target = Self {
+            fbo: 0,
+            color: 0,
+            depth: None,
+            width,
+            height,
+        };
+        unsafe {
+            gl::GenFramebuffers(1, &mut target.fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+            gl::GenTextures(1, &mut target.color);
+            gl::BindTexture(gl::TEXTURE_2D, target.color);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA16F as i32, width, height, 0, gl::RGBA, gl::FLOAT, std::ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, target.color, 0);
+            if with_depth {
+                let mut depth = 0;
+                gl::GenRenderbuffers(1, &mut depth);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+                gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth);
+                target.depth = Some(depth);
+            }
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                println!("Render target {}x{} is incomplete", width, height);
+            }
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        target
+    }
+
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl::Viewport(0, 0, self.width, self.height);
+        }
+    }
+
+    pub fn color_texture(&self) -> gl::types::GLuint {
+        self.color
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    pub fn delete(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.color);
+            if let Some(depth) = self.depth.take() {
+                gl::DeleteRenderbuffers(1, &depth);
+            }
+        }
+    }
+}
+
+/// Creates the target, or recreates it when its size changed.
+fn resize_target(target: &mut Option<RenderTarget>, width: i32, height: i32, with_depth: bool) {
+    if target.as_ref().is_some_and(|target| target.size() == (width, height)) {
+        return;
+    }
+    if let Some(mut old) = target.take() {
+        old.delete();
+    }
+    *target = Some(RenderTarget::new(width, height, with_depth));
+}
+
+fn compile_pass(source: &str) -> Shader {
+    let shader = Shader::new(FULLSCREEN_VERT, &format!("{}{}", POST_HEADER, source));
+    shader.use_program();
+    shader.set_int("screenTexture", 0);
+    shader
+}
+
+/// Draws the full-screen triangle reading `input` on texture unit 0.
+fn draw_fullscreen(shader: &Shader, input: gl::types::GLuint, width: i32, height: i32) {
+    shader.use_program();
+    shader.set_vec2("texelSize", Vector2::new(1.0 / width as f32, 1.0 / height as f32));
+    unsafe {
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, input);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    }
+}
+
+struct PostPass {
+    effect: Box<dyn PostEffect>,
+    shader: Option<Shader>,
+    enabled: bool,
+}
+
+/// HDR scene target followed by an ordered chain of full-screen effects, the last one draws to the window.
+#[derive(Default)]
+pub struct PostProcessStack {
+    passes: Vec<PostPass>,
+    scene: Option<RenderTarget>,
+    ping_pong: [Option<RenderTarget>; 2],
+    copy_shader: Option<Shader>,
+    vao: gl::types::GLuint,
+}
+
+impl PostProcessStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bloom, exposure, ACES tonemapping, gamma correction then FXAA.
+    pub fn standard() -> Self {
+        let mut stack = Self::new();
+        stack.add(Bloom::new());
+        stack.add(Exposure::new(1.0));
+        stack.add(Tonemap::new(Tonemapper::Aces));
+        stack.add(GammaCorrection::new(2.2));
+        stack.add(Fxaa::new());
+        stack
+    }
+
+    /// Appends the effect at the end of the chain.
+    pub fn add<T: PostEffect>(&mut self, effect: T) {
+        self.passes.push(PostPass { effect: Box::new(effect), shader: None, enabled: true });
+    }
+
+    /// Inserts the effect before the pass named `before`, or at the end when there is no such pass.
+    pub fn insert_before<T: PostEffect>(&mut self, before: &str, effect: T) {
+        let index = self.position(before).unwrap_or(self.passes.len());
+        self.passes.insert(index, PostPass { effect: Box::new(effect), shader: None, enabled: true });
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn PostEffect>> {
+        let index = self.position(name)?;
+        Some(self.passes.remove(index).effect)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if let Some(index) = self.position(name) {
+            self.passes[index].enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.position(name).is_some_and(|index| self.passes[index].enabled)
+    }
+
+    /// First effect of type `T`, to change its parameters.
+    pub fn get_mut<T: PostEffect>(&mut self) -> Option<&mut T> {
+        self.passes.iter_mut().find_map(|pass| {
+            let effect: &mut dyn Any = pass.effect.as_mut();
+            effect.downcast_mut::<T>()
+        })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.effect.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.passes.iter().position(|pass| pass.effect.name() == name)
+    }
+
+    /// Binds the HDR scene target, resized to the current viewport.
+    pub(crate) fn begin(&mut self) {
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let (width, height) = (viewport[2].max(1), viewport[3].max(1));
+        resize_target(&mut self.scene, width, height, true);
+        for target in self.ping_pong.iter_mut() {
+            resize_target(target, width, height, false);
+        }
+        if self.vao == 0 {
+            unsafe { gl::GenVertexArrays(1, &mut self.vao) };
+        }
+        self.scene.as_ref().unwrap().bind();
+    }
+
+    /// Runs the enabled effects on the scene target, the last one draws to the window.
+    pub(crate) fn finish(&mut self) {
+        let scene = match &self.scene {
+            Some(scene) => scene,
+            None => return,
+        };
+        let (width, height) = scene.size();
+        let mut input = scene.color_texture();
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::BindVertexArray(self.vao);
+        }
+        let enabled: Vec<usize> = (0..self.passes.len()).filter(|i| self.passes[*i].enabled).collect();
+        for (step, index) in enabled.iter().enumerate() {
+            let pass = &mut self.passes[*index];
+            pass.effect.prepare(input, width, height);
+            if pass.shader.is_none() {
+                pass.shader = Some(compile_pass(&pass.effect.fragment_source()));
+            }
+            let shader = pass.shader.unwrap();
+            let output = &self.ping_pong[step % 2];
+            if step + 1 == enabled.len() {
+                Self::bind_window(width, height);
+            } else {
+                output.as_ref().unwrap().bind();
+            }
+            shader.use_program();
+            pass.effect.set_uniforms(&shader);
+            draw_fullscreen(&shader, input, width, height);
+            input = output.as_ref().unwrap().color_texture();
+        }
+        if enabled.is_empty() {
+            let shader = *self.copy_shader.get_or_insert_with(|| compile_pass(COPY_FRAG));
+            Self::bind_window(width, height);
+            draw_fullscreen(&shader, input, width, height);
+        }
+        unsafe {
+            gl::BindVertexArray(0);
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    fn bind_window(width: i32, height: i32) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width, height);
+        }
+    }
+}
+
+/// Multiplies the HDR colors before tonemapping.
+pub struct Exposure {
+    pub exposure: f32,
+}
+
+impl Exposure {
+    pub fn new(exposure: f32) -> Self {
+        Self { exposure }
+    }
+}
+
+impl PostEffect for Exposure {
+    fn name(&self) -> &str {
+        "exposure"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform float exposure;
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            FragColor = vec4(color.rgb * exposure, color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        shader.set_float("exposure", self.exposure);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Tonemapper {
+    Reinhard,
+    /// Filmic curve fitted from the ACES reference transform.
+    Aces,
+}
+
+/// Maps the HDR colors to the [0, 1] range.
+pub struct Tonemap {
+    pub tonemapper: Tonemapper,
+}
+
+impl Tonemap {
+    pub fn new(tonemapper: Tonemapper) -> Self {
+        Self { tonemapper }
+    }
+}
+
+impl PostEffect for Tonemap {
+    fn name(&self) -> &str {
+        "tonemap"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform bool aces;
+
+        vec3 acesFilm(vec3 x)
+        {
+            return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+        }
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            vec3 mapped = aces ? acesFilm(color.rgb) : color.rgb / (color.rgb + vec3(1.0));
+            FragColor = vec4(mapped, color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        shader.set_bool("aces", self.tonemapper == Tonemapper::Aces);
+    }
+}
+
+pub struct GammaCorrection {
+    pub gamma: f32,
+}
+
+impl GammaCorrection {
+    pub fn new(gamma: f32) -> Self {
+        Self { gamma }
+    }
+}
+
+impl PostEffect for GammaCorrection {
+    fn name(&self) -> &str {
+        "gamma"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform float gamma;
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            FragColor = vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / gamma)), color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        shader.set_float("gamma", self.gamma);
+    }
+}
+
+/// Adds a blurred copy of the pixels brighter than `threshold`, blurred at half resolution.
+pub struct Bloom {
+    pub threshold: f32,
+    pub intensity: f32,
+    /// Number of horizontal plus vertical blur passes.
+    pub blur_passes: u32,
+    bright_shader: Option<Shader>,
+    blur_shader: Option<Shader>,
+    targets: [Option<RenderTarget>; 2],
+    result: gl::types::GLuint,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bloom {
+    pub fn new() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.5,
+            blur_passes: 5,
+            bright_shader: None,
+            blur_shader: None,
+            targets: [None, None],
+            result: 0,
+        }
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform sampler2D bloomTexture;
+        uniform float intensity;
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            FragColor = vec4(color.rgb + texture(bloomTexture, TexCoord).rgb * intensity, color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        shader.set_int("bloomTexture", 1);
+        shader.set_float("intensity", self.intensity);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.result);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    fn prepare(&mut self, input: gl::types::GLuint, width: i32, height: i32) {
+        let (width, height) = ((width / 2).max(1), (height / 2).max(1));
+        for target in self.targets.iter_mut() {
+            resize_target(target, width, height, false);
+        }
+        let bright = *self.bright_shader.get_or_insert_with(|| compile_pass(r#"
+        uniform float threshold;
+
+        void main()
+        {
+            vec3 color = texture(screenTexture, TexCoord).rgb;
+            float brightness = dot(color, vec3(0.2126, 0.7152, 0.0722));
+            FragColor = vec4(brightness > threshold ? color : vec3(0.0), 1.0);
+        }
+    "#));
+        let blur = *self.blur_shader.get_or_insert_with(|| compile_pass(r#"
+        uniform bool horizontal;
+
+        void main()
+        {
+            float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+            vec2 step = horizontal ? vec2(texelSize.x, 0.0) : vec2(0.0, texelSize.y);
+            vec3 result = texture(screenTexture, TexCoord).rgb * weights[0];
+            for (int i = 1; i < 5; ++i) {
+                result += texture(screenTexture, TexCoord + step * i).rgb * weights[i];
+                result += texture(screenTexture, TexCoord - step * i).rgb * weights[i];
+            }
+            FragColor = vec4(result, 1.0);
+        }
+    "#));
+        let targets = [self.targets[0].as_ref().unwrap(), self.targets[1].as_ref().unwrap()];
+        targets[0].bind();
+        bright.use_program();
+        bright.set_float("threshold", self.threshold);
+        draw_fullscreen(&bright, input, width, height);
+        for pass in 0..self.blur_passes * 2 {
+            let (source, destination) = (targets[(pass % 2) as usize], targets[((pass + 1) % 2) as usize]);
+            destination.bind();
+            blur.use_program();
+            blur.set_bool("horizontal", pass % 2 == 0);
+            draw_fullscreen(&blur, source.color_texture(), width, height);
+        }
+        //An even number of passes ends in the first target
+        self.result = targets[0].color_texture();
+    }
+}
+
+/// Fast approximate anti-aliasing, blurs the edges found from the luminance contrast.
+#[derive(Default)]
+pub struct Fxaa {}
+
+impl Fxaa {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &str {
+        "fxaa"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        const float REDUCE_MIN = 1.0 / 128.0;
+        const float REDUCE_MUL = 1.0 / 8.0;
+        const float SPAN_MAX = 8.0;
+
+        float luma(vec3 color)
+        {
+            return dot(color, vec3(0.299, 0.587, 0.114));
+        }
+
+        void main()
+        {
+            float lumaNW = luma(texture(screenTexture, TexCoord + vec2(-1.0, -1.0) * texelSize).rgb);
+            float lumaNE = luma(texture(screenTexture, TexCoord + vec2(1.0, -1.0) * texelSize).rgb);
+            float lumaSW = luma(texture(screenTexture, TexCoord + vec2(-1.0, 1.0) * texelSize).rgb);
+            float lumaSE = luma(texture(screenTexture, TexCoord + vec2(1.0, 1.0) * texelSize).rgb);
+            vec4 center = texture(screenTexture, TexCoord);
+            float lumaM = luma(center.rgb);
+            float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+            float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+            vec2 dir = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+            float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+            float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+            dir = clamp(dir * rcpDirMin, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texelSize;
+
+            vec3 rgbA = 0.5 * (texture(screenTexture, TexCoord + dir * (1.0 / 3.0 - 0.5)).rgb
+                + texture(screenTexture, TexCoord + dir * (2.0 / 3.0 - 0.5)).rgb);
+            vec3 rgbB = rgbA * 0.5 + 0.25 * (texture(screenTexture, TexCoord - dir * 0.5).rgb
+                + texture(screenTexture, TexCoord + dir * 0.5).rgb);
+            float lumaB = luma(rgbB);
+            FragColor = vec4((lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB, center.a);
+        }
+    "#.to_string()
+    }
+}
+
+/// Remaps the colors through a lookup table image: a horizontal strip of `size` slices of `size` by `size` pixels,
+/// e.g. 256x16 for a LUT of size 16.
+pub struct ColorGrading {
+    pub lut_path: String,
+    /// Blend between the original (0) and the graded colors (1).
+    pub strength: f32,
+    lut: Option<(gl::types::GLuint, u32)>,
+}
+
+impl ColorGrading {
+    pub fn new(lut_path: &str) -> Self {
+        Self {
+            lut_path: lut_path.to_string(),
+            strength: 1.0,
+            lut: None,
+        }
+    }
+
+    fn load_lut(path: &str) -> Option<(gl::types::GLuint, u32)> {
+        let image = match image::open(path) {
+            Ok(image) => image.to_rgba(),
+            Err(err) => {
+                println!("Color grading LUT {} not loaded: {}", path, err);
+                return None;
+            },
+        };
+        let (width, height) = image.dimensions();
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as i32, width as i32, height as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, image.into_raw().as_ptr() as *const gl::types::GLvoid);
+        }
+        Some((texture, height))
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &str {
+        "color_grading"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform sampler2D lut;
+        uniform float lutSize;
+        uniform float strength;
+
+        vec3 lutSample(float slice, vec2 uv)
+        {
+            vec2 coords = vec2((slice * lutSize + uv.x * (lutSize - 1.0) + 0.5) / (lutSize * lutSize), (uv.y * (lutSize - 1.0) + 0.5) / lutSize);
+            return texture(lut, coords).rgb;
+        }
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            vec3 clamped = clamp(color.rgb, 0.0, 1.0);
+            float blue = clamped.b * (lutSize - 1.0);
+            vec3 low = lutSample(floor(blue), clamped.rg);
+            vec3 high = lutSample(min(floor(blue) + 1.0, lutSize - 1.0), clamped.rg);
+            vec3 graded = mix(low, high, fract(blue));
+            FragColor = vec4(mix(color.rgb, graded, strength), color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        let (texture, size) = self.lut.unwrap_or((0, 1));
+        shader.set_int("lut", 1);
+        shader.set_float("lutSize", size as f32);
+        shader.set_float("strength", if self.lut.is_some() { self.strength } else { 0.0 });
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    fn prepare(&mut self, _input: gl::types::GLuint, _width: i32, _height: i32) {
+        if self.lut.is_none() && !self.lut_path.is_empty() {
+            self.lut = Self::load_lut(&self.lut_path);
+            if self.lut.is_none() {
+                //Do not retry every frame
+                self.lut_path.clear();
+            }
+        }
+    }
+}
+
+/// Darkens the corners of the screen.
+pub struct Vignette {
+    pub intensity: f32,
+    /// Distance from the center, in half screen units, where the darkening starts.
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vignette {
+    pub fn new() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 0.75,
+            smoothness: 0.45,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    fn name(&self) -> &str {
+        "vignette"
+    }
+
+    fn fragment_source(&self) -> String {
+        r#"
+        uniform float intensity;
+        uniform float radius;
+        uniform float smoothness;
+
+        void main()
+        {
+            vec4 color = texture(screenTexture, TexCoord);
+            float distance = length(TexCoord - vec2(0.5)) * 2.0;
+            float vignette = smoothstep(radius, radius + smoothness, distance);
+            FragColor = vec4(color.rgb * (1.0 - vignette * intensity), color.a);
+        }
+    "#.to_string()
+    }
+
+    fn set_uniforms(&self, shader: &Shader) {
+        shader.set_float("intensity", self.intensity);
+        shader.set_float("radius", self.radius);
+        shader.set_float("smoothness", self.smoothness);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Invert;
+
+    impl PostEffect for Invert {
+        fn name(&self) -> &str {
+            "invert"
+        }
+
+        fn fragment_source(&self) -> String {
+            "void main() { FragColor = vec4(1.0) - texture(screenTexture, TexCoord); }".to_string()
+        }
+    }
+
+    #[test]
+    fn stack_order_and_parameters() {
+        let mut stack = PostProcessStack::standard();
+        assert_eq!(stack.names(), vec!["bloom", "exposure", "tonemap", "gamma", "fxaa"]);
+        stack.insert_before("gamma", Invert);
+        stack.add(Vignette::new());
+        stack.remove("fxaa");
+        assert_eq!(stack.names(), vec!["bloom", "exposure", "tonemap", "invert", "gamma", "vignette"]);
+
+        stack.set_enabled("bloom", false);
+        assert!(!stack.is_enabled("bloom") && stack.is_enabled("tonemap"));
+        stack.get_mut::<Exposure>().unwrap().exposure = 2.0;
+        stack.get_mut::<Tonemap>().unwrap().tonemapper = Tonemapper::Reinhard;
+        assert_eq!(stack.get_mut::<Exposure>().unwrap().exposure, 2.0);
+        assert!(stack.get_mut::<ColorGrading>().is_none());
+    }
+}
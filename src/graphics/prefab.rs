@@ -0,0 +1,523 @@
+extern crate serde_json;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::graphics::{ComponentStorageManager, Index};
+use crate::graphics::serialization::{EntityData, SceneFormat, SceneSerializer};
+use crate::graphics::events::EntityEvent;
+use crate::graphics::transform::Transform;
+
+
+/// Version written in new prefab files.
+pub const PREFAB_VERSION: u32 = 1;
+
+/// Entity a prefab child was spawned under.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Parent(pub Index);
+
+/// Per-instance values replacing fields of the prefab, keyed by a path in the prefab tree,
+/// e.g. "transform/translation", "components/health" or "children/0/material".
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Overrides(BTreeMap<String, Value>);
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<T: Serialize>(mut self, path: &str, value: T) -> Self {
+        self.set(path, value);
+        self
+    }
+
+    pub fn set<T: Serialize>(&mut self, path: &str, value: T) {
+        let value = serde_json::to_value(value).expect("Override value can't be serialized");
+        self.0.insert(path.to_string(), value);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// True if a value replaces `field` or a part of it.
+    pub fn sets(&self, field: &str) -> bool {
+        self.0.keys().any(|path| path.strip_prefix(field).is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+    }
+
+    fn apply(&self, node: PrefabNode) -> Result<PrefabNode, String> {
+        if self.is_empty() {
+            return Ok(node);
+        }
+        let mut value = serde_json::to_value(node).map_err(|err| err.to_string())?;
+        for (path, field) in self.0.iter() {
+            Self::set_path(&mut value, path, field.clone())?;
+        }
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    fn set_path(value: &mut Value, path: &str, field: Value) -> Result<(), String> {
+        let mut current = value;
+        for key in path.split('/').filter(|key| !key.is_empty()) {
+            if current.is_null() {
+                *current = Value::Object(serde_json::Map::new());
+            }
+            current = match current {
+                Value::Object(map) => map.entry(key).or_insert(Value::Null),
+                Value::Array(array) => key.parse::<usize>().ok()
+                    .and_then(move |index| array.get_mut(index))
+                    .ok_or_else(|| format!("Override {}: no element {}", path, key))?,
+                _ => return Err(format!("Override {}: {} is not a field", path, key)),
+            };
+        }
+        *current = field;
+        Ok(())
+    }
+}
+
+/// One entity of a prefab. A node can instance another prefab file, its fields then replace those of the prefab root
+/// and its overrides apply to that prefab.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PrefabNode {
+    #[serde(flatten)]
+    pub entity: EntityData,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<String>,
+    #[serde(default, skip_serializing_if = "Overrides::is_empty")]
+    pub overrides: Overrides,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<PrefabNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrefabFile {
+    pub version: u32,
+    pub root: PrefabNode,
+}
+
+/// Added to the root entity of every prefab instance, used to update the instance when the prefab changes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabInstance {
+    pub prefab: PathBuf,
+    pub overrides: Overrides,
+    /// Entities spawned for the children of the prefab, the root excluded.
+    pub children: Vec<Index>,
+    dependencies: Vec<PathBuf>,
+}
+
+impl PrefabInstance {
+    pub fn depends_on(&self, path: &Path) -> bool {
+        self.dependencies.iter().any(|dependency| dependency == path)
+    }
+}
+
+struct LoadedPrefab {
+    /// `None` once the file changed, until it parses again.
+    root: Option<PrefabNode>,
+    modified: Option<SystemTime>,
+}
+
+/// Prefab files loaded so far, nested prefab paths are relative to the file using them.
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: HashMap<PathBuf, LoadedPrefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn load(&mut self, path: &Path) -> Result<PrefabNode, String> {
+        if let Some(root) = self.prefabs.get(path).and_then(|prefab| prefab.root.as_ref()) {
+            return Ok(root.clone());
+        }
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let file = Self::parse(&text, SceneFormat::from_path(path)).map_err(|err| format!("{}: {}", path.display(), err))?;
+        //A file that changed keeps the modification time seen by `take_changed`, so a later save is still reported
+        let modified = match self.prefabs.get(path) {
+            Some(prefab) => prefab.modified,
+            None => Self::modified(path),
+        };
+        self.prefabs.insert(path.to_path_buf(), LoadedPrefab {
+            root: Some(file.root.clone()),
+            modified,
+        });
+        Ok(file.root)
+    }
+
+    pub fn parse(text: &str, format: SceneFormat) -> Result<PrefabFile, String> {
+        let value = SceneSerializer::parse_value(text, format)?;
+        let version = value.get("version").and_then(Value::as_u64).ok_or("Prefab without version")? as u32;
+        if version == 0 || version > PREFAB_VERSION {
+            return Err(format!("Unsupported prefab version {}, the last one is {}", version, PREFAB_VERSION));
+        }
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    /// Prefab tree with every nested prefab replaced by its content, `dependencies` receives the files used.
+    fn expand(&mut self, path: &Path, dependencies: &mut Vec<PathBuf>, stack: &mut Vec<PathBuf>) -> Result<PrefabNode, String> {
+        if stack.iter().any(|parent| parent == path) {
+            return Err(format!("Prefab {} includes itself", path.display()));
+        }
+        if !dependencies.iter().any(|dependency| dependency == path) {
+            dependencies.push(path.to_path_buf());
+        }
+        stack.push(path.to_path_buf());
+        let root = self.load(path)?;
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let root = self.expand_node(root, &directory, dependencies, stack);
+        stack.pop();
+        root
+    }
+
+    fn expand_node(&mut self, node: PrefabNode, directory: &Path, dependencies: &mut Vec<PathBuf>, stack: &mut Vec<PathBuf>) -> Result<PrefabNode, String> {
+        let PrefabNode { entity, prefab, overrides, children } = node;
+        let mut children = children.into_iter()
+            .map(|child| self.expand_node(child, directory, dependencies, stack))
+            .collect::<Result<Vec<_>, _>>()?;
+        match prefab {
+            Some(prefab) => {
+                let mut nested = self.expand(&directory.join(prefab), dependencies, stack)?;
+                nested.entity = Self::merge_entity(nested.entity, entity);
+                nested = overrides.apply(nested)?;
+                nested.children.append(&mut children);
+                Ok(nested)
+            },
+            None => overrides.apply(PrefabNode { entity, prefab: None, overrides: Overrides::new(), children }),
+        }
+    }
+
+    /// Fields set on a node instancing a prefab replace those of the prefab root, its components are added.
+    fn merge_entity(mut base: EntityData, node: EntityData) -> EntityData {
+        if node.transform.is_some() {
+            base.transform = node.transform;
+        }
+        if node.mesh.is_some() {
+            base.mesh = node.mesh;
+        }
+        if node.material.is_some() {
+            base.material = node.material;
+        }
+        if node.point_light.is_some() {
+            base.point_light = node.point_light;
+        }
+        if node.sprite.is_some() {
+            base.sprite = node.sprite;
+        }
+        if node.tilemap.is_some() {
+            base.tilemap = node.tilemap;
+        }
+        base.components.extend(node.components);
+        base
+    }
+
+    /// Spawns the prefab, its root entity is returned. Nothing stays spawned when it fails.
+    pub fn instantiate<P: AsRef<Path>>(&mut self, path: P, overrides: Overrides, scenes: &mut SceneSerializer, storage: &mut ComponentStorageManager) -> Result<Index, String> {
+        let root = storage.spawn();
+        storage.get_mut_events().send(EntityEvent::Spawned(root));
+        storage.insert(root, PrefabInstance {
+            prefab: path.as_ref().to_path_buf(),
+            overrides,
+            children: Vec::new(),
+            dependencies: Vec::new(),
+        });
+        if let Err(err) = self.respawn(root, scenes, storage) {
+            let children = storage.get::<PrefabInstance>(root).map(|instance| instance.children.clone()).unwrap_or_default();
+            for id in children.into_iter().chain(std::iter::once(root)) {
+                storage.get_mut_events().send(EntityEvent::Despawned(id));
+                storage.despawn_entity(id);
+            }
+            return Err(err);
+        }
+        Ok(root)
+    }
+
+    /// Spawns again the children of an instance whose previous children were despawned, the root keeps its id.
+    pub fn respawn(&mut self, root: Index, scenes: &mut SceneSerializer, storage: &mut ComponentStorageManager) -> Result<(), String> {
+        let (node, dependencies) = self.expand_instance(root, storage)?;
+        Self::spawn_instance(root, node, dependencies, scenes, storage)
+    }
+
+    /// Prefab tree of an instance with its overrides applied and the files it uses, nothing is spawned.
+    pub(crate) fn expand_instance(&mut self, root: Index, storage: &ComponentStorageManager) -> Result<(PrefabNode, Vec<PathBuf>), String> {
+        let instance = storage.get::<PrefabInstance>(root).ok_or("Entity is not a prefab instance")?;
+        let mut dependencies = Vec::new();
+        let node = self.expand(&instance.prefab, &mut dependencies, &mut Vec::new())?;
+        Ok((instance.overrides.apply(node)?, dependencies))
+    }
+
+    /// Spawns the children of an expanded instance under `root`.
+    /// The fields the root got from a previous version of the prefab are removed first, the transform is kept if the instance overrides it.
+    pub(crate) fn spawn_instance(root: Index, node: PrefabNode, dependencies: Vec<PathBuf>, scenes: &mut SceneSerializer, storage: &mut ComponentStorageManager) -> Result<(), String> {
+        scenes.remove_entity_data(storage, root);
+        if !storage.get::<PrefabInstance>(root).is_some_and(|instance| instance.overrides.sets("transform")) {
+            storage.remove::<Transform>(root);
+        }
+        let mut children = Vec::new();
+        let result = Self::spawn_node(node, root, scenes, storage, &mut children);
+        if let Some(instance) = storage.get_mut::<PrefabInstance>(root) {
+            instance.children = children;
+            instance.dependencies = dependencies;
+        }
+        result
+    }
+
+    fn spawn_node(node: PrefabNode, id: Index, scenes: &mut SceneSerializer, storage: &mut ComponentStorageManager, spawned: &mut Vec<Index>) -> Result<(), String> {
+        scenes.load_entity(node.entity, storage, id)?;
+        for child in node.children {
+            let child_id = storage.spawn();
+            storage.get_mut_events().send(EntityEvent::Spawned(child_id));
+            storage.insert(child_id, Parent(id));
+            spawned.push(child_id);
+            Self::spawn_node(child, child_id, scenes, storage, spawned)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets the content of the prefab files modified since they were last checked and returns their paths.
+    /// The files stay watched, one that doesn't parse yet is reported again when it is saved next.
+    pub fn take_changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, prefab) in self.prefabs.iter_mut() {
+            let modified = Self::modified(path);
+            if modified != prefab.modified {
+                prefab.modified = modified;
+                prefab.root = None;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    /// Roots of the instances using a prefab file modified since the last call, see `take_changed`.
+    pub(crate) fn changed_instances(&mut self, storage: &ComponentStorageManager) -> Vec<Index> {
+        let changed = self.take_changed();
+        if changed.is_empty() {
+            return Vec::new();
+        }
+        storage.query_read::<(Index, &PrefabInstance)>().iter()
+            .filter(|(_, instance)| changed.iter().any(|path| instance.depends_on(path)))
+            .map(|(root, _)| root)
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::mesh::Mesh;
+    use crate::graphics::serialization::{MeshAsset, SceneComponent};
+    use crate::graphics::transform::Vector3;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    impl SceneComponent for Health {
+        fn scene_name() -> &'static str {
+            "health"
+        }
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("neutrino_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn write_prefabs(directory: &Path, health: u32) {
+        fs::write(directory.join("crate.ron"), format!("(
+            version: 1,
+            root: (
+                transform: Some((translation: (0.0, 0.0, 0.0))),
+                components: {{ \"health\": {} }},
+                children: [(transform: Some((translation: (0.0, 1.0, 0.0))))],
+            ),
+        )", health)).unwrap();
+        fs::write(directory.join("stack.ron"), "(
+            version: 1,
+            root: (
+                children: [
+                    (prefab: Some(\"crate.ron\")),
+                    (prefab: Some(\"crate.ron\"), overrides: { \"transform/translation\": (2.0, 0.0, 0.0) }),
+                ],
+            ),
+        )").unwrap();
+    }
+
+    #[test]
+    fn nested_prefabs_with_overrides() {
+        let directory = directory("prefab_nested");
+        write_prefabs(&directory, 10);
+        let mut scenes = SceneSerializer::new();
+        scenes.register_component::<Health>();
+        let mut storage = ComponentStorageManager::new();
+        let mut library = PrefabLibrary::new();
+
+        let overrides = Overrides::new().with("children/0/components/health", 99);
+        let root = library.instantiate(directory.join("stack.ron"), overrides, &mut scenes, &mut storage).unwrap();
+        let instance = storage.get::<PrefabInstance>(root).unwrap().clone();
+        assert_eq!(instance.children.len(), 4);
+        assert!(instance.depends_on(&directory.join("crate.ron")));
+        let (first, second) = (instance.children[0], instance.children[2]);
+        assert_eq!(storage.get::<Parent>(first), Some(&Parent(root)));
+        assert_eq!(storage.get::<Parent>(instance.children[1]), Some(&Parent(first)));
+        assert_eq!(storage.get::<Health>(first), Some(&Health(99)));
+        assert_eq!(storage.get::<Health>(second), Some(&Health(10)));
+        assert_eq!(storage.get::<Transform>(second).unwrap().translation, Vector3::new(2.0, 0.0, 0.0));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn edits_propagate_on_reload() {
+        let directory = directory("prefab_reload");
+        write_prefabs(&directory, 10);
+        let mut scenes = SceneSerializer::new();
+        scenes.register_component::<Health>();
+        let mut storage = ComponentStorageManager::new();
+        let mut library = PrefabLibrary::new();
+        let root = library.instantiate(directory.join("crate.ron"), Overrides::new(), &mut scenes, &mut storage).unwrap();
+        assert!(library.take_changed().is_empty());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        write_prefabs(&directory, 20);
+        let changed = library.take_changed();
+        assert_eq!(changed, vec![directory.join("crate.ron")]);
+        for child in storage.get::<PrefabInstance>(root).unwrap().children.clone() {
+            storage.despawn(child);
+        }
+        library.respawn(root, &mut scenes, &mut storage).unwrap();
+        assert_eq!(storage.get::<Health>(root), Some(&Health(20)));
+        assert_eq!(storage.count::<Parent>(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Same steps as `Engine::reload_prefabs`, returns the instances that failed to reload.
+    fn reload(library: &mut PrefabLibrary, scenes: &mut SceneSerializer, storage: &mut ComponentStorageManager) -> Vec<Index> {
+        let mut failed = Vec::new();
+        for root in library.changed_instances(storage) {
+            let (node, dependencies) = match library.expand_instance(root, storage) {
+                Ok(expanded) => expanded,
+                Err(_) => {
+                    failed.push(root);
+                    continue;
+                },
+            };
+            for child in storage.get::<PrefabInstance>(root).unwrap().children.clone() {
+                storage.despawn(child);
+            }
+            PrefabLibrary::spawn_instance(root, node, dependencies, scenes, storage).unwrap();
+        }
+        failed
+    }
+
+    #[test]
+    fn broken_saves_keep_the_prefab_watched() {
+        let directory = directory("prefab_broken_save");
+        write_prefabs(&directory, 10);
+        let mut scenes = SceneSerializer::new();
+        scenes.register_component::<Health>();
+        let mut storage = ComponentStorageManager::new();
+        let mut library = PrefabLibrary::new();
+        let root = library.instantiate(directory.join("crate.ron"), Overrides::new(), &mut scenes, &mut storage).unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(directory.join("crate.ron"), "(version: 1, root: (components: {").unwrap();
+        assert_eq!(reload(&mut library, &mut scenes, &mut storage), vec![root]);
+        assert_eq!(storage.get::<Health>(root), Some(&Health(10)));
+        assert_eq!(storage.count::<Parent>(), 1);
+        assert!(reload(&mut library, &mut scenes, &mut storage).is_empty());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        write_prefabs(&directory, 30);
+        assert!(reload(&mut library, &mut scenes, &mut storage).is_empty());
+        assert_eq!(storage.get::<Health>(root), Some(&Health(30)));
+        assert_eq!(storage.count::<Parent>(), 1);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn fields_removed_from_the_prefab_leave_the_instances() {
+        let directory = directory("prefab_removed_field");
+        let path = directory.join("lamp.ron");
+        fs::write(&path, "(version: 1, root: (transform: Some((translation: (0.0, 1.0, 0.0))), mesh: Some((asset: Some(\"quad\"))), components: { \"health\": 5 }))").unwrap();
+        let mut scenes = SceneSerializer::new();
+        scenes.register_component::<Health>();
+        let mut storage = ComponentStorageManager::new();
+        let mut library = PrefabLibrary::new();
+        let root = library.instantiate(&path, Overrides::new(), &mut scenes, &mut storage).unwrap();
+        let moved = library.instantiate(&path, Overrides::new().with("transform/translation", (4.0, 0.0, 0.0)), &mut scenes, &mut storage).unwrap();
+        assert!(storage.contains::<Mesh>(root) && storage.contains::<MeshAsset>(root));
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&path, "(version: 1, root: (children: []))").unwrap();
+        assert!(reload(&mut library, &mut scenes, &mut storage).is_empty());
+        for id in [root, moved] {
+            assert!(!storage.contains::<Mesh>(id) && !storage.contains::<MeshAsset>(id));
+            assert_eq!(storage.get::<Health>(id), None);
+            assert!(storage.contains::<PrefabInstance>(id));
+        }
+        assert_eq!(storage.get::<Transform>(root), None);
+        assert_eq!(storage.get::<Transform>(moved).unwrap().translation, Vector3::new(4.0, 0.0, 0.0));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn nested_prefab_nodes_place_their_prefab() {
+        let directory = directory("prefab_placed");
+        write_prefabs(&directory, 10);
+        fs::write(directory.join("placed.ron"), "(
+            version: 1,
+            root: (
+                children: [
+                    (prefab: Some(\"crate.ron\"), transform: Some((translation: (0.0, 0.0, 5.0))), components: { \"health\": 4 }),
+                    (prefab: Some(\"crate.ron\"), transform: Some((translation: (0.0, 0.0, 5.0))), overrides: { \"transform/translation\": (3.0, 0.0, 0.0) }),
+                ],
+            ),
+        )").unwrap();
+        let mut scenes = SceneSerializer::new();
+        scenes.register_component::<Health>();
+        let mut storage = ComponentStorageManager::new();
+        let root = PrefabLibrary::new().instantiate(directory.join("placed.ron"), Overrides::new(), &mut scenes, &mut storage).unwrap();
+        let instance = storage.get::<PrefabInstance>(root).unwrap().clone();
+        assert_eq!(instance.children.len(), 4);
+        let (first, second) = (instance.children[0], instance.children[2]);
+        assert_eq!(storage.get::<Transform>(first).unwrap().translation, Vector3::new(0.0, 0.0, 5.0));
+        assert_eq!(storage.get::<Health>(first), Some(&Health(4)));
+        assert_eq!(storage.get::<Transform>(instance.children[1]).unwrap().translation, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(storage.get::<Transform>(second).unwrap().translation, Vector3::new(3.0, 0.0, 0.0));
+        assert_eq!(storage.get::<Health>(second), Some(&Health(10)));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn self_including_prefab_fails() {
+        let directory = directory("prefab_cycle");
+        fs::write(directory.join("loop.ron"), "(version: 1, root: (prefab: Some(\"loop.ron\")))").unwrap();
+        let mut storage = ComponentStorageManager::new();
+        let result = PrefabLibrary::new().instantiate(directory.join("loop.ron"), Overrides::new(), &mut SceneSerializer::new(), &mut storage);
+        assert!(result.is_err());
+        assert_eq!(storage.count::<PrefabInstance>(), 0);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn failed_instances_are_despawned() {
+        let directory = directory("prefab_broken");
+        fs::write(directory.join("broken.ron"), "(version: 1, root: (children: [(), (mesh: Some((asset: Some(\"missing\"))))]))").unwrap();
+        let mut storage = ComponentStorageManager::new();
+        let result = PrefabLibrary::new().instantiate(directory.join("broken.ron"), Overrides::new(), &mut SceneSerializer::new(), &mut storage);
+        assert!(result.is_err());
+        assert_eq!(storage.query_read::<Index>().iter().count(), 0);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use cgmath::{Matrix4, Vector3};
+use crate::graphics::shader::{MaterialKey, FRAME_UNIFORMS_BINDING};
+
+
+/// Distance from the camera mapped to the largest depth of a sort key.
+pub const MAX_SORT_DEPTH: f32 = 100.0;
+
+const DEPTH_BITS: u32 = 30;
+const STATE_BITS: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderPass {
+    Opaque,
+    Transparent,
+}
+
+/// Draws are executed in increasing key order: opaque draws are grouped by shader then texture and go front to back,
+/// transparent draws go back to front whatever their state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortKey(u64);
+
+impl SortKey {
+    pub fn new(pass: RenderPass, material: MaterialKey, depth: f32) -> Self {
+        let depth_mask = (1u64 << DEPTH_BITS) - 1;
+        let depth = ((depth / MAX_SORT_DEPTH).clamp(0.0, 1.0) as f64 * depth_mask as f64) as u64;
+        let state_mask = (1u64 << STATE_BITS) - 1;
+        let (shader, texture) = (material.0 as u64 & state_mask, material.1 as u64 & state_mask);
+        let key = match pass {
+            RenderPass::Opaque => shader << (STATE_BITS + DEPTH_BITS) | texture << DEPTH_BITS | depth,
+            RenderPass::Transparent => (depth_mask - depth) << (2 * STATE_BITS) | shader << STATE_BITS | texture,
+        };
+        SortKey((pass as u64) << 62 | key)
+    }
+
+    pub fn pass(self) -> RenderPass {
+        match self.0 >> 62 {
+            0 => RenderPass::Opaque,
+            _ => RenderPass::Transparent,
+        }
+    }
+}
+
+/// Draws collected during a frame, executed in sort key order.
+pub struct RenderQueue<T> {
+    items: Vec<(SortKey, T)>,
+}
+
+impl<T> Default for RenderQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, key: SortKey, item: T) {
+        self.items.push((key, item));
+    }
+
+    pub fn sort(&mut self) {
+        self.items.sort_by_key(|(key, _)| *key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(SortKey, T)> {
+        self.items.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+/// Layout of the std140 `Frame` uniform block declared by the shaders.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct FrameData {
+    view: [[f32; 4]; 4],
+    projection: [[f32; 4]; 4],
+    view_position: [f32; 4],
+    light_position: [f32; 4],
+    light_color: [f32; 4],
+}
+
+/// Uniform buffer holding the data shared by every draw of a frame.
+#[derive(Default)]
+pub struct FrameUniforms {
+    ubo: gl::types::GLuint,
+}
+
+impl FrameUniforms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn upload(&mut self, view: Matrix4<f32>, projection: Matrix4<f32>, view_position: Vector3<f32>, light_position: Vector3<f32>, light_color: Vector3<f32>) {
+        let data = FrameData {
+            view: view.into(),
+            projection: projection.into(),
+            view_position: view_position.extend(1.0).into(),
+            light_position: light_position.extend(1.0).into(),
+            light_color: light_color.extend(1.0).into(),
+        };
+        let size = std::mem::size_of::<FrameData>() as gl::types::GLsizeiptr;
+        unsafe {
+            if self.ubo == 0 {
+                gl::GenBuffers(1, &mut self.ubo);
+                gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+                gl::BufferData(gl::UNIFORM_BUFFER, size, std::ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BindBufferBase(gl::UNIFORM_BUFFER, FRAME_UNIFORMS_BINDING, self.ubo);
+            }
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size, &data as *const FrameData as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(keys: &[(SortKey, &'static str)]) -> Vec<&'static str> {
+        let mut queue = RenderQueue::new();
+        for (key, name) in keys.iter() {
+            queue.push(*key, *name);
+        }
+        queue.sort();
+        queue.iter().map(|(_, name)| *name).collect()
+    }
+
+    #[test]
+    fn opaque_grouped_by_state_then_front_to_back() {
+        let keys = [
+            (SortKey::new(RenderPass::Opaque, (2, 1), 1.0), "shader 2"),
+            (SortKey::new(RenderPass::Opaque, (1, 1), 50.0), "far"),
+            (SortKey::new(RenderPass::Opaque, (1, 1), 5.0), "near"),
+            (SortKey::new(RenderPass::Opaque, (1, 2), 0.0), "other texture"),
+        ];
+        assert_eq!(order(&keys), vec!["near", "far", "other texture", "shader 2"]);
+    }
+
+    #[test]
+    fn transparent_after_opaque_back_to_front() {
+        let keys = [
+            (SortKey::new(RenderPass::Transparent, (1, 1), 2.0), "near glass"),
+            (SortKey::new(RenderPass::Transparent, (3, 1), 20.0), "far glass"),
+            (SortKey::new(RenderPass::Opaque, (9, 9), 99.0), "wall"),
+        ];
+        assert_eq!(order(&keys), vec!["wall", "far glass", "near glass"]);
+        assert_eq!(keys[0].0.pass(), RenderPass::Transparent);
+        assert_eq!(keys[2].0.pass(), RenderPass::Opaque);
+    }
+}
@@ -0,0 +1,169 @@
+use std::hash::{Hash, Hasher};
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+    /// Colors already multiplied by their alpha.
+    Premultiplied,
+    Multiply,
+}
+
+impl BlendMode {
+    /// Source and destination factors, `None` when blending is disabled.
+    pub fn factors(self) -> Option<(gl::types::GLenum, gl::types::GLenum)> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some((gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Additive => Some((gl::SRC_ALPHA, gl::ONE)),
+            BlendMode::Premultiplied => Some((gl::ONE, gl::ONE_MINUS_SRC_ALPHA)),
+            BlendMode::Multiply => Some((gl::DST_COLOR, gl::ZERO)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+/// Fixed function states of a material.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderState {
+    pub blend: BlendMode,
+    /// Fragments with a lower alpha are discarded.
+    pub alpha_cutoff: Option<f32>,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub cull: CullMode,
+    /// Factor and units given to `glPolygonOffset`, e.g. to draw decals over a surface.
+    pub polygon_offset: Option<(f32, f32)>,
+}
+
+impl Default for RenderState {
+    fn default() -> Self {
+        Self::opaque()
+    }
+}
+
+impl RenderState {
+    pub fn opaque() -> Self {
+        Self {
+            blend: BlendMode::Opaque,
+            alpha_cutoff: None,
+            depth_test: true,
+            depth_write: true,
+            cull: CullMode::None,
+            polygon_offset: None,
+        }
+    }
+
+    /// Blended state, drawn after the opaque objects without writing depth.
+    pub fn transparent(blend: BlendMode) -> Self {
+        Self {
+            blend,
+            depth_write: false,
+            ..Self::opaque()
+        }
+    }
+
+    /// Opaque state discarding the fragments with an alpha below `threshold`.
+    pub fn cutout(threshold: f32) -> Self {
+        Self {
+            alpha_cutoff: Some(threshold),
+            ..Self::opaque()
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.blend != BlendMode::Opaque
+    }
+
+    /// Sets the GL states differing from `previous`, all of them when there is no previous state.
+    pub fn apply(&self, previous: Option<&RenderState>) {
+        unsafe {
+            if previous.is_none_or(|previous| previous.blend != self.blend) {
+                match self.blend.factors() {
+                    None => gl::Disable(gl::BLEND),
+                    Some((source, destination)) => {
+                        gl::Enable(gl::BLEND);
+                        gl::BlendFunc(source, destination);
+                    },
+                }
+            }
+            if previous.is_none_or(|previous| previous.depth_test != self.depth_test) {
+                if self.depth_test { gl::Enable(gl::DEPTH_TEST) } else { gl::Disable(gl::DEPTH_TEST) }
+            }
+            if previous.is_none_or(|previous| previous.depth_write != self.depth_write) {
+                gl::DepthMask(if self.depth_write { gl::TRUE } else { gl::FALSE });
+            }
+            if previous.is_none_or(|previous| previous.cull != self.cull) {
+                match self.cull {
+                    CullMode::None => gl::Disable(gl::CULL_FACE),
+                    CullMode::Back => {
+                        gl::Enable(gl::CULL_FACE);
+                        gl::CullFace(gl::BACK);
+                    },
+                    CullMode::Front => {
+                        gl::Enable(gl::CULL_FACE);
+                        gl::CullFace(gl::FRONT);
+                    },
+                }
+            }
+            if previous.is_none_or(|previous| previous.polygon_offset != self.polygon_offset) {
+                match self.polygon_offset {
+                    None => gl::Disable(gl::POLYGON_OFFSET_FILL),
+                    Some((factor, units)) => {
+                        gl::Enable(gl::POLYGON_OFFSET_FILL);
+                        gl::PolygonOffset(factor, units);
+                    },
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for RenderState {
+    fn eq(&self, other: &Self) -> bool {
+        self.blend == other.blend
+            && self.alpha_cutoff.map(f32::to_bits) == other.alpha_cutoff.map(f32::to_bits)
+            && self.depth_test == other.depth_test
+            && self.depth_write == other.depth_write
+            && self.cull == other.cull
+            && self.polygon_offset.map(|(factor, units)| (factor.to_bits(), units.to_bits())) == other.polygon_offset.map(|(factor, units)| (factor.to_bits(), units.to_bits()))
+    }
+}
+
+impl Eq for RenderState {}
+
+impl Hash for RenderState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.blend.hash(state);
+        self.alpha_cutoff.map(f32::to_bits).hash(state);
+        self.depth_test.hash(state);
+        self.depth_write.hash(state);
+        self.cull.hash(state);
+        self.polygon_offset.map(|(factor, units)| (factor.to_bits(), units.to_bits())).hash(state);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn presets() {
+        assert!(!RenderState::opaque().is_transparent());
+        assert!(!RenderState::cutout(0.5).is_transparent());
+        let glass = RenderState::transparent(BlendMode::Alpha);
+        assert!(glass.is_transparent() && !glass.depth_write);
+        let states: HashSet<RenderState> = vec![RenderState::cutout(0.5), RenderState::cutout(0.5), RenderState::cutout(0.2), glass].into_iter().collect();
+        assert_eq!(states.len(), 3);
+    }
+}
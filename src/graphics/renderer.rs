@@ -1,5 +1,23 @@
-use std::collections::HashMap;
-use crate::graphics::{Index, ComponentStorageManager, Mesh};
+use std::collections::{HashMap, HashSet};
+use cgmath::{Matrix4, Vector4, InnerSpace};
+use crate::graphics::{Index, ComponentStorageManager, Mesh, Material, Transform};
+use crate::graphics::shader::MaterialKey;
+use crate::graphics::render_queue::{RenderQueue, RenderPass, SortKey, FrameUniforms};
+use crate::graphics::render_state::{RenderState, BlendMode};
+use crate::graphics::debug_draw::{DebugRenderer, DebugView};
+use crate::graphics::shader::Shader;
+use crate::graphics::post_process::PostProcessStack;
+use crate::graphics::text::TextRenderer;
+use crate::graphics::ui::{Ui, UiRenderer};
+use crate::graphics::hud::HudRenderer;
+use crate::graphics::sprite::SpriteRenderer;
+use crate::graphics::fog::FogUniforms;
+use crate::graphics::light::{PointLight, PointLightData, LightUniforms};
+use crate::graphics::deferred::DeferredRenderer;
+use crate::graphics::ssao::{Ssao, AO_UNIT};
+use crate::graphics::environment::{Environment, IRRADIANCE_UNIT, PREFILTER_UNIT, PREFILTER_MIPS};
+use crate::graphics::serialization::MeshAsset;
+use crate::ecs::{Entity, Changed};
 
 
 
@@ -20,14 +38,353 @@ impl RenderObject {
     }
 }
 
+/// How the opaque materials are lit.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum RenderPath {
+    /// Each draw is shaded by the scene light and the `MAX_FORWARD_LIGHTS` point lights nearest to the camera.
+    #[default]
+    Forward,
+    /// Materials having a G-buffer shader are lit after being drawn, by every point light. Others stay forward.
+    Deferred,
+}
+
+/// Color multiplied with the vertex colors of the entity.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tint(pub Vector4<f32>);
+
+impl Default for Tint {
+    fn default() -> Self {
+        Tint(Vector4::new(1.0, 1.0, 1.0, 1.0))
+    }
+}
+
+/// Per-instance attributes, read by the shaders at locations 4 to 8.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct InstanceData {
+    model: [[f32; 4]; 4],
+    tint: [f32; 4],
+}
+
+impl InstanceData {
+    fn new(model: Matrix4<f32>, tint: Tint) -> Self {
+        Self {
+            model: model.into(),
+            tint: tint.0.into(),
+        }
+    }
+}
+
+struct InstanceGroup {
+    material: Material,
+    instances: Vec<InstanceData>,
+    depth: f32,
+}
+
+enum Draw {
+    Object { object: RenderObject, count: i32, model: Matrix4<f32>, tint: Tint },
+    Instances { batch: String, instances: Vec<InstanceData> },
+}
+
+struct RenderCommand {
+    material: Material,
+    draw: Draw,
+}
+
+/// Geometry of a mesh asset shared by all the entities using it, plus their instance buffer.
+struct InstanceBatch {
+    object: RenderObject,
+    instance_vbo: gl::types::GLuint,
+    vertex_count: i32,
+    index_count: i32,
+}
+
+impl InstanceBatch {
+    fn new(object: RenderObject, mesh: &Mesh) -> Self {
+        let mut batch = Self {
+            object,
+            instance_vbo: 0,
+            vertex_count: mesh.positions.len() as i32,
+            index_count: mesh.indices.len() as i32,
+        };
+        let stride = std::mem::size_of::<InstanceData>() as gl::types::GLint;
+        let vec4_size = std::mem::size_of::<[f32; 4]>();
+        unsafe {
+            gl::BindVertexArray(batch.object.vao);
+            gl::GenBuffers(1, &mut batch.instance_vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, batch.instance_vbo);
+            //Locations 4 to 7 hold the columns of the model matrix, 8 the tint
+            for column in 0..5 {
+                let location = 4 + column;
+                gl::EnableVertexAttribArray(location);
+                gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, stride, (column as usize * vec4_size) as *const gl::types::GLvoid);
+                gl::VertexAttribDivisor(location, 1);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+        batch
+    }
+
+    fn draw(&self, instances: &[InstanceData]) {
+        unsafe {
+            gl::BindVertexArray(self.object.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(instances) as gl::types::GLsizeiptr,
+                instances.as_ptr() as *const gl::types::GLvoid,
+                gl::STREAM_DRAW,
+            );
+            match self.object.ebo {
+                None => gl::DrawArraysInstanced(gl::TRIANGLES, 0, self.vertex_count, instances.len() as i32),
+                Some(_ebo) => gl::DrawElementsInstanced(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, std::ptr::null(), instances.len() as i32),
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+}
+
 pub struct RenderSystem {
     objects_to_render: HashMap<Index, RenderObject>,
+    batches: HashMap<String, InstanceBatch>,
+    /// Entities drawn with the batch of their mesh asset, a later change of their mesh detaches them from it.
+    instanced: HashSet<Index>,
+    queue: RenderQueue<RenderCommand>,
+    frame_uniforms: FrameUniforms,
+    fog_uniforms: FogUniforms,
+    light_uniforms: LightUniforms,
+    render_path: RenderPath,
+    deferred: Option<DeferredRenderer>,
+    ssao: Ssao,
+    debug_renderer: Option<DebugRenderer>,
+    text_renderer: Option<TextRenderer>,
+    ui_renderer: Option<UiRenderer>,
+    hud_renderer: Option<HudRenderer>,
+    sprite_renderer: Option<SpriteRenderer>,
+    post_process: PostProcessStack,
+    environment: Option<Environment>,
 }
 
 impl RenderSystem {
     pub fn new() -> Self {
         Self {
             objects_to_render: HashMap::new(),
+            batches: HashMap::new(),
+            instanced: HashSet::new(),
+            queue: RenderQueue::new(),
+            frame_uniforms: FrameUniforms::new(),
+            fog_uniforms: FogUniforms::new(),
+            light_uniforms: LightUniforms::new(),
+            render_path: RenderPath::Forward,
+            deferred: None,
+            ssao: Ssao::new(),
+            debug_renderer: None,
+            text_renderer: None,
+            ui_renderer: None,
+            hud_renderer: None,
+            sprite_renderer: None,
+            post_process: PostProcessStack::new(),
+            environment: None,
+        }
+    }
+
+    pub fn get_render_path(&self) -> RenderPath {
+        self.render_path
+    }
+
+    pub fn set_render_path(&mut self, render_path: RenderPath) {
+        self.render_path = render_path;
+    }
+
+    /// Whether the draw writes to the G-buffer, when there is a geometry pass.
+    fn in_geometry_pass(key: SortKey, material: &Material) -> bool {
+        key.pass() == RenderPass::Opaque && material.gbuffer_shader.is_some()
+    }
+
+    /// Whether the draw is done by the geometry pass of the deferred path instead of `execute_queue`.
+    fn is_deferred(&self, key: SortKey, material: &Material) -> bool {
+        self.render_path == RenderPath::Deferred && Self::in_geometry_pass(key, material)
+    }
+
+    pub fn get_mut_ssao(&mut self) -> &mut Ssao {
+        &mut self.ssao
+    }
+
+    /// Sky drawn behind the scene and used for the ambient lighting, `None` keeps the clear color and constant ambient.
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment;
+    }
+
+    pub fn get_mut_environment(&mut self) -> Option<&mut Environment> {
+        self.environment.as_mut()
+    }
+
+    /// Sets the uniforms of the ambient term, `occluded` surfaces also use the SSAO when it is enabled.
+    fn set_ambient_uniforms(&self, shader: &Shader, occluded: bool) {
+        shader.set_int("irradianceMap", IRRADIANCE_UNIT as i32);
+        shader.set_int("prefilterMap", PREFILTER_UNIT as i32);
+        shader.set_int("aoMap", AO_UNIT as i32);
+        shader.set_bool("useAmbientOcclusion", occluded && self.ssao.enabled);
+        match self.environment.as_ref().filter(|environment| environment.is_ready()) {
+            Some(environment) => {
+                shader.set_bool("useEnvironment", true);
+                shader.set_float("environmentIntensity", environment.intensity);
+                shader.set_float("maxReflectionLod", (PREFILTER_MIPS - 1) as f32);
+            },
+            None => shader.set_bool("useEnvironment", false),
+        }
+    }
+
+    /// Ends the UI of the frame and draws it over the window.
+    pub(crate) fn render_ui(&mut self, ui: &mut Ui) {
+        if let Some(output) = ui.end_frame() {
+            let primitives = ui.get_context().tessellate(output.shapes, output.pixels_per_point);
+            self.ui_renderer.get_or_insert_with(UiRenderer::new).paint(&output.textures_delta, &primitives, output.pixels_per_point);
+        }
+    }
+
+    pub fn get_mut_post_process(&mut self) -> &mut PostProcessStack {
+        &mut self.post_process
+    }
+
+    /// Runs the sorted draws, the shader, texture and render states are only changed when they differ from the previous draw.
+    fn execute_queue(&self) {
+        let mut bound_state: Option<RenderState> = None;
+        let mut bound_shader = None;
+        let mut bound_texture = None;
+        let mut sky_drawn = false;
+        if let Some(environment) = &self.environment {
+            environment.bind_maps();
+        }
+        for (key, command) in self.queue.iter() {
+            if self.is_deferred(*key, &command.material) {
+                continue;
+            }
+            //The sky fills the background between the opaque and the transparent draws
+            if key.pass() == RenderPass::Transparent && !sky_drawn {
+                sky_drawn = true;
+                self.draw_sky(&mut bound_state);
+                bound_shader = None;
+            }
+            let mut state = command.material.state;
+            //Opaque materials given a translucent tint are alpha blended
+            if key.pass() == RenderPass::Transparent && !state.is_transparent() {
+                state.blend = BlendMode::Alpha;
+                state.depth_write = false;
+            }
+            let state_changed = bound_state != Some(state);
+            if state_changed {
+                state.apply(bound_state.as_ref());
+                bound_state = Some(state);
+            }
+            let (shader, texture) = command.material.key();
+            let shader_changed = bound_shader != Some(shader);
+            if shader_changed {
+                bound_shader = Some(shader);
+                command.material.shader.use_program();
+                self.set_ambient_uniforms(&command.material.shader, key.pass() == RenderPass::Opaque);
+            }
+            if state_changed || shader_changed {
+                command.material.shader.set_float("alphaCutoff", state.alpha_cutoff.unwrap_or(0.0));
+            }
+            if bound_texture != Some(texture) {
+                bound_texture = Some(texture);
+                command.material.bind_texture();
+            }
+
+            self.submit(&command.material.shader, &command.draw);
+        }
+        if !sky_drawn {
+            self.draw_sky(&mut bound_state);
+        }
+        //Leave the default states for the next passes
+        RenderState::opaque().apply(bound_state.as_ref());
+    }
+
+    /// Draws the opaque surfaces having a G-buffer shader into the bound G-buffer.
+    fn execute_geometry_pass(&self) {
+        let mut bound_state: Option<RenderState> = None;
+        let mut bound_shader = None;
+        let mut bound_texture = None;
+        for (_, command) in self.queue.iter().filter(|(key, command)| Self::in_geometry_pass(*key, &command.material)) {
+            let state = command.material.state;
+            let state_changed = bound_state != Some(state);
+            if state_changed {
+                state.apply(bound_state.as_ref());
+                bound_state = Some(state);
+            }
+            let shader = command.material.gbuffer_shader.unwrap();
+            let shader_changed = bound_shader != Some(shader);
+            if shader_changed {
+                bound_shader = Some(shader);
+                shader.use_program();
+            }
+            if state_changed || shader_changed {
+                shader.set_float("alphaCutoff", state.alpha_cutoff.unwrap_or(0.0));
+            }
+            let (_, texture) = command.material.key();
+            if bound_texture != Some(texture) {
+                bound_texture = Some(texture);
+                command.material.bind_texture();
+            }
+            self.submit(&shader, &command.draw);
+        }
+        RenderState::opaque().apply(bound_state.as_ref());
+    }
+
+    fn draw_sky(&self, bound_state: &mut Option<RenderState>) {
+        if let Some(environment) = &self.environment {
+            RenderState::opaque().apply(bound_state.as_ref());
+            *bound_state = Some(RenderState::opaque());
+            environment.draw_skybox();
+        }
+    }
+
+    fn submit(&self, shader: &Shader, draw: &Draw) {
+        match draw {
+            Draw::Object { object, count, model, tint } => {
+                shader.set_bool("instanced", false);
+                shader.set_mat4("model", *model);
+                shader.set_vec4("tint", tint.0);
+                unsafe {
+                    gl::BindVertexArray(object.vao);
+                    match object.ebo {
+                        None => {gl::DrawArrays(gl::TRIANGLES, 0, *count)},
+                        Some(_ebo) => {gl::DrawElements(gl::TRIANGLES, *count, gl::UNSIGNED_INT,std::ptr::null())},
+                    }
+                }
+            },
+            Draw::Instances { batch, instances } => {
+                shader.set_bool("instanced", true);
+                self.batches[batch].draw(instances);
+            },
+        }
+    }
+
+    /// Draws the edges of every queued draw slightly in front of the shaded triangles.
+    fn draw_wireframe(&self, shader: Shader) {
+        unsafe {
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+            gl::Enable(gl::POLYGON_OFFSET_LINE);
+            gl::PolygonOffset(-1.0, -1.0);
+        }
+        for (_, command) in self.queue.iter() {
+            self.submit(&shader, &command.draw);
+        }
+        unsafe {
+            gl::Disable(gl::POLYGON_OFFSET_LINE);
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        }
+    }
+
+    /// Creates the batch of a mesh asset, or updates its geometry when the mesh changed.
+    /// Returns true if the dirty flag of the mesh has to be cleared.
+    /// Builds the shared geometry of a mesh asset from the first entity using it.
+    fn prepare_batch(&mut self, name: &str, mesh: &Mesh) {
+        if !self.batches.contains_key(name) {
+            let batch = InstanceBatch::new(Self::build_gl_object(mesh), mesh);
+            self.batches.insert(name.to_string(), batch);
         }
     }
 
@@ -36,6 +393,12 @@ impl RenderSystem {
         id: Index,
         mesh: &Mesh,
     ) -> RenderObject {
+        let gl_object = Self::build_gl_object(mesh);
+        self.objects_to_render.insert(id, gl_object);
+        gl_object
+    }
+
+    fn build_gl_object(mesh: &Mesh) -> RenderObject {
         println!("Creating new object to render ...");
         let mut gl_object = RenderObject::new();
         unsafe {
@@ -109,12 +472,15 @@ impl RenderSystem {
             gl::BindBuffer(gl::ARRAY_BUFFER, 0); // unbind the buffer
             gl::BindVertexArray(0);
         }
-        self.objects_to_render.insert(id, gl_object);
-        return gl_object;
+        gl_object
     }
 
     fn update_gl_object(&mut self, id: &Index, mesh: &Mesh) {
         let gl_object = self.objects_to_render.get_mut(id).expect("Trying to update a gl_object that doesn't exist!");
+        Self::upload_gl_object(gl_object, mesh);
+    }
+
+    fn upload_gl_object(gl_object: &mut RenderObject, mesh: &Mesh) {
         unsafe {
             gl::BindVertexArray(gl_object.vao);
 
@@ -149,52 +515,151 @@ impl RenderSystem {
         }
     }
 
+    pub fn remove_object(&mut self, id: &Index) {
+        self.instanced.remove(id);
+        if let Some(mut gl_object) = self.objects_to_render.remove(id) {
+            unsafe {
+                gl::DeleteVertexArrays(1, &gl_object.vao);
+                gl::DeleteBuffers(1, &gl_object.vbo);
+                if let Some(ebo) = gl_object.ebo.take() {
+                    gl::DeleteBuffers(1, &ebo);
+                }
+            }
+        }
+    }
 
     pub fn render(&mut self, storage: &mut ComponentStorageManager) {
-        for (_, trans) in storage.transform_manager.iter_mut() {
+        for trans in storage.query_filtered::<&mut Transform, Changed<Transform>>().iter() {
             trans.update_local_transform();
         }
 
+        if let Some(environment) = &mut self.environment {
+            environment.prepare();
+        }
+        self.post_process.begin();
         unsafe { gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT) };
 
-        for (id, mesh) in storage.mesh_manager.iter_mut() {
-            let material = storage.material_manager.get(id).expect("No material found!"); //storage.get_material(*id).unwrap();
-            let transform = storage.transform_manager.get(id).expect("No transform found!"); //storage.get_transform(*id).unwrap();
+        let camera = storage.camera;
+        self.frame_uniforms.upload(camera.lookat(), camera.projection(), camera.position, storage.light.position, storage.light.color);
+        self.fog_uniforms.upload(storage.fog.as_ref());
+        let point_lights: Vec<PointLightData> = storage.query_read::<(&PointLight, &Transform)>().iter()
+            .map(|(light, transform)| PointLightData::new(light, transform.translation))
+            .collect();
+        self.light_uniforms.upload(&point_lights, camera.position);
+
+        self.queue.clear();
+        let mut groups: HashMap<(&str, MaterialKey, RenderState), InstanceGroup> = HashMap::new();
+        //Meshes are borrowed mutably only to clear the dirty flag of the uploaded ones, to keep `Changed<Mesh>` meaningful
+        let mut uploaded = Vec::new();
+        let mut detached = Vec::new();
+        let mut query = storage.query_read::<(Entity, &Mesh, &Material, &Transform, Option<&MeshAsset>, Option<&Tint>)>();
+        for (id, mesh, material, transform, asset, tint) in query.iter() {
+            let tint = tint.copied().unwrap_or_default();
+            let pass = if material.state.is_transparent() || tint.0.w < 1.0 { RenderPass::Transparent } else { RenderPass::Opaque };
+            let depth = (transform.translation - camera.position).magnitude();
+            //Opaque entities sharing a mesh asset are drawn with instancing, transparent ones are sorted one by one.
+            //An entity whose mesh changed after it was drawn as an instance no longer matches the asset and is drawn on its own
+            if let (Some(asset), RenderPass::Opaque) = (asset, pass) {
+                if mesh.is_dirty() && self.instanced.contains(&id) {
+                    detached.push(id);
+                } else {
+                    self.prepare_batch(&asset.0, mesh);
+                    if mesh.is_dirty() {
+                        uploaded.push(id);
+                    }
+                    self.instanced.insert(id);
+                    let group = groups.entry((asset.0.as_str(), material.key(), material.state))
+                        .or_insert_with(|| InstanceGroup { material: *material, instances: Vec::new(), depth });
+                    group.instances.push(InstanceData::new(transform.local_transform, tint));
+                    group.depth = group.depth.min(depth);
+                    continue;
+                }
+            }
+
             let gl_object = match self.objects_to_render.get(&id) {
-                None => { 
-                    mesh.dirty = false;
-                    self.create_object_to_render(*id, mesh)
-                    },
-                Some(object) => *object,
+                None => {
+                    if mesh.is_dirty() {
+                        uploaded.push(id);
+                    }
+                    self.create_object_to_render(id, mesh)
+                },
+                Some(object) => {
+                    let object = *object;
+                    //Check mesh dirty flag
+                    if mesh.is_dirty() {
+                        self.update_gl_object(&id, mesh);
+                        uploaded.push(id);
+                    }
+                    object
+                },
             };
-            //Check mesh dirty flag
-            if mesh.is_dirty() {
-                self.update_gl_object(id, mesh);
+            let count = match gl_object.ebo {
+                None => mesh.positions.len(),
+                Some(_ebo) => mesh.indices.len(),
+            } as i32;
+            self.queue.push(SortKey::new(pass, material.key(), depth), RenderCommand {
+                material: *material,
+                draw: Draw::Object { object: gl_object, count, model: transform.local_transform, tint },
+            });
+        }
+        for ((name, material_key, _), group) in groups {
+            self.queue.push(SortKey::new(RenderPass::Opaque, material_key, group.depth), RenderCommand {
+                material: group.material,
+                draw: Draw::Instances { batch: name.to_string(), instances: group.instances },
+            });
+        }
+        for id in uploaded {
+            if let Some(mesh) = storage.get_mut::<Mesh>(id) {
                 mesh.dirty = false;
-           }
-            
-            //Compute MVP matrix
-            let view_mat = storage.camera.lookat();
-            //let view_mat = cgmath::Matrix4::from_translation(cgmath::Vector3::new(-1.0, 0.0, -3.0));
-            let projection_mat: cgmath::Matrix4<f32> = cgmath::perspective(cgmath::Deg(45.0), 1024.0/768.0, 0.1, 100.0);
-            let model_mat = transform.local_transform;
-
-            material.bind();
-            material.shader.set_mat4("model", model_mat);
-            material.shader.set_mat4("view", view_mat);
-            material.shader.set_mat4("projection", projection_mat);
-            material.shader.set_vec3("lightColor", storage.light.color);
-            material.shader.set_vec3("lightPos", storage.light.position);
-            material.shader.set_vec3("viewPos", storage.camera.position);
+            }
+        }
+        for id in detached {
+            self.instanced.remove(&id);
+            storage.remove::<MeshAsset>(id);
+        }
 
-            unsafe {
-                gl::BindVertexArray(gl_object.vao);
-                match gl_object.ebo {
-                    None => {gl::DrawArrays(gl::TRIANGLES, 0, mesh.positions.len() as i32)},
-                    Some(_ebo) => {gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, gl::UNSIGNED_INT,std::ptr::null())},
+        self.queue.sort();
+        //The SSAO reads the G-buffer, filled by the forward path only for it
+        if self.render_path == RenderPath::Deferred || self.ssao.enabled {
+            if self.deferred.is_none() {
+                self.deferred = Some(DeferredRenderer::new());
+            }
+            self.deferred.as_mut().unwrap().begin_geometry();
+            self.execute_geometry_pass();
+            let deferred = self.deferred.as_ref().unwrap();
+            if self.ssao.enabled {
+                if let Some((normal, depth, width, height)) = deferred.normal_and_depth() {
+                    self.ssao.render(normal, depth, width, height, camera.projection());
+                }
+            }
+            deferred.end_geometry();
+            if self.render_path == RenderPath::Deferred {
+                if let Some(environment) = &self.environment {
+                    environment.bind_maps();
                 }
+                deferred.light(&point_lights, camera.projection() * camera.lookat(), |shader| self.set_ambient_uniforms(shader, true));
             }
         }
+        self.execute_queue();
+        self.sprite_renderer.get_or_insert_with(SpriteRenderer::new).draw(storage);
+
+        if self.debug_renderer.is_none() {
+            self.debug_renderer = Some(DebugRenderer::new());
+        }
+        let debug_renderer = self.debug_renderer.as_ref().unwrap();
+        if storage.debug_draw.is_view_enabled(DebugView::Wireframe) {
+            self.draw_wireframe(debug_renderer.wireframe_shader());
+        }
+        storage.debug_draw.collect_views(&storage.world, &storage.light, &storage.camera);
+        debug_renderer.draw_lines(&storage.debug_draw, &camera);
+        if self.text_renderer.is_none() {
+            self.text_renderer = Some(TextRenderer::new());
+        }
+        let text_renderer = self.text_renderer.as_mut().unwrap();
+        text_renderer.draw_world(&storage.text, &camera);
+        self.post_process.finish();
+        self.hud_renderer.get_or_insert_with(HudRenderer::new).draw(&mut storage.hud);
+        text_renderer.draw_screen(&storage.text, &camera);
         unsafe {
             match gl::GetError(){
                 gl::NO_ERROR => (),
@@ -1,5 +1,5 @@
 use math::Mat4;
-use crate::graphics::{Index, ComponentStorageManager};
+use crate::graphics::{Index, ComponentStorageManager, Transform};
 use std::collections::HashMap;
 
 
@@ -9,7 +9,7 @@ struct SceneManager {
 
 impl SceneManager {
     pub fn update_transforms(&mut self, storage: &ComponentStorageManager) {
-        for (id, trans) in storage.transform_manager.iter() {
+        for (id, trans) in storage.storage::<Transform>().into_iter().flat_map(|transforms| transforms.iter()) {
             //self.transforms.insert(*id, trans.calculate_local_transform());
         }
     }
@@ -0,0 +1,353 @@
+extern crate rayon;
+
+use crate::graphics::ComponentStorageManager;
+use crate::graphics::inputs::InputSystem;
+use crate::graphics::commands::Commands;
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+    Late,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::Render, Stage::Late];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+pub struct SystemData<'a> {
+    pub storage: &'a mut ComponentStorageManager,
+    pub input: &'a InputSystem,
+    pub commands: &'a mut Commands,
+    pub delta: f32,
+}
+
+pub struct ReadOnlySystemData<'a> {
+    pub storage: &'a ComponentStorageManager,
+    pub input: &'a InputSystem,
+    pub delta: f32,
+}
+
+pub trait System: Send {
+    fn run(&mut self, data: SystemData);
+}
+
+/// System that only reads the world, it can run in parallel with the other read-only systems of its stage.
+pub trait ReadOnlySystem: Send {
+    fn run(&mut self, data: ReadOnlySystemData);
+}
+
+impl<F> System for F where F: FnMut(SystemData) + Send {
+    fn run(&mut self, data: SystemData) {
+        self(data)
+    }
+}
+
+impl<F> ReadOnlySystem for F where F: FnMut(ReadOnlySystemData) + Send {
+    fn run(&mut self, data: ReadOnlySystemData) {
+        self(data)
+    }
+}
+
+/// Systems owned by the engine itself, they are scheduled like user systems so these can be ordered around them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EngineSystem {
+    States,
+    Animation,
+    Render,
+    Debug,
+}
+
+impl EngineSystem {
+    pub fn label(self) -> &'static str {
+        match self {
+            EngineSystem::States => "states",
+            EngineSystem::Animation => "animation",
+            EngineSystem::Render => "render",
+            EngineSystem::Debug => "debug",
+        }
+    }
+}
+
+enum SystemKind {
+    Engine(EngineSystem),
+    Exclusive(Box<dyn System>),
+    ReadOnly(Box<dyn ReadOnlySystem>),
+}
+
+pub struct SystemDescriptor {
+    label: String,
+    kind: SystemKind,
+    before: Vec<String>,
+    after: Vec<String>,
+}
+
+impl SystemDescriptor {
+    fn new(label: &str, kind: SystemKind) -> Self {
+        Self {
+            label: label.to_string(),
+            kind,
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Runs this system before the systems labelled `label` in the same stage.
+    pub fn before(&mut self, label: &str) -> &mut Self {
+        self.before.push(label.to_string());
+        self
+    }
+
+    /// Runs this system after the systems labelled `label` in the same stage.
+    pub fn after(&mut self, label: &str) -> &mut Self {
+        self.after.push(label.to_string());
+        self
+    }
+
+    fn runs_before(&self, other: &SystemDescriptor) -> bool {
+        self.before.contains(&other.label) || other.after.contains(&self.label)
+    }
+
+    fn is_read_only(&self) -> bool {
+        matches!(self.kind, SystemKind::ReadOnly(_))
+    }
+}
+
+/// One step of a stage: either an engine system or a batch of user systems that may run together.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Engine(EngineSystem),
+    Systems(Vec<usize>),
+}
+
+#[derive(Default)]
+struct StageSystems {
+    systems: Vec<SystemDescriptor>,
+    plan: Option<Vec<Step>>,
+}
+
+pub struct Schedule {
+    stages: Vec<StageSystems>,
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        let mut schedule = Self::empty();
+        schedule.add(Stage::Update, SystemDescriptor::new(EngineSystem::States.label(), SystemKind::Engine(EngineSystem::States)));
+        schedule.add(Stage::Render, SystemDescriptor::new(EngineSystem::Animation.label(), SystemKind::Engine(EngineSystem::Animation))).before(EngineSystem::Render.label());
+        schedule.add(Stage::Render, SystemDescriptor::new(EngineSystem::Render.label(), SystemKind::Engine(EngineSystem::Render)));
+        schedule.add(Stage::Late, SystemDescriptor::new(EngineSystem::Debug.label(), SystemKind::Engine(EngineSystem::Debug)));
+        schedule
+    }
+
+    fn empty() -> Self {
+        Self {
+            stages: Stage::ALL.iter().map(|_| StageSystems::default()).collect(),
+            pool: None,
+        }
+    }
+
+    fn add(&mut self, stage: Stage, descriptor: SystemDescriptor) -> &mut SystemDescriptor {
+        let stage = &mut self.stages[stage.index()];
+        stage.plan = None;
+        stage.systems.push(descriptor);
+        stage.systems.last_mut().unwrap()
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, stage: Stage, label: &str, system: S) -> &mut SystemDescriptor {
+        self.add(stage, SystemDescriptor::new(label, SystemKind::Exclusive(Box::new(system))))
+    }
+
+    pub fn add_read_only_system<S: ReadOnlySystem + 'static>(&mut self, stage: Stage, label: &str, system: S) -> &mut SystemDescriptor {
+        self.add(stage, SystemDescriptor::new(label, SystemKind::ReadOnly(Box::new(system))))
+    }
+
+    /// Runs batches of read-only systems on a pool of `threads` threads, 0 or 1 runs everything on the main thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.pool = if threads > 1 {
+            Some(rayon::ThreadPoolBuilder::new().num_threads(threads).build().expect("Unable to create the system thread pool"))
+        } else {
+            None
+        };
+    }
+
+    /// Ordered steps of the stage, recomputed only when systems were added.
+    pub fn plan(&mut self, stage: Stage) -> Vec<Step> {
+        let stage_systems = &mut self.stages[stage.index()];
+        if stage_systems.plan.is_none() {
+            let order = Self::sort(&stage_systems.systems)
+                .unwrap_or_else(|| panic!("Cycle in the system ordering constraints of stage {:?}", stage));
+            stage_systems.plan = Some(Self::batch(&stage_systems.systems, &order));
+        }
+        stage_systems.plan.clone().unwrap()
+    }
+
+    /// Topological sort keeping the registration order when there is no constraint.
+    fn sort(systems: &[SystemDescriptor]) -> Option<Vec<usize>> {
+        let count = systems.len();
+        let mut incoming = vec![0; count];
+        for (i, a) in systems.iter().enumerate() {
+            for (j, b) in systems.iter().enumerate() {
+                if i != j && a.runs_before(b) {
+                    incoming[j] += 1;
+                }
+            }
+        }
+        let mut done = vec![false; count];
+        let mut order = Vec::with_capacity(count);
+        while order.len() < count {
+            let next = (0..count).find(|i| !done[*i] && incoming[*i] == 0)?;
+            done[next] = true;
+            order.push(next);
+            for (j, other) in systems.iter().enumerate() {
+                if j != next && systems[next].runs_before(other) {
+                    incoming[j] -= 1;
+                }
+            }
+        }
+        Some(order)
+    }
+
+    fn batch(systems: &[SystemDescriptor], order: &[usize]) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let mut batch: Vec<usize> = Vec::new();
+        for index in order {
+            let system = &systems[*index];
+            let independent = batch.iter().all(|other| !systems[*other].runs_before(system) && !system.runs_before(&systems[*other]));
+            if !batch.is_empty() && (!system.is_read_only() || !independent) {
+                steps.push(Step::Systems(std::mem::take(&mut batch)));
+            }
+            match system.kind {
+                SystemKind::Engine(engine_system) => steps.push(Step::Engine(engine_system)),
+                SystemKind::Exclusive(_) => steps.push(Step::Systems(vec![*index])),
+                SystemKind::ReadOnly(_) => batch.push(*index),
+            }
+        }
+        if !batch.is_empty() {
+            steps.push(Step::Systems(batch));
+        }
+        steps
+    }
+
+    pub fn run_systems(&mut self, stage: Stage, indices: &[usize], storage: &mut ComponentStorageManager, input: &InputSystem, commands: &mut Commands, delta: f32) {
+        let systems = &mut self.stages[stage.index()].systems;
+        if indices.len() == 1 {
+            match &mut systems[indices[0]].kind {
+                SystemKind::Exclusive(system) => system.run(SystemData { storage, input, commands, delta }),
+                SystemKind::ReadOnly(system) => system.run(ReadOnlySystemData { storage, input, delta }),
+                SystemKind::Engine(_) => (),
+            }
+            return;
+        }
+
+        let storage: &ComponentStorageManager = storage;
+        let batch: Vec<&mut Box<dyn ReadOnlySystem>> = systems.iter_mut()
+            .enumerate()
+            .filter(|(i, _)| indices.contains(i))
+            .filter_map(|(_, descriptor)| match &mut descriptor.kind {
+                SystemKind::ReadOnly(system) => Some(system),
+                _ => None,
+            })
+            .collect();
+        match &self.pool {
+            Some(pool) => pool.scope(|scope| {
+                for system in batch {
+                    scope.spawn(move |_| system.run(ReadOnlySystemData { storage, input, delta }));
+                }
+            }),
+            None => {
+                for system in batch {
+                    system.run(ReadOnlySystemData { storage, input, delta });
+                }
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn labels(schedule: &mut Schedule, stage: Stage) -> Vec<String> {
+        let mut labels = Vec::new();
+        for step in schedule.plan(stage) {
+            match step {
+                Step::Engine(engine_system) => labels.push(engine_system.label().to_string()),
+                Step::Systems(indices) => {
+                    let names: Vec<&str> = indices.iter().map(|i| schedule.stages[stage.index()].systems[*i].label()).collect();
+                    labels.push(names.join("+"));
+                },
+            }
+        }
+        labels
+    }
+
+    #[test]
+    fn ordering_constraints() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, "c", |_: SystemData| ());
+        schedule.add_system(Stage::Update, "a", |_: SystemData| ()).before("states");
+        schedule.add_system(Stage::Update, "b", |_: SystemData| ()).after("c").before("a");
+        assert_eq!(labels(&mut schedule, Stage::Update), vec!["c", "b", "a", "states"]);
+    }
+
+    #[test]
+    fn independent_read_only_systems_are_batched() {
+        let mut schedule = Schedule::empty();
+        schedule.add_read_only_system(Stage::PostUpdate, "a", |_: ReadOnlySystemData| ());
+        schedule.add_read_only_system(Stage::PostUpdate, "b", |_: ReadOnlySystemData| ());
+        schedule.add_read_only_system(Stage::PostUpdate, "c", |_: ReadOnlySystemData| ()).after("b");
+        schedule.add_system(Stage::PostUpdate, "d", |_: SystemData| ());
+        schedule.add_read_only_system(Stage::PostUpdate, "e", |_: ReadOnlySystemData| ());
+        assert_eq!(labels(&mut schedule, Stage::PostUpdate), vec!["a+b", "c", "d", "e"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn cycle_panics() {
+        let mut schedule = Schedule::empty();
+        schedule.add_system(Stage::Update, "a", |_: SystemData| ()).after("b");
+        schedule.add_system(Stage::Update, "b", |_: SystemData| ()).after("a");
+        schedule.plan(Stage::Update);
+    }
+
+    #[test]
+    fn runs_batch_on_thread_pool() {
+        let mut schedule = Schedule::empty();
+        schedule.set_threads(2);
+        let counter = Arc::new(Mutex::new(0));
+        for label in ["a", "b", "c"].iter() {
+            let counter = counter.clone();
+            schedule.add_read_only_system(Stage::Update, label, move |_: ReadOnlySystemData| *counter.lock().unwrap() += 1);
+        }
+        let mut storage = ComponentStorageManager::new();
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        for step in schedule.plan(Stage::Update) {
+            if let Step::Systems(indices) = step {
+                schedule.run_systems(Stage::Update, &indices, &mut storage, &input, &mut commands, 0.0);
+            }
+        }
+        assert_eq!(*counter.lock().unwrap(), 3);
+    }
+}
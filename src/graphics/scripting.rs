@@ -0,0 +1,322 @@
+extern crate rhai;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+use rhai::{CallFnOptions, Dynamic, FuncArgs, Map, Scope, AST};
+use crate::graphics::transform::{Transform, Vector3, Deg};
+use crate::graphics::camera::Camera;
+use crate::graphics::light::Light;
+use crate::graphics::inputs::{InputSystem, MouseButton, ButtonState};
+use crate::graphics::states::{EntityState, GameData};
+
+
+/// Seconds between two checks of the script file modification time.
+const RELOAD_INTERVAL: f32 = 0.5;
+
+/// Snapshot of the `InputSystem` given to scripts.
+#[derive(Debug, Clone)]
+pub struct ScriptInput {
+    pressed_keys: Vec<String>,
+    mouse_left: bool,
+    mouse_right: bool,
+    mouse_middle: bool,
+    mouse_x_delta: f32,
+    mouse_y_delta: f32,
+}
+
+impl ScriptInput {
+    pub fn new(input: &InputSystem) -> Self {
+        let (mouse_x_delta, mouse_y_delta) = input.get_mouse_motion();
+        Self {
+            pressed_keys: input.pressed_keys().map(|key| format!("{:?}", key)).collect(),
+            mouse_left: input.is_mouse_button_pressed(MouseButton::LEFT) == ButtonState::PRESSED,
+            mouse_right: input.is_mouse_button_pressed(MouseButton::RIGHT) == ButtonState::PRESSED,
+            mouse_middle: input.is_mouse_button_pressed(MouseButton::MIDDLE) == ButtonState::PRESSED,
+            mouse_x_delta: mouse_x_delta as f32,
+            mouse_y_delta: mouse_y_delta as f32,
+        }
+    }
+
+    /// `key` is the name of the `Key` variant, e.g. "Z" or "Space".
+    pub fn is_key_pressed(&self, key: &str) -> bool {
+        self.pressed_keys.iter().any(|pressed| pressed == key)
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: &str) -> bool {
+        match button {
+            "left" => self.mouse_left,
+            "right" => self.mouse_right,
+            "middle" => self.mouse_middle,
+            _ => false,
+        }
+    }
+}
+
+/// The `this` of the script callbacks, copied back to the world once the callback returns.
+#[derive(Debug, Clone)]
+pub struct ScriptContext {
+    transform: Option<Transform>,
+    camera: Camera,
+    light: Light,
+    input: ScriptInput,
+    vars: Map,
+}
+
+/// Rhai engine with the engine types registered, shared by all the scripts.
+#[derive(Clone)]
+pub struct ScriptEngine {
+    engine: Rc<rhai::Engine>,
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        let mut engine = rhai::Engine::new();
+        Self::register_api(&mut engine);
+        Self {
+            engine: Rc::new(engine),
+        }
+    }
+
+    /// Loads a script reloaded whenever its file changes.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<ScriptState, String> {
+        let path = path.as_ref().to_path_buf();
+        let ast = self.engine.compile_file(path.clone()).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let mut script = ScriptState::new(self.engine.clone(), ast);
+        script.modified = Self::modified(&path);
+        script.path = Some(path);
+        Ok(script)
+    }
+
+    pub fn compile(&self, source: &str) -> Result<ScriptState, String> {
+        let ast = self.engine.compile(source).map_err(|err| err.to_string())?;
+        Ok(ScriptState::new(self.engine.clone(), ast))
+    }
+
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn register_api(engine: &mut rhai::Engine) {
+        engine.register_type_with_name::<Vector3<f32>>("Vec3")
+            .register_fn("vec3", |x: f32, y: f32, z: f32| Vector3::new(x, y, z))
+            .register_get_set("x", |v: &mut Vector3<f32>| v.x, |v: &mut Vector3<f32>, x: f32| v.x = x)
+            .register_get_set("y", |v: &mut Vector3<f32>| v.y, |v: &mut Vector3<f32>, y: f32| v.y = y)
+            .register_get_set("z", |v: &mut Vector3<f32>| v.z, |v: &mut Vector3<f32>, z: f32| v.z = z)
+            .register_fn("+", |a: Vector3<f32>, b: Vector3<f32>| a + b)
+            .register_fn("-", |a: Vector3<f32>, b: Vector3<f32>| a - b)
+            .register_fn("*", |a: Vector3<f32>, k: f32| a * k)
+            .register_fn("to_string", |v: &mut Vector3<f32>| format!("({}, {}, {})", v.x, v.y, v.z))
+            .register_fn("to_debug", |v: &mut Vector3<f32>| format!("{:?}", v));
+
+        engine.register_type_with_name::<Transform>("Transform")
+            .register_get_set("translation", |t: &mut Transform| t.translation, |t: &mut Transform, v: Vector3<f32>| t.translation = v)
+            .register_get_set("scale", |t: &mut Transform| t.scale, |t: &mut Transform, v: Vector3<f32>| t.scale = v)
+            .register_get_set("euler_angles", |t: &mut Transform| t.euler_angles(), |t: &mut Transform, v: Vector3<f32>| t.set_euler_angles(v))
+            .register_fn("rotate_around", |t: &mut Transform, axis: Vector3<f32>, degrees: f32| t.rotate_around(axis, Deg(degrees)))
+            .register_fn("look_at", |t: &mut Transform, target: Vector3<f32>, up: Vector3<f32>| t.look_at(target, up))
+            .register_fn("forward", |t: &mut Transform| t.forward())
+            .register_fn("right", |t: &mut Transform| t.right())
+            .register_fn("up", |t: &mut Transform| t.up());
+
+        engine.register_type_with_name::<Camera>("Camera")
+            .register_get_set("position", |c: &mut Camera| c.position, |c: &mut Camera, v: Vector3<f32>| c.position = v)
+            .register_get_set("direction", |c: &mut Camera| c.direction, |c: &mut Camera, v: Vector3<f32>| c.direction = v);
+
+        engine.register_type_with_name::<Light>("Light")
+            .register_get_set("position", |l: &mut Light| l.position, |l: &mut Light, v: Vector3<f32>| l.position = v)
+            .register_get_set("color", |l: &mut Light| l.color, |l: &mut Light, v: Vector3<f32>| l.color = v)
+            .register_fn("rotate", |l: &mut Light, center: Vector3<f32>, angles: Vector3<f32>| l.rotate(center, angles));
+
+        engine.register_type_with_name::<ScriptInput>("Input")
+            .register_fn("is_key_pressed", |i: &mut ScriptInput, key: &str| i.is_key_pressed(key))
+            .register_fn("is_mouse_button_pressed", |i: &mut ScriptInput, button: &str| i.is_mouse_button_pressed(button))
+            .register_get("mouse_x_delta", |i: &mut ScriptInput| i.mouse_x_delta)
+            .register_get("mouse_y_delta", |i: &mut ScriptInput| i.mouse_y_delta);
+
+        engine.register_type_with_name::<ScriptContext>("Context")
+            .register_get("transform", |c: &mut ScriptContext| c.transform.map_or(Dynamic::UNIT, Dynamic::from))
+            .register_set("transform", |c: &mut ScriptContext, t: Transform| if c.transform.is_some() {
+                c.transform = Some(t);
+            })
+            .register_get_set("camera", |c: &mut ScriptContext| c.camera, |c: &mut ScriptContext, camera: Camera| c.camera = camera)
+            .register_get_set("light", |c: &mut ScriptContext| c.light, |c: &mut ScriptContext, light: Light| c.light = light)
+            .register_get("input", |c: &mut ScriptContext| c.input.clone())
+            .register_get_set("vars", |c: &mut ScriptContext| c.vars.clone(), |c: &mut ScriptContext, vars: Map| c.vars = vars);
+    }
+}
+
+/// Entity behaviour written in Rhai. The script defines any of `on_create()`, `on_update(delta)` and `on_delete()`,
+/// they access the entity through `this.transform`, `this.camera`, `this.light`, `this.input` and keep their own state in `this.vars`.
+pub struct ScriptState {
+    engine: Rc<rhai::Engine>,
+    ast: AST,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    vars: Map,
+    since_reload_check: f32,
+}
+
+impl ScriptState {
+    fn new(engine: Rc<rhai::Engine>, ast: AST) -> Self {
+        Self {
+            engine,
+            ast,
+            path: None,
+            modified: None,
+            vars: Map::new(),
+            since_reload_check: 0.0,
+        }
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Recompiles the script if its file was modified, the previous version is kept when the new one doesn't compile.
+    pub fn reload_if_changed(&mut self) -> bool {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return false,
+        };
+        let modified = ScriptEngine::modified(path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        match self.engine.compile_file(path.clone()) {
+            Ok(ast) => {
+                println!("Script reloaded: {}", path.display());
+                self.ast = ast;
+                true
+            },
+            Err(err) => {
+                println!("Script {} not reloaded: {}", path.display(), err);
+                false
+            },
+        }
+    }
+
+    fn has_function(&self, name: &str, arity: usize) -> bool {
+        self.ast.iter_functions().any(|function| function.name == name && function.params.len() == arity)
+    }
+
+    fn call(&mut self, name: &str, data: GameData, args: impl FuncArgs, arity: usize) {
+        if !self.has_function(name, arity) {
+            return;
+        }
+        let GameData { id, storage, input, .. } = data;
+        let context = ScriptContext {
+            transform: storage.get::<Transform>(id).copied(),
+            camera: *storage.get_camera(),
+            light: *storage.get_light(),
+            input: ScriptInput::new(input),
+            vars: std::mem::take(&mut self.vars),
+        };
+        let mut this = Dynamic::from(context);
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut this);
+        if let Err(err) = self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &self.ast, name, args) {
+            println!("Script error in {}: {}", name, err);
+        }
+
+        let context = match this.try_cast::<ScriptContext>() {
+            Some(context) => context,
+            None => {
+                println!("Script error in {}: `this` was replaced", name);
+                return;
+            },
+        };
+        self.vars = context.vars;
+        if let Some(transform) = context.transform {
+            if storage.get::<Transform>(id) != Some(&transform) {
+                if let Ok(current) = storage.get_mut_transform(id) {
+                    *current = transform;
+                }
+            }
+        }
+        if *storage.get_camera() != context.camera {
+            *storage.get_mut_camera() = context.camera;
+        }
+        *storage.get_mut_light() = context.light;
+    }
+}
+
+impl EntityState for ScriptState {
+    fn on_create(&mut self, data: GameData) {
+        self.call("on_create", data, (), 0);
+    }
+
+    fn on_update(&mut self, data: GameData, delta: f32) {
+        self.since_reload_check += delta;
+        if self.since_reload_check >= RELOAD_INTERVAL {
+            self.since_reload_check = 0.0;
+            self.reload_if_changed();
+        }
+        self.call("on_update", data, (delta,), 1);
+    }
+
+    fn on_delete(&mut self, data: GameData) {
+        self.call("on_delete", data, (), 0);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::ComponentStorageManager;
+    use crate::graphics::commands::Commands;
+    use crate::graphics::states::StateSystem;
+
+    #[test]
+    fn script_moves_entity_and_camera() {
+        let scripts = ScriptEngine::new();
+        let script = scripts.compile("
+            fn on_create() { this.vars.speed = 2.0; }
+            fn on_update(delta) {
+                this.transform.translation.x += this.vars.speed * delta;
+                this.camera.position = vec3(1.0, 2.0, 3.0);
+                if this.input.is_key_pressed(\"Z\") { this.light.color = vec3(0.0, 0.0, 0.0); }
+            }
+        ").unwrap();
+        let mut storage = ComponentStorageManager::new();
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        let mut states = StateSystem::new();
+        let id = storage.spawn();
+        storage.insert(id, Transform::new_default());
+        states.add_entity_states(id, script);
+        states.run_update_state(&mut storage, &input, &mut commands, 0.5);
+        states.run_update_state(&mut storage, &input, &mut commands, 0.5);
+        assert_eq!(storage.get_transform(id).unwrap().translation.x, 2.0);
+        assert_eq!(storage.get_camera().position, Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(storage.get_light().color, Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn script_is_reloaded_when_file_changes() {
+        let path = std::env::temp_dir().join(format!("neutrino_reload_{}.rhai", std::process::id()));
+        fs::write(&path, "fn on_update(delta) { this.transform.translation.y = 1.0; }").unwrap();
+        let mut script = ScriptEngine::new().load(&path).unwrap();
+        assert!(!script.reload_if_changed());
+
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&path, "fn on_update(delta) { this.transform.translation.y = 5.0; }").unwrap();
+        assert!(script.reload_if_changed());
+        let mut storage = ComponentStorageManager::new();
+        let id = storage.spawn();
+        storage.insert(id, Transform::new_default());
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        script.on_update(GameData { id, storage: &mut storage, input: &input, commands: &mut commands }, 0.0);
+        assert_eq!(storage.get_transform(id).unwrap().translation.y, 5.0);
+        fs::remove_file(&path).unwrap();
+    }
+}
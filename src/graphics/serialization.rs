@@ -0,0 +1,751 @@
+extern crate ron;
+extern crate serde_json;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::ecs::{Component, World};
+use crate::graphics::{ComponentStorageManager, Index};
+use crate::graphics::mesh::{Mesh, Vertex, Color, UV};
+use crate::graphics::shader::{Material, MaterialBuilder, Texture};
+use crate::graphics::sprite::{Region, Sprite};
+use crate::graphics::tilemap::{Tile, Tilemap};
+use crate::graphics::transform::{Transform, Vector3, Quaternion};
+use crate::graphics::camera::Camera;
+use crate::graphics::light::{Light, PointLight};
+use crate::graphics::fog::Fog;
+use crate::graphics::primitives::PrimitiveBuilder;
+use crate::graphics::events::EntityEvent;
+use crate::graphics::prefab::{Overrides, PrefabInstance, PrefabLibrary};
+
+
+/// Version written in new scene files.
+pub const SCENE_VERSION: u32 = 1;
+
+/// Upgrades, in place, a scene of version `i + 1` to version `i + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[];
+
+/// Name of the asset the mesh of an entity was created from, saved instead of the vertices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshAsset(pub String);
+
+/// Name of the asset the material of an entity was created from.
+/// Besides registered materials, "simple" and "texture:<path>" are always available.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialAsset(pub String);
+
+/// Name of the asset the sprite texture of an entity was created from, a registered texture or an image path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureAsset(pub String);
+
+/// Name of the asset the tilemap of an entity was created from, a registered tilemap or the path of a Tiled map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TilemapAsset(pub String);
+
+/// User component saved in scene files.
+pub trait SceneComponent: Component + Serialize + DeserializeOwned {
+    /// Key of the component in scene files, it must not change once scenes are saved.
+    fn scene_name() -> &'static str;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SceneFormat {
+    Ron,
+    Json,
+}
+
+impl SceneFormat {
+    /// Format matching the file extension, RON by default.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => SceneFormat::Json,
+            _ => SceneFormat::Ron,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    #[serde(default)]
+    pub camera: Option<CameraData>,
+    #[serde(default)]
+    pub light: Option<LightData>,
+    #[serde(default)]
+    pub fog: Option<Fog>,
+    #[serde(default)]
+    pub entities: Vec<EntityData>,
+    /// Prefab instances, spawned from their prefab file when the scene is loaded.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub instances: Vec<InstanceData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceData {
+    pub prefab: String,
+    #[serde(default, skip_serializing_if = "Overrides::is_empty")]
+    pub overrides: Overrides,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraData {
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightData {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointLightData {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpriteData {
+    pub texture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+    #[serde(default = "SpriteData::default_tint")]
+    pub tint: [f32; 4],
+    #[serde(default)]
+    pub flip_x: bool,
+    #[serde(default)]
+    pub flip_y: bool,
+    #[serde(default = "SpriteData::default_pivot")]
+    pub pivot: [f32; 2],
+    #[serde(default)]
+    pub layer: i32,
+    #[serde(default)]
+    pub order: i32,
+}
+
+impl SpriteData {
+    fn default_tint() -> [f32; 4] {
+        [1.0, 1.0, 1.0, 1.0]
+    }
+
+    fn default_pivot() -> [f32; 2] {
+        [0.5, 0.5]
+    }
+}
+
+/// The tile sets come from the asset, the layers are saved to keep the tiles changed since it was loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TilemapData {
+    pub asset: String,
+    #[serde(default)]
+    pub layers: Vec<TileLayerData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileLayerData {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub sorting_layer: i32,
+    pub tiles: Vec<Option<Tile>>,
+}
+
+/// Rotation is stored as euler angles in degrees to keep files editable.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformData {
+    pub translation: [f32; 3],
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "TransformData::default_scale")]
+    pub scale: [f32; 3],
+}
+
+impl TransformData {
+    fn default_scale() -> [f32; 3] {
+        [1.0, 1.0, 1.0]
+    }
+}
+
+/// Either an asset name or the whole mesh for meshes built in code.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct MeshData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<Vertex>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors: Vec<Color>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub texture_coords: Vec<UV>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub normals: Vec<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indices: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct EntityData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub point_light: Option<PointLightData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<SpriteData>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tilemap: Option<TilemapData>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, Value>,
+}
+
+struct ComponentSerializer {
+    name: &'static str,
+    save: fn(&World, Index) -> Result<Option<Value>, String>,
+    load: fn(&mut World, Index, Value) -> Result<(), String>,
+    remove: fn(&mut World, Index),
+}
+
+fn save_component<T: SceneComponent>(world: &World, id: Index) -> Result<Option<Value>, String> {
+    match world.get::<T>(id) {
+        Some(component) => serde_json::to_value(component).map(Some).map_err(|err| format!("{}: {}", T::scene_name(), err)),
+        None => Ok(None),
+    }
+}
+
+fn load_component<T: SceneComponent>(world: &mut World, id: Index, value: Value) -> Result<(), String> {
+    let component = serde_json::from_value::<T>(value).map_err(|err| format!("{}: {}", T::scene_name(), err))?;
+    world.insert(id, component);
+    Ok(())
+}
+
+fn remove_component<T: SceneComponent>(world: &mut World, id: Index) {
+    world.remove::<T>(id);
+}
+
+fn to_array(vector: Vector3<f32>) -> [f32; 3] {
+    [vector.x, vector.y, vector.z]
+}
+
+fn to_vector(array: [f32; 3]) -> Vector3<f32> {
+    Vector3::new(array[0], array[1], array[2])
+}
+
+/// Saves and loads the entities of a `ComponentStorageManager`, knows the user components and the named assets.
+#[derive(Default)]
+pub struct SceneSerializer {
+    components: Vec<ComponentSerializer>,
+    meshes: HashMap<String, Mesh>,
+    materials: HashMap<String, Material>,
+    textures: HashMap<String, Texture>,
+    tilemaps: HashMap<String, Tilemap>,
+}
+
+impl SceneSerializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_component<T: SceneComponent>(&mut self) {
+        assert!(self.components.iter().all(|component| component.name != T::scene_name()), "Scene component {} registered twice", T::scene_name());
+        self.components.push(ComponentSerializer {
+            name: T::scene_name(),
+            save: save_component::<T>,
+            load: load_component::<T>,
+            remove: remove_component::<T>,
+        });
+    }
+
+    pub fn register_mesh(&mut self, name: &str, mesh: Mesh) {
+        self.meshes.insert(name.to_string(), mesh);
+    }
+
+    pub fn register_material(&mut self, name: &str, material: Material) {
+        self.materials.insert(name.to_string(), material);
+    }
+
+    pub fn register_texture(&mut self, name: &str, texture: Texture) {
+        self.textures.insert(name.to_string(), texture);
+    }
+
+    pub fn register_tilemap(&mut self, name: &str, tilemap: Tilemap) {
+        self.tilemaps.insert(name.to_string(), tilemap);
+    }
+
+    fn mesh_asset(&self, name: &str) -> Result<Mesh, String> {
+        match (self.meshes.get(name), name) {
+            (Some(mesh), _) => Ok(mesh.clone()),
+            (None, "quad") => Ok(PrimitiveBuilder::quad()),
+            (None, "cube") => Ok(PrimitiveBuilder::cube(Color::new(1.0, 0.5, 0.31))),
+            (None, _) => Err(format!("Unknown mesh asset {}", name)),
+        }
+    }
+
+    fn material_asset(&mut self, name: &str) -> Result<Material, String> {
+        if let Some(material) = self.materials.get(name) {
+            return Ok(*material);
+        }
+        let material = if name == "simple" {
+            MaterialBuilder::simple_material_2d()
+        } else if let Some(path) = name.strip_prefix("texture:") {
+            MaterialBuilder::simple_texture_material_2d(path)
+        } else {
+            return Err(format!("Unknown material asset {}", name));
+        };
+        self.materials.insert(name.to_string(), material);
+        Ok(material)
+    }
+
+    /// Prefab instances are saved as their prefab and overrides, the entities spawned for them are not saved.
+    fn texture_asset(&mut self, name: &str) -> Result<Texture, String> {
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(*texture);
+        }
+        if !Path::new(name).is_file() {
+            return Err(format!("Unknown texture asset {}", name));
+        }
+        let texture = Texture::from_file(name).with_nearest_filter();
+        self.textures.insert(name.to_string(), texture);
+        Ok(texture)
+    }
+
+    fn tilemap_asset(&self, name: &str) -> Result<Tilemap, String> {
+        match self.tilemaps.get(name) {
+            Some(tilemap) => Ok(tilemap.clone()),
+            None if Path::new(name).is_file() => Tilemap::from_file(name),
+            None => Err(format!("Unknown tilemap asset {}", name)),
+        }
+    }
+
+    pub fn save(&self, storage: &ComponentStorageManager) -> Result<SceneFile, String> {
+        let camera = storage.get_camera();
+        let light = storage.get_light();
+        let mut instances = Vec::new();
+        let spawned: Vec<Index> = storage.query_read::<&PrefabInstance>().iter()
+            .flat_map(|instance| instance.children.iter().copied())
+            .collect();
+        let mut entities = Vec::new();
+        for id in storage.entities().iter().filter(|id| !spawned.contains(id)) {
+            match storage.get::<PrefabInstance>(id) {
+                Some(instance) => instances.push(InstanceData {
+                    prefab: instance.prefab.to_string_lossy().into_owned(),
+                    overrides: instance.overrides.clone(),
+                }),
+                None => entities.push(self.save_entity(storage, id)?),
+            }
+        }
+        Ok(SceneFile {
+            version: SCENE_VERSION,
+            camera: Some(CameraData { position: to_array(camera.position), direction: to_array(camera.direction) }),
+            light: Some(LightData { position: to_array(light.position), color: to_array(light.color) }),
+            fog: storage.get_fog().copied(),
+            entities,
+            instances,
+        })
+    }
+
+    fn save_entity(&self, storage: &ComponentStorageManager, id: Index) -> Result<EntityData, String> {
+        let transform = storage.get::<Transform>(id).map(|transform| TransformData {
+            translation: to_array(transform.translation),
+            rotation: to_array(transform.euler_angles()),
+            scale: to_array(transform.scale),
+        });
+        let mesh = match (storage.get::<MeshAsset>(id), storage.get::<Mesh>(id)) {
+            (Some(asset), _) => Some(MeshData { asset: Some(asset.0.clone()), ..MeshData::default() }),
+            (None, Some(mesh)) => Some(MeshData::from(mesh)),
+            (None, None) => None,
+        };
+        if storage.contains::<Material>(id) && !storage.contains::<MaterialAsset>(id) {
+            println!("Material of entity {} not saved: it has no MaterialAsset", id);
+        }
+        if storage.contains::<Sprite>(id) && !storage.contains::<TextureAsset>(id) {
+            println!("Sprite of entity {} not saved: it has no TextureAsset", id);
+        }
+        let sprite = storage.get::<Sprite>(id).zip(storage.get::<TextureAsset>(id)).map(|(sprite, asset)| SpriteData {
+            texture: asset.0.clone(),
+            region: sprite.region,
+            tint: sprite.tint.into(),
+            flip_x: sprite.flip_x,
+            flip_y: sprite.flip_y,
+            pivot: sprite.pivot.into(),
+            layer: sprite.layer,
+            order: sprite.order,
+        });
+        if storage.contains::<Tilemap>(id) && !storage.contains::<TilemapAsset>(id) {
+            println!("Tilemap of entity {} not saved: it has no TilemapAsset", id);
+        }
+        let tilemap = storage.get::<Tilemap>(id).zip(storage.get::<TilemapAsset>(id)).map(|(tilemap, asset)| TilemapData {
+            asset: asset.0.clone(),
+            layers: tilemap.get_layers().iter().map(|layer| TileLayerData {
+                name: layer.name.clone(),
+                visible: layer.visible,
+                opacity: layer.opacity,
+                sorting_layer: layer.sorting_layer,
+                tiles: layer.get_tiles().to_vec(),
+            }).collect(),
+        });
+        let mut components = BTreeMap::new();
+        for component in self.components.iter() {
+            if let Some(value) = (component.save)(storage, id)? {
+                components.insert(component.name.to_string(), value);
+            }
+        }
+        Ok(EntityData {
+            transform,
+            mesh,
+            material: storage.get::<MaterialAsset>(id).map(|asset| asset.0.clone()),
+            point_light: storage.get::<PointLight>(id).map(|light| PointLightData {
+                color: to_array(light.color),
+                intensity: light.intensity,
+                radius: light.radius,
+            }),
+            sprite,
+            tilemap,
+            components,
+        })
+    }
+
+    /// Spawns the entities of the scene then its prefab instances, the camera, the light and the fog are replaced.
+    /// Returns the entities followed by the roots of the instances.
+    /// Nothing is changed when an entity fails to load, the entities already spawned are despawned.
+    pub fn load(&mut self, scene: SceneFile, prefabs: &mut PrefabLibrary, storage: &mut ComponentStorageManager) -> Result<Vec<Index>, String> {
+        let mut ids = Vec::new();
+        for entity in scene.entities {
+            let id = storage.spawn();
+            ids.push(id);
+            if let Err(err) = self.load_entity(entity, storage, id) {
+                for id in ids {
+                    storage.despawn_entity(id);
+                }
+                return Err(err);
+            }
+        }
+        let mut roots = Vec::new();
+        for instance in scene.instances {
+            match prefabs.instantiate(&instance.prefab, instance.overrides, self, storage) {
+                Ok(root) => roots.push(root),
+                Err(err) => {
+                    //Instances were announced when spawned, unlike the entities
+                    for root in roots {
+                        let children = storage.get::<PrefabInstance>(root).map(|instance| instance.children.clone()).unwrap_or_default();
+                        for id in children.into_iter().chain(std::iter::once(root)) {
+                            storage.get_mut_events().send(EntityEvent::Despawned(id));
+                            storage.despawn_entity(id);
+                        }
+                    }
+                    for id in ids {
+                        storage.despawn_entity(id);
+                    }
+                    return Err(err);
+                },
+            }
+        }
+        for id in ids.iter() {
+            storage.get_mut_events().send(EntityEvent::Spawned(*id));
+        }
+        ids.extend(roots);
+        if let Some(camera) = scene.camera {
+            *storage.get_mut_camera() = Camera::new(to_vector(camera.position), to_vector(camera.direction));
+        }
+        if let Some(light) = scene.light {
+            *storage.get_mut_light() = Light {
+                position: to_vector(light.position),
+                color: to_vector(light.color),
+            };
+        }
+        if scene.fog.is_some() {
+            storage.set_fog(scene.fog);
+        }
+        Ok(ids)
+    }
+
+    pub(crate) fn load_entity(&mut self, entity: EntityData, storage: &mut ComponentStorageManager, id: Index) -> Result<(), String> {
+        if let Some(data) = entity.transform {
+            let mut transform = Transform::new(to_vector(data.translation), Quaternion::new(1.0, 0.0, 0.0, 0.0), to_vector(data.scale));
+            transform.set_euler_angles(to_vector(data.rotation));
+            storage.insert(id, transform);
+        }
+        if let Some(data) = entity.mesh {
+            match &data.asset {
+                Some(name) => {
+                    let mesh = self.mesh_asset(name)?;
+                    storage.insert(id, mesh);
+                    storage.insert(id, MeshAsset(name.clone()));
+                },
+                None => {
+                    storage.insert(id, Mesh::from(data));
+                },
+            }
+        }
+        if let Some(name) = entity.material {
+            let material = self.material_asset(&name)?;
+            storage.insert(id, material);
+            storage.insert(id, MaterialAsset(name));
+        }
+        if let Some(light) = entity.point_light {
+            storage.insert(id, PointLight::new(to_vector(light.color), light.intensity, light.radius));
+        }
+        if let Some(data) = entity.sprite {
+            let mut sprite = Sprite::new(self.texture_asset(&data.texture)?)
+                .with_tint(data.tint.into())
+                .with_flip(data.flip_x, data.flip_y)
+                .with_pivot(data.pivot.into());
+            sprite.region = data.region;
+            sprite.layer = data.layer;
+            sprite.order = data.order;
+            storage.insert(id, sprite);
+            storage.insert(id, TextureAsset(data.texture));
+        }
+        if let Some(data) = entity.tilemap {
+            let mut tilemap = self.tilemap_asset(&data.asset)?;
+            for (index, saved) in data.layers.into_iter().enumerate() {
+                //Layers added after the map was loaded come after those of the asset
+                let index = if index < tilemap.get_layers().len() { index } else { tilemap.add_layer(&saved.name) };
+                if !tilemap.set_tiles(index, saved.tiles) {
+                    return Err(format!("Tile layer {} doesn't match the size of tilemap {}", saved.name, data.asset));
+                }
+                let layer = tilemap.get_mut_layer(index).unwrap();
+                layer.name = saved.name;
+                layer.visible = saved.visible;
+                layer.opacity = saved.opacity;
+                layer.sorting_layer = saved.sorting_layer;
+            }
+            storage.insert(id, tilemap);
+            storage.insert(id, TilemapAsset(data.asset));
+        }
+        for (name, value) in entity.components {
+            match self.components.iter().find(|component| component.name == name) {
+                Some(component) => (component.load)(storage, id, value)?,
+                None => println!("Unknown scene component {} ignored", name),
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the components `load_entity` may have added, the transform excepted.
+    pub(crate) fn remove_entity_data(&self, storage: &mut ComponentStorageManager, id: Index) {
+        storage.remove::<Mesh>(id);
+        storage.remove::<MeshAsset>(id);
+        storage.remove::<Material>(id);
+        storage.remove::<MaterialAsset>(id);
+        storage.remove::<PointLight>(id);
+        storage.remove::<Sprite>(id);
+        storage.remove::<TextureAsset>(id);
+        storage.remove::<Tilemap>(id);
+        storage.remove::<TilemapAsset>(id);
+        for component in self.components.iter() {
+            (component.remove)(storage, id);
+        }
+    }
+
+    pub fn to_string(scene: &SceneFile, format: SceneFormat) -> Result<String, String> {
+        match format {
+            SceneFormat::Ron => ron::ser::to_string_pretty(scene, ron::ser::PrettyConfig::new()).map_err(|err| err.to_string()),
+            SceneFormat::Json => serde_json::to_string_pretty(scene).map_err(|err| err.to_string()),
+        }
+    }
+
+    /// Parses RON or JSON into a format independent value.
+    pub(crate) fn parse_value(text: &str, format: SceneFormat) -> Result<Value, String> {
+        match format {
+            SceneFormat::Ron => {
+                let value: ron::Value = ron::from_str(text).map_err(|err| err.to_string())?;
+                serde_json::to_value(value).map_err(|err| err.to_string())
+            },
+            SceneFormat::Json => serde_json::from_str(text).map_err(|err| err.to_string()),
+        }
+    }
+
+    /// Parses a scene of any version up to `SCENE_VERSION`, older scenes are migrated.
+    pub fn parse(text: &str, format: SceneFormat) -> Result<SceneFile, String> {
+        let mut value = Self::parse_value(text, format)?;
+        let version = value.get("version").and_then(Value::as_u64).ok_or("Scene without version")? as u32;
+        if version == 0 || version > SCENE_VERSION {
+            return Err(format!("Unsupported scene version {}, the last one is {}", version, SCENE_VERSION));
+        }
+        for migration in MIGRATIONS[(version - 1) as usize..].iter() {
+            migration(&mut value);
+        }
+        value["version"] = Value::from(SCENE_VERSION);
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    pub fn save_to_file<P: AsRef<Path>>(&self, storage: &ComponentStorageManager, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let text = Self::to_string(&self.save(storage)?, SceneFormat::from_path(path))?;
+        fs::write(path, text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn load_from_file<P: AsRef<Path>>(&mut self, prefabs: &mut PrefabLibrary, storage: &mut ComponentStorageManager, path: P) -> Result<Vec<Index>, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let scene = Self::parse(&text, SceneFormat::from_path(path)).map_err(|err| format!("{}: {}", path.display(), err))?;
+        self.load(scene, prefabs, storage)
+    }
+}
+
+impl From<&Mesh> for MeshData {
+    fn from(mesh: &Mesh) -> Self {
+        Self {
+            asset: None,
+            positions: mesh.positions.clone(),
+            colors: mesh.colors.clone(),
+            texture_coords: mesh.texture_coords.clone(),
+            normals: mesh.normals.iter().map(|normal| to_array(*normal)).collect(),
+            indices: mesh.indices.clone(),
+        }
+    }
+}
+
+impl From<MeshData> for Mesh {
+    fn from(data: MeshData) -> Self {
+        let normals = data.normals.into_iter().map(to_vector).collect();
+        Mesh::new(data.positions, data.colors, data.texture_coords, normals, data.indices)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+    use crate::graphics::fog::FogMode;
+    use crate::graphics::tilemap::TileSet;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    impl SceneComponent for Health {
+        fn scene_name() -> &'static str {
+            "health"
+        }
+    }
+
+    fn scene() -> (SceneSerializer, ComponentStorageManager) {
+        let mut serializer = SceneSerializer::new();
+        serializer.register_component::<Health>();
+        serializer.register_texture("hero", Texture::fake(1, 32, 32));
+        let mut storage = ComponentStorageManager::new();
+        let id = storage.spawn();
+        let mut transform = Transform::new_default();
+        transform.translation = Vector3::new(1.0, 2.0, 3.0);
+        transform.set_euler_angles(Vector3::new(0.0, 90.0, 0.0));
+        storage.insert(id, transform);
+        storage.insert(id, Mesh::new(vec![Vertex::new(0.0, 1.0, 0.0)], vec![Color::new(1.0, 0.0, 0.0)], vec![UV::new(0.0, 1.0)], vec![Vector3::new(0.0, 0.0, 1.0)], vec![0]));
+        storage.insert(id, Health(7));
+        storage.insert(id, PointLight::new(Vector3::new(1.0, 0.5, 0.0), 2.0, 8.0));
+        let other = storage.spawn();
+        storage.insert(other, Health(3));
+        storage.insert(other, Sprite::new(Texture::fake(1, 32, 32)).with_region(Region::new(16, 0, 16, 16)).with_flip(true, false));
+        storage.insert(other, TextureAsset("hero".to_string()));
+        storage.get_mut_camera().position = Vector3::new(0.0, 5.0, 0.0);
+        storage.set_fog(Some(Fog::new(FogMode::Height { density: 0.2, base: 1.0, falloff: 0.5 }, Vector3::new(0.5, 0.6, 0.7))));
+        (serializer, storage)
+    }
+
+    #[test]
+    fn round_trip_in_both_formats() {
+        let (mut serializer, storage) = scene();
+        let saved = serializer.save(&storage).unwrap();
+        for format in [SceneFormat::Ron, SceneFormat::Json].iter() {
+            let text = SceneSerializer::to_string(&saved, *format).unwrap();
+            let parsed = SceneSerializer::parse(&text, *format).unwrap();
+            assert_eq!(parsed, saved);
+
+            let mut loaded = ComponentStorageManager::new();
+            let ids = serializer.load(parsed, &mut PrefabLibrary::new(), &mut loaded).unwrap();
+            assert_eq!(ids.len(), 2);
+            assert_eq!(loaded.get::<Health>(ids[1]), Some(&Health(3)));
+            assert_eq!(loaded.get::<Sprite>(ids[1]), Some(&Sprite::new(Texture::fake(1, 32, 32)).with_region(Region::new(16, 0, 16, 16)).with_flip(true, false)));
+            assert_eq!(loaded.get::<Health>(ids[0]), Some(&Health(7)));
+            let transform = loaded.get_transform(ids[0]).unwrap();
+            assert!((transform.translation - Vector3::new(1.0, 2.0, 3.0)).magnitude() < 1e-5);
+            assert!((transform.euler_angles().y - 90.0).abs() < 1e-3);
+            assert_eq!(loaded.get_mesh(ids[0]).unwrap().indices, vec![0]);
+            assert_eq!(loaded.get::<PointLight>(ids[0]), Some(&PointLight::new(Vector3::new(1.0, 0.5, 0.0), 2.0, 8.0)));
+            assert_eq!(loaded.get_camera().position, Vector3::new(0.0, 5.0, 0.0));
+            assert_eq!(loaded.get_fog(), storage.get_fog());
+        }
+    }
+
+    #[test]
+    fn failed_loads_leave_the_world_unchanged() {
+        let (mut serializer, storage) = scene();
+        let mut saved = serializer.save(&storage).unwrap();
+        saved.entities[1].mesh = Some(MeshData { asset: Some("missing".to_string()), ..MeshData::default() });
+        let mut loaded = ComponentStorageManager::new();
+        assert!(serializer.load(saved, &mut PrefabLibrary::new(), &mut loaded).is_err());
+        assert_eq!(loaded.query_read::<Index>().iter().count(), 0);
+        assert_eq!(loaded.get_camera().position, Camera::default().position);
+        assert!(loaded.get_fog().is_none());
+    }
+
+    #[test]
+    fn prefab_instances_are_saved_as_their_prefab() {
+        let directory = std::env::temp_dir().join(format!("neutrino_scene_instances_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("tree.ron");
+        fs::write(&path, "(version: 1, root: (components: { \"health\": 1 }, children: [(components: { \"health\": 2 })]))").unwrap();
+        let (mut serializer, mut storage) = scene();
+        let overrides = Overrides::new().with("components/health", 5);
+        PrefabLibrary::new().instantiate(&path, overrides.clone(), &mut serializer, &mut storage).unwrap();
+        let saved = serializer.save(&storage).unwrap();
+        assert_eq!(saved.entities.len(), 2);
+        assert_eq!(saved.instances, vec![InstanceData { prefab: path.to_string_lossy().into_owned(), overrides }]);
+
+        let mut loaded = ComponentStorageManager::new();
+        let ids = serializer.load(saved, &mut PrefabLibrary::new(), &mut loaded).unwrap();
+        assert_eq!(ids.len(), 3);
+        let instance = loaded.get::<PrefabInstance>(ids[2]).unwrap();
+        assert_eq!(instance.children.len(), 1);
+        assert_eq!(loaded.get::<Health>(ids[2]), Some(&Health(5)));
+        assert_eq!(loaded.get::<Health>(instance.children[0]), Some(&Health(2)));
+        assert_eq!(loaded.query_read::<Index>().iter().count(), 4);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn tilemaps_keep_their_changed_tiles() {
+        let mut serializer = SceneSerializer::new();
+        let mut level = Tilemap::new(4, 4, 16, 16);
+        level.add_tileset(TileSet::new(Texture::fake(1, 64, 32), 16, 16));
+        level.add_layer("ground");
+        level.set_tile(0, 1, 1, Some(Tile::new(0, 2)));
+        serializer.register_tilemap("level", level.clone());
+        let mut storage = ComponentStorageManager::new();
+        let id = storage.spawn();
+        level.set_tile(0, 1, 1, None);
+        level.set_tile(0, 3, 0, Some(Tile::new(0, 5).with_flip(true, false)));
+        let top = level.add_layer("top");
+        level.get_mut_layer(top).unwrap().opacity = 0.5;
+        level.set_tile(top, 2, 2, Some(Tile::new(0, 1)));
+        storage.insert(id, level);
+        storage.insert(id, TilemapAsset("level".to_string()));
+
+        let text = SceneSerializer::to_string(&serializer.save(&storage).unwrap(), SceneFormat::Ron).unwrap();
+        let mut loaded = ComponentStorageManager::new();
+        let ids = serializer.load(SceneSerializer::parse(&text, SceneFormat::Ron).unwrap(), &mut PrefabLibrary::new(), &mut loaded).unwrap();
+        let tilemap = loaded.get::<Tilemap>(ids[0]).unwrap();
+        assert_eq!(tilemap.get_layers().len(), 2);
+        assert_eq!(tilemap.get_tile(0, 1, 1), None);
+        assert_eq!(tilemap.get_tile(0, 3, 0), Some(Tile::new(0, 5).with_flip(true, false)));
+        assert_eq!(tilemap.get_tile(top, 2, 2), Some(Tile::new(0, 1)));
+        assert_eq!(tilemap.get_layer(top).unwrap().opacity, 0.5);
+        assert_eq!(loaded.get::<TilemapAsset>(ids[0]), Some(&TilemapAsset("level".to_string())));
+    }
+
+    #[test]
+    fn missing_fields_use_defaults_and_newer_versions_fail() {
+        let scene = SceneSerializer::parse("(version: 1, entities: [(transform: Some((translation: (1.0, 0.0, 0.0))))])", SceneFormat::Ron).unwrap();
+        assert_eq!(scene.entities[0].transform.as_ref().unwrap().scale, [1.0, 1.0, 1.0]);
+        assert!(scene.camera.is_none());
+        assert!(SceneSerializer::parse("{\"version\": 2}", SceneFormat::Json).is_err());
+    }
+}
@@ -2,9 +2,19 @@ extern crate image;
 
 use image::GenericImageView;
 use std::ffi::CString;
-pub use cgmath::{Matrix4, Vector3};
+use crate::graphics::render_state::RenderState;
+use crate::graphics::fog::include_fog;
+use crate::graphics::light::include_lights;
+pub use cgmath::{Matrix4, Vector2, Vector3, Vector4};
 pub use cgmath::prelude::*;
 
+/// Binding point of the per-frame uniform buffer, see `render_queue::FrameUniforms`.
+pub const FRAME_UNIFORMS_BINDING: gl::types::GLuint = 0;
+/// Binding point of the scene fog uniform buffer, see `fog::FogUniforms`.
+pub const FOG_UNIFORMS_BINDING: gl::types::GLuint = 1;
+/// Binding point of the forward point lights uniform buffer, see `light::LightUniforms`.
+pub const LIGHT_UNIFORMS_BINDING: gl::types::GLuint = 2;
+
 pub struct MaterialBuilder {}
 
 impl MaterialBuilder {
@@ -15,6 +25,8 @@ impl MaterialBuilder {
         layout (location = 1) in vec4 aColor;
         layout (location = 2) in vec2 aTexCoord;
         layout (location = 3) in vec3 aNormal;
+        layout (location = 4) in mat4 aInstanceModel;
+        layout (location = 8) in vec4 aInstanceTint;
 
         out vec4 ourColor;
         out vec2 TexCoord;
@@ -22,16 +34,24 @@ impl MaterialBuilder {
         out vec3 Normal;
 
         uniform mat4 model;
-        uniform mat4 view;
-        uniform mat4 projection;
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+        uniform vec4 tint;
+        uniform bool instanced;
 
         void main()
         {
-            gl_Position = projection * view * model * vec4(aPos, 1.0);
-            FragPos = vec3(model * vec4(aPos, 1.0));
-            ourColor = aColor;
+            mat4 modelMat = instanced ? aInstanceModel : model;
+            gl_Position = projection * view * modelMat * vec4(aPos, 1.0);
+            FragPos = vec3(modelMat * vec4(aPos, 1.0));
+            ourColor = aColor * (instanced ? aInstanceTint : tint);
             TexCoord = vec2(aTexCoord.x, aTexCoord.y);
-            Normal = mat3(transpose(inverse(model))) * aNormal;
+            Normal = mat3(transpose(inverse(modelMat))) * aNormal;
         }
     "#;
 
@@ -44,57 +64,92 @@ impl MaterialBuilder {
         in vec3 Normal; 
         in vec3 FragPos;
 
-        uniform vec3 lightPos;
-        uniform vec3 lightColor;
-        uniform vec3 viewPos;
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
         uniform sampler2D texture1;
+        uniform float alphaCutoff;
+        #include "lights"
+        #include "fog"
 
         void main()
         {
-            // ambient
-            float ambientStrength = 0.3;
-            vec3 ambient = ambientStrength * lightColor;
-
-            // diffuse 
             vec3 norm = normalize(Normal);
-            vec3 lightDir = normalize(lightPos - FragPos);
-            float diff = max(dot(norm, lightDir), 0.0);
-            vec3 diffuse = diff * lightColor;
-
-            // specular
+            vec3 viewDir = normalize(viewPos.xyz - FragPos);
             float specularStrength = 0.5;
-            vec3 viewDir = normalize(viewPos - FragPos);
-            vec3 reflectDir = reflect(-lightDir, norm);
-            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
-            vec3 specular = specularStrength * spec * lightColor;
 
-            vec3 result = (ambient + diffuse + specular) * vec3(ourColor);
-            FragColor = texture(texture1, TexCoord) * vec4(result, 1.0);
+            vec3 light = sceneLight(FragPos, norm, viewDir, specularStrength) + forwardPointLights(FragPos, norm, viewDir, specularStrength);
+            FragColor = texture(texture1, TexCoord) * vec4(light * vec3(ourColor), ourColor.a);
+            if (FragColor.a < alphaCutoff)
+                discard;
+            FragColor.rgb = applyFog(FragColor.rgb, FragPos, viewPos.xyz);
+        }
+    "#;
+
+        // Same surface written to the G-buffer of the deferred path, see `deferred::GBuffer`
+        let gbuffer_source = r#"
+        #version 330 core
+        layout (location = 0) out vec4 gAlbedo;
+        layout (location = 1) out vec4 gNormal;
+        layout (location = 2) out vec4 gMaterial;
+
+        in vec4 ourColor;
+        in vec2 TexCoord;
+        in vec3 Normal;
+
+        uniform sampler2D texture1;
+        uniform float alphaCutoff;
+
+        void main()
+        {
+            vec4 albedo = texture(texture1, TexCoord) * ourColor;
+            if (albedo.a < alphaCutoff)
+                discard;
+            gAlbedo = vec4(albedo.rgb, 1.0);
+            gNormal = vec4(normalize(Normal), 0.0);
+            // x: specular strength, y: lit
+            gMaterial = vec4(0.5, 1.0, 0.0, 0.0);
         }
     "#;
 
-        let shader = Shader::new(&vert_source, &frag_source);
-        Material::new(shader, Texture::new_empty())
+        let shader = Shader::new(vert_source, &include_fog(&include_lights(frag_source)));
+        Material::new(shader, Texture::new_empty()).with_gbuffer_shader(Shader::new(vert_source, gbuffer_source))
     }
 
     pub fn simple_texture_material_2d(image_path: &str) -> Material {
         let vert_source = r#"
         #version 330 core
         layout (location = 0) in vec3 aPos;
-        layout (location = 1) in vec3 aColor;
+        layout (location = 1) in vec4 aColor;
         layout (location = 2) in vec2 aTexCoord;
+        layout (location = 4) in mat4 aInstanceModel;
+        layout (location = 8) in vec4 aInstanceTint;
 
-        out vec3 ourColor;
+        out vec4 ourColor;
         out vec2 TexCoord;
+        out vec3 FragPos;
 
         uniform mat4 model;
-        uniform mat4 view;
-        uniform mat4 projection;
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+        uniform vec4 tint;
+        uniform bool instanced;
 
         void main()
         {
-            gl_Position = projection * view * model * vec4(aPos, 1.0);
-            ourColor = aColor;
+            mat4 modelMat = instanced ? aInstanceModel : model;
+            gl_Position = projection * view * modelMat * vec4(aPos, 1.0);
+            FragPos = vec3(modelMat * vec4(aPos, 1.0));
+            ourColor = aColor * (instanced ? aInstanceTint : tint);
             TexCoord = vec2(aTexCoord.x, aTexCoord.y);
         }
     "#;
@@ -103,20 +158,57 @@ impl MaterialBuilder {
         #version 330 core
         out vec4 FragColor;
 
-        in vec3 ourColor;
+        in vec4 ourColor;
         in vec2 TexCoord;
+        in vec3 FragPos;
 
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
         // texture sampler
         uniform sampler2D texture1;
+        uniform float alphaCutoff;
+        #include "fog"
 
         void main()
         {
-            FragColor = texture(texture1, TexCoord) * vec4(ourColor, 1.0);
+            FragColor = texture(texture1, TexCoord) * ourColor;
+            if (FragColor.a < alphaCutoff)
+                discard;
+            FragColor.rgb = applyFog(FragColor.rgb, FragPos, viewPos.xyz);
         }
     "#;
 
-        let shader = Shader::new(&vert_source, &frag_source);
-        Material::new(shader, Texture::from_file(image_path))
+        let gbuffer_source = r#"
+        #version 330 core
+        layout (location = 0) out vec4 gAlbedo;
+        layout (location = 1) out vec4 gNormal;
+        layout (location = 2) out vec4 gMaterial;
+
+        in vec4 ourColor;
+        in vec2 TexCoord;
+
+        uniform sampler2D texture1;
+        uniform float alphaCutoff;
+
+        void main()
+        {
+            vec4 albedo = texture(texture1, TexCoord) * ourColor;
+            if (albedo.a < alphaCutoff)
+                discard;
+            gAlbedo = vec4(albedo.rgb, 1.0);
+            gNormal = vec4(0.0);
+            // unlit
+            gMaterial = vec4(0.0);
+        }
+    "#;
+
+        let shader = Shader::new(vert_source, &include_fog(frag_source));
+        Material::new(shader, Texture::from_file(image_path)).with_gbuffer_shader(Shader::new(vert_source, gbuffer_source))
     }
 }
 
@@ -124,6 +216,9 @@ impl MaterialBuilder {
 pub struct Material {
     pub shader: Shader,
     texture: Texture,
+    pub state: RenderState,
+    /// Shader writing the surface to the G-buffer, materials without one are always drawn by the forward path.
+    pub gbuffer_shader: Option<Shader>,
 }
 
 impl Material {
@@ -131,6 +226,8 @@ impl Material {
         Self {
             shader: shader,
             texture: texture,
+            state: RenderState::opaque(),
+            gbuffer_shader: None,
         }
     }
 
@@ -138,18 +235,43 @@ impl Material {
         Self {
             shader: shader,
             texture: Texture::new_empty(),
+            state: RenderState::opaque(),
+            gbuffer_shader: None,
         }
     }
 
+    pub fn with_state(mut self, state: RenderState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn with_gbuffer_shader(mut self, shader: Shader) -> Self {
+        self.gbuffer_shader = Some(shader);
+        self
+    }
+
     pub fn bind(&self) {
         self.texture.bind();
         self.shader.use_program();
     }
+
+    pub fn bind_texture(&self) {
+        self.texture.bind();
+    }
+
+    /// Materials with the same key render the same way and can be batched together.
+    pub fn key(&self) -> MaterialKey {
+        (self.shader.id, self.texture.id)
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+pub type MaterialKey = (gl::types::GLuint, gl::types::GLuint);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Texture {
     id: gl::types::GLuint,
+    width: u32,
+    height: u32,
 }
 
 impl Texture {
@@ -158,6 +280,8 @@ impl Texture {
 
         Self {
             id: Texture::generate_gl_texture(white_image, 1, 1),
+            width: 1,
+            height: 1,
         }
     }
 
@@ -168,7 +292,34 @@ impl Texture {
 
         Self {
             id: Texture::generate_gl_texture(img.into_raw(), width, height),
+            width,
+            height,
+        }
+    }
+
+    /// Samples the nearest texel without mipmaps, for pixel art.
+    pub fn with_nearest_filter(self) -> Self {
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
         }
+        self
+    }
+
+    /// Width and height in pixels.
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub(crate) fn get_id(&self) -> gl::types::GLuint {
+        self.id
+    }
+
+    /// Texture handle without GL storage, for tests.
+    #[cfg(test)]
+    pub(crate) fn fake(id: gl::types::GLuint, width: u32, height: u32) -> Self {
+        Self { id, width, height }
     }
 
     fn generate_gl_texture(image: Vec<u8>, width: u32, height: u32) -> gl::types::GLuint {
@@ -207,7 +358,7 @@ impl Texture {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Shader {
     id: gl::types::GLuint,
 }
@@ -234,6 +385,15 @@ impl Shader {
             gl::LinkProgram(program_id);
         }
         check_link_status(program_id).unwrap();
+        for (block, binding) in [("Frame", FRAME_UNIFORMS_BINDING), ("Fog", FOG_UNIFORMS_BINDING), ("Lights", LIGHT_UNIFORMS_BINDING)] {
+            let block_name = CString::new(block).unwrap();
+            unsafe {
+                let block_index = gl::GetUniformBlockIndex(program_id, block_name.as_ptr());
+                if block_index != gl::INVALID_INDEX {
+                    gl::UniformBlockBinding(program_id, block_index, binding);
+                }
+            }
+        }
         unsafe {
             gl::DetachShader(program_id, vert_id);
             gl::DetachShader(program_id, frag_id);
@@ -257,6 +417,41 @@ impl Shader {
         }
     }
 
+    pub fn set_vec4(&self, name: &str, vec: Vector4<f32>) {
+        let vec_name = CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform4fv(gl::GetUniformLocation(self.id, vec_name.as_ptr()), 1, vec.as_ptr());
+        }
+    }
+
+    pub fn set_float(&self, name: &str, value: f32) {
+        let float_name = CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform1f(gl::GetUniformLocation(self.id, float_name.as_ptr()), value);
+        }
+    }
+
+    pub fn set_int(&self, name: &str, value: i32) {
+        let int_name = CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform1i(gl::GetUniformLocation(self.id, int_name.as_ptr()), value);
+        }
+    }
+
+    pub fn set_vec2(&self, name: &str, vec: Vector2<f32>) {
+        let vec_name = CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform2fv(gl::GetUniformLocation(self.id, vec_name.as_ptr()), 1, vec.as_ptr());
+        }
+    }
+
+    pub fn set_bool(&self, name: &str, value: bool) {
+        let bool_name = CString::new(name).unwrap();
+        unsafe {
+            gl::Uniform1i(gl::GetUniformLocation(self.id, bool_name.as_ptr()), value as i32);
+        }
+    }
+
     pub fn set_vec3(&self, name: &str, vec: Vector3<f32>) {
         let vec_name = CString::new(name).unwrap();
         unsafe {
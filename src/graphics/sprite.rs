@@ -0,0 +1,862 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use cgmath::{Matrix4, Rotation, SquareMatrix, Vector2, Vector3, Vector4};
+use serde::de::{Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use crate::ecs::Entity;
+use crate::graphics::ComponentStorageManager;
+use crate::graphics::render_state::{BlendMode, RenderState};
+use crate::graphics::shader::{Shader, Texture};
+use crate::graphics::tilemap::{TileChunk, Tilemap};
+use crate::graphics::transform::Transform;
+
+
+/// Area of a texture in pixels, from its top left corner.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+}
+
+/// Textured quad drawn by the 2D renderer at the position of the entity `Transform`, one texture pixel per world unit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sprite {
+    pub texture: Texture,
+    /// Part of the texture drawn, the whole texture when `None`.
+    pub region: Option<Region>,
+    pub tint: Vector4<f32>,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Point of the sprite placed at the entity position, from (0, 0) at the bottom left to (1, 1) at the top right.
+    pub pivot: Vector2<f32>,
+    /// Sprites are drawn by increasing layer, then by increasing order in a layer.
+    pub layer: i32,
+    pub order: i32,
+}
+
+impl Sprite {
+    pub fn new(texture: Texture) -> Self {
+        Self {
+            texture,
+            region: None,
+            tint: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            flip_x: false,
+            flip_y: false,
+            pivot: Vector2::new(0.5, 0.5),
+            layer: 0,
+            order: 0,
+        }
+    }
+
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn with_tint(mut self, tint: Vector4<f32>) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_pivot(mut self, pivot: Vector2<f32>) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn with_layer(mut self, layer: i32, order: i32) -> Self {
+        self.layer = layer;
+        self.order = order;
+        self
+    }
+
+    fn get_region(&self) -> Region {
+        let (width, height) = self.texture.get_size();
+        self.region.unwrap_or(Region::new(0, 0, width, height))
+    }
+
+    /// Corners in world units, counter clockwise from the bottom left, with their texture coordinates.
+    fn corners(&self, transform: &Transform) -> [([f32; 2], [f32; 2]); 4] {
+        let region = self.get_region();
+        let (texture_width, texture_height) = self.texture.get_size();
+        let (texture_width, texture_height) = (texture_width.max(1) as f32, texture_height.max(1) as f32);
+        let (mut left, mut right) = (region.x as f32 / texture_width, (region.x + region.width) as f32 / texture_width);
+        //Images are stored from their top row
+        let (mut top, mut bottom) = (region.y as f32 / texture_height, (region.y + region.height) as f32 / texture_height);
+        if self.flip_x {
+            std::mem::swap(&mut left, &mut right);
+        }
+        if self.flip_y {
+            std::mem::swap(&mut top, &mut bottom);
+        }
+        let size = Vector2::new(region.width as f32 * transform.scale.x, region.height as f32 * transform.scale.y);
+        let min = Vector2::new(-self.pivot.x * size.x, -self.pivot.y * size.y);
+        let max = min + size;
+        let corner = |x: f32, y: f32, uv: [f32; 2]| {
+            let position = transform.rotation.rotate_vector(Vector3::new(x, y, 0.0)) + transform.translation;
+            ([position.x, position.y], uv)
+        };
+        [
+            corner(min.x, min.y, [left, bottom]),
+            corner(max.x, min.y, [right, bottom]),
+            corner(max.x, max.y, [right, top]),
+            corner(min.x, max.y, [left, top]),
+        ]
+    }
+}
+
+/// How an animation continues after its last frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayMode {
+    Loop,
+    /// Stops on the last frame.
+    Once,
+    /// Plays backward after the last frame, then forward again.
+    PingPong,
+}
+
+/// Frames of a sprite-sheet animation with the duration of each one in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub frames: Vec<Region>,
+    pub durations: Vec<f32>,
+    pub mode: PlayMode,
+}
+
+impl AnimationClip {
+    /// Clip showing every frame for `frame_duration` seconds.
+    pub fn new(frames: Vec<Region>, frame_duration: f32, mode: PlayMode) -> Self {
+        let durations = vec![frame_duration; frames.len()];
+        Self { frames, durations, mode }
+    }
+
+    /// Frame indices of one cycle, the way back of a ping-pong clip does not repeat its first and last frames.
+    fn sequence(&self) -> Vec<usize> {
+        let count = self.frames.len();
+        let mut sequence: Vec<usize> = (0..count).collect();
+        if self.mode == PlayMode::PingPong && count > 2 {
+            sequence.extend((1..count - 1).rev());
+        }
+        sequence
+    }
+
+    /// Length of one cycle in seconds.
+    pub fn duration(&self) -> f32 {
+        self.sequence().iter().map(|index| self.durations[*index]).sum()
+    }
+
+    /// Index of the frame shown `time` seconds after the start of the clip.
+    pub fn frame_at(&self, time: f32) -> usize {
+        let sequence = self.sequence();
+        let duration = self.duration();
+        if sequence.is_empty() || duration <= 0.0 {
+            return 0;
+        }
+        if self.mode == PlayMode::Once && time >= duration {
+            return sequence[sequence.len() - 1];
+        }
+        let mut time = time.rem_euclid(duration);
+        for index in sequence.iter() {
+            if time < self.durations[*index] {
+                return *index;
+            }
+            time -= self.durations[*index];
+        }
+        sequence[sequence.len() - 1]
+    }
+}
+
+/// Plays a clip on the `Sprite` of the entity, the frames replace the sprite region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteAnimation {
+    clip: AnimationClip,
+    /// Playback speed multiplier.
+    pub speed: f32,
+    pub playing: bool,
+    time: f32,
+}
+
+impl SpriteAnimation {
+    pub fn new(clip: AnimationClip) -> Self {
+        Self {
+            clip,
+            speed: 1.0,
+            playing: true,
+            time: 0.0,
+        }
+    }
+
+    /// Plays `clip` from its first frame, unless it is already playing.
+    pub fn play(&mut self, clip: AnimationClip) {
+        if self.clip != clip {
+            self.clip = clip;
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn get_clip(&self) -> &AnimationClip {
+        &self.clip
+    }
+
+    pub fn get_frame_index(&self) -> usize {
+        self.clip.frame_at(self.time)
+    }
+
+    pub fn get_frame(&self) -> Option<Region> {
+        self.clip.frames.get(self.get_frame_index()).copied()
+    }
+
+    /// True once a clip played once has reached its end.
+    pub fn is_finished(&self) -> bool {
+        self.clip.mode == PlayMode::Once && self.time >= self.clip.duration()
+    }
+
+    pub fn advance(&mut self, delta: f32) {
+        if self.playing {
+            self.time += delta * self.speed;
+        }
+    }
+}
+
+/// Advances the animations and shows their current frame, run by the "animation" engine system.
+/// Stopped animations and sprites already showing their frame are not borrowed mutably.
+pub(crate) fn update_animations(storage: &mut ComponentStorageManager, delta: f32) {
+    let changed: Vec<Entity> = storage.query_read::<(Entity, &SpriteAnimation, &Sprite)>().iter()
+        .filter(|(_, animation, sprite)| {
+            (animation.playing && !animation.is_finished()) || animation.get_frame().is_some_and(|frame| sprite.region != Some(frame))
+        })
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for entity in changed {
+        let frame = match storage.get_mut::<SpriteAnimation>(entity) {
+            Some(animation) => {
+                animation.advance(delta);
+                animation.get_frame()
+            },
+            None => continue,
+        };
+        if frame.is_some() && storage.get::<Sprite>(entity).is_some_and(|sprite| sprite.region != frame) {
+            storage.get_mut::<Sprite>(entity).unwrap().region = frame;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SheetRect {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(Deserialize)]
+struct SheetFrame {
+    #[serde(default)]
+    filename: Option<String>,
+    frame: SheetRect,
+    #[serde(default)]
+    rotated: bool,
+    /// Milliseconds, exported by Aseprite.
+    #[serde(default)]
+    duration: Option<f32>,
+}
+
+/// Frames of the hash format in the order of the file.
+struct OrderedFrames(Vec<(String, SheetFrame)>);
+
+impl<'de> Deserialize<'de> for OrderedFrames {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FramesVisitor;
+
+        impl<'de> Visitor<'de> for FramesVisitor {
+            type Value = OrderedFrames;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of frames")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut frames = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    frames.push(entry);
+                }
+                Ok(OrderedFrames(frames))
+            }
+        }
+
+        deserializer.deserialize_map(FramesVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SheetFrames {
+    Array(Vec<SheetFrame>),
+    Hash(OrderedFrames),
+}
+
+#[derive(Deserialize)]
+struct FrameTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SheetMeta {
+    image: String,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<FrameTag>,
+}
+
+#[derive(Deserialize)]
+struct SheetFile {
+    frames: SheetFrames,
+    meta: SheetMeta,
+}
+
+/// Default duration of the frames of a sheet without durations.
+const DEFAULT_FRAME_DURATION: f32 = 0.1;
+
+/// Content of a sheet file, before its image is loaded.
+struct SheetData {
+    image: String,
+    frames: Vec<(String, Region)>,
+    animations: HashMap<String, AnimationClip>,
+}
+
+/// Reads the JSON sheets exported by TexturePacker or Aseprite, as an array or a hash of frames.
+/// Aseprite tags become animations.
+fn parse_sheet(json: &str) -> Result<SheetData, String> {
+    let sheet: SheetFile = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    let entries: Vec<(String, SheetFrame)> = match sheet.frames {
+        SheetFrames::Array(frames) => frames.into_iter().enumerate()
+            .map(|(index, frame)| (frame.filename.clone().unwrap_or_else(|| index.to_string()), frame))
+            .collect(),
+        SheetFrames::Hash(OrderedFrames(frames)) => frames,
+    };
+    if let Some((name, _)) = entries.iter().find(|(_, frame)| frame.rotated) {
+        return Err(format!("Frame {} is rotated, rotated frames are not supported", name));
+    }
+    let frames: Vec<(String, Region)> = entries.iter()
+        .map(|(name, frame)| (name.clone(), Region::new(frame.frame.x, frame.frame.y, frame.frame.w, frame.frame.h)))
+        .collect();
+    let mut animations = HashMap::new();
+    for tag in sheet.meta.frame_tags {
+        if tag.from > tag.to || tag.to >= frames.len() {
+            return Err(format!("Tag {} uses missing frames", tag.name));
+        }
+        let mut indices: Vec<usize> = (tag.from..=tag.to).collect();
+        let mode = match tag.direction.as_deref() {
+            Some("reverse") => {
+                indices.reverse();
+                PlayMode::Loop
+            },
+            Some("pingpong") => PlayMode::PingPong,
+            _ => PlayMode::Loop,
+        };
+        animations.insert(tag.name, AnimationClip {
+            frames: indices.iter().map(|index| frames[*index].1).collect(),
+            durations: indices.iter().map(|index| entries[*index].1.duration.map_or(DEFAULT_FRAME_DURATION, |duration| duration / 1000.0)).collect(),
+            mode,
+        });
+    }
+    Ok(SheetData { image: sheet.meta.image, frames, animations })
+}
+
+/// Texture holding several sprites, as named frames and animations.
+#[derive(Debug, Clone)]
+pub struct SpriteAtlas {
+    texture: Texture,
+    frames: HashMap<String, Region>,
+    animations: HashMap<String, AnimationClip>,
+}
+
+impl SpriteAtlas {
+    pub fn new(texture: Texture) -> Self {
+        Self {
+            texture,
+            frames: HashMap::new(),
+            animations: HashMap::new(),
+        }
+    }
+
+    /// Sheet of frames of the same size, named "0", "1"... row by row from the top left corner.
+    pub fn grid(texture: Texture, frame_width: u32, frame_height: u32) -> Self {
+        let mut atlas = Self::new(texture);
+        let (width, height) = texture.get_size();
+        let columns = width / frame_width.max(1);
+        let rows = height / frame_height.max(1);
+        for index in 0..columns * rows {
+            let region = Region::new((index % columns) * frame_width, (index / columns) * frame_height, frame_width, frame_height);
+            atlas.add_frame(&index.to_string(), region);
+        }
+        atlas
+    }
+
+    /// Loads a TexturePacker or Aseprite JSON sheet, its image path is relative to the sheet.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let sheet = parse_sheet(&json).map_err(|err| format!("{}: {}", path, err))?;
+        let image = Path::new(path).parent().unwrap_or_else(|| Path::new("")).join(&sheet.image);
+        if !image.is_file() {
+            return Err(format!("{}: image {} not found", path, image.display()));
+        }
+        let mut atlas = Self::new(Texture::from_file(&image.to_string_lossy()));
+        for (name, region) in sheet.frames {
+            atlas.add_frame(&name, region);
+        }
+        atlas.animations = sheet.animations;
+        Ok(atlas)
+    }
+
+    pub fn get_texture(&self) -> Texture {
+        self.texture
+    }
+
+    pub fn add_frame(&mut self, name: &str, region: Region) {
+        self.frames.insert(name.to_string(), region);
+    }
+
+    pub fn get_frame(&self, name: &str) -> Option<Region> {
+        self.frames.get(name).copied()
+    }
+
+    pub fn add_animation(&mut self, name: &str, clip: AnimationClip) {
+        self.animations.insert(name.to_string(), clip);
+    }
+
+    pub fn get_animation(&self, name: &str) -> Option<&AnimationClip> {
+        self.animations.get(name)
+    }
+
+    /// Clip of the named frames, e.g. `atlas.clip(&["run_0", "run_1"], 0.1, PlayMode::Loop)`.
+    pub fn clip(&self, frames: &[&str], frame_duration: f32, mode: PlayMode) -> Result<AnimationClip, String> {
+        let regions = frames.iter()
+            .map(|name| self.get_frame(name).ok_or_else(|| format!("Unknown frame {}", name)))
+            .collect::<Result<Vec<Region>, String>>()?;
+        Ok(AnimationClip::new(regions, frame_duration, mode))
+    }
+
+    /// Sprite showing the frame `name`.
+    pub fn sprite(&self, name: &str) -> Option<Sprite> {
+        self.get_frame(name).map(|region| Sprite::new(self.texture).with_region(region))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub(crate) struct SpriteVertex {
+    pub(crate) position: [f32; 2],
+    pub(crate) uv: [f32; 2],
+    pub(crate) color: [f32; 4],
+}
+
+/// Vertices of the sprites in drawing order, with the sorting layer, texture and vertex count of each batch.
+fn build_batches(mut sprites: Vec<(Sprite, Transform)>) -> (Vec<SpriteVertex>, Vec<(i32, Texture, usize)>) {
+    //Sprites of a same layer and order are grouped by texture
+    sprites.sort_by_key(|(sprite, _)| (sprite.layer, sprite.order, sprite.texture.get_id()));
+    let mut vertices = Vec::with_capacity(sprites.len() * 6);
+    let mut batches: Vec<(i32, Texture, usize)> = Vec::new();
+    for (sprite, transform) in sprites.iter() {
+        let color: [f32; 4] = sprite.tint.into();
+        let corners = sprite.corners(transform);
+        for index in [0, 1, 2, 0, 2, 3] {
+            let (position, uv) = corners[index];
+            vertices.push(SpriteVertex { position, uv, color });
+        }
+        match batches.last_mut() {
+            Some((layer, texture, count)) if *layer == sprite.layer && *texture == sprite.texture => *count += 6,
+            _ => batches.push((sprite.layer, sprite.texture, 6)),
+        }
+    }
+    (vertices, batches)
+}
+
+/// True if the local `bounds` placed by `model` overlap the world rectangle `view`.
+fn is_in_view(model: &Matrix4<f32>, bounds: (Vector2<f32>, Vector2<f32>), view: (Vector2<f32>, Vector2<f32>)) -> bool {
+    let (min, max) = bounds;
+    let corners = [(min.x, min.y), (max.x, min.y), (max.x, max.y), (min.x, max.y)];
+    let mut world_min = Vector2::new(f32::MAX, f32::MAX);
+    let mut world_max = Vector2::new(f32::MIN, f32::MIN);
+    for (x, y) in corners.iter() {
+        let corner = model * Vector4::new(*x, *y, 0.0, 1.0);
+        world_min = Vector2::new(world_min.x.min(corner.x), world_min.y.min(corner.y));
+        world_max = Vector2::new(world_max.x.max(corner.x), world_max.y.max(corner.y));
+    }
+    world_min.x <= view.1.x && world_max.x >= view.0.x && world_min.y <= view.1.y && world_max.y >= view.0.y
+}
+
+fn create_vertex_array() -> (gl::types::GLuint, gl::types::GLuint) {
+    let (mut vao, mut vbo) = (0, 0);
+    let stride = std::mem::size_of::<SpriteVertex>() as gl::types::GLint;
+    unsafe {
+        gl::GenVertexArrays(1, &mut vao);
+        gl::BindVertexArray(vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 2]>() as *const gl::types::GLvoid);
+        gl::EnableVertexAttribArray(2);
+        gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 4]>() as *const gl::types::GLvoid);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        gl::BindVertexArray(0);
+    }
+    (vao, vbo)
+}
+
+/// Vertex buffer of a tilemap chunk, uploaded again when the chunk version changes.
+struct ChunkBuffers {
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+    version: u64,
+    batches: Vec<(Texture, usize)>,
+    /// Cleared every frame, the buffers of chunks whose tilemap is gone are deleted.
+    used: bool,
+}
+
+impl ChunkBuffers {
+    fn new() -> Self {
+        let (vao, vbo) = create_vertex_array();
+        Self {
+            vao,
+            vbo,
+            version: 0,
+            batches: Vec::new(),
+            used: true,
+        }
+    }
+
+    fn upload(&mut self, chunk: &TileChunk, tilemap: &Tilemap) {
+        let mut vertices: Vec<SpriteVertex> = Vec::new();
+        self.batches.clear();
+        for (tileset, tile_vertices) in chunk.batches.iter() {
+            if let Some(tileset) = tilemap.get_tileset(*tileset) {
+                vertices.extend_from_slice(tile_vertices);
+                self.batches.push((tileset.get_texture(), tile_vertices.len()));
+            }
+        }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(vertices.as_slice()) as gl::types::GLsizeiptr, vertices.as_ptr() as *const gl::types::GLvoid, gl::STATIC_DRAW);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        self.version = chunk.version;
+    }
+}
+
+impl Drop for ChunkBuffers {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+enum DrawItem {
+    /// Chunk of a tilemap with its model matrix and layer opacity.
+    Chunk((Entity, usize), Matrix4<f32>, f32),
+    /// Range of the sprite vertices sharing a texture.
+    Sprites(Texture, usize, usize),
+}
+
+/// Draws the tilemaps and sprites over the 3D scene with the 2D camera.
+/// Sprites sharing a texture are drawn in one call, tilemap chunks outside of the view are skipped.
+pub(crate) struct SpriteRenderer {
+    shader: Shader,
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+    chunks: HashMap<(Entity, usize), ChunkBuffers>,
+}
+
+impl SpriteRenderer {
+    pub(crate) fn new() -> Self {
+        let vert_source = r#"
+        #version 330 core
+        layout (location = 0) in vec2 aPos;
+        layout (location = 1) in vec2 aTexCoord;
+        layout (location = 2) in vec4 aColor;
+
+        out vec2 TexCoord;
+        out vec4 ourColor;
+
+        uniform mat4 projection;
+        uniform mat4 model;
+
+        void main()
+        {
+            gl_Position = projection * model * vec4(aPos, 0.0, 1.0);
+            TexCoord = aTexCoord;
+            ourColor = aColor;
+        }
+    "#;
+        let frag_source = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        in vec2 TexCoord;
+        in vec4 ourColor;
+
+        uniform sampler2D spriteTexture;
+        uniform vec4 tint;
+
+        void main()
+        {
+            FragColor = tint * ourColor * texture(spriteTexture, TexCoord);
+            if (FragColor.a <= 0.0)
+                discard;
+        }
+    "#;
+        let (vao, vbo) = create_vertex_array();
+        Self {
+            shader: Shader::new(vert_source, frag_source),
+            vao,
+            vbo,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Draws the tilemaps and sprites of the world in the bound framebuffer, sized like the viewport.
+    /// The changed tilemap chunks are rebuilt first.
+    pub(crate) fn draw(&mut self, storage: &mut ComponentStorageManager) {
+        let camera = storage.camera_2d;
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let (width, height) = (viewport[2] as f32, viewport[3] as f32);
+        let half = Vector2::new(width, height) / (2.0 * camera.zoom);
+        let view = (camera.position - half, camera.position + half);
+
+        //Tile layers are drawn before the sprites of their sorting layer
+        let mut items: Vec<((i32, bool), DrawItem)> = Vec::new();
+        let dirty: Vec<Entity> = storage.query_read::<(Entity, &Tilemap)>().iter()
+            .filter(|(_, tilemap)| tilemap.needs_rebuild())
+            .map(|(entity, _)| entity)
+            .collect();
+        for entity in dirty {
+            storage.get_mut::<Tilemap>(entity).unwrap().rebuild_chunks();
+        }
+        for (entity, tilemap, transform) in storage.query_read::<(Entity, &Tilemap, &Transform)>().iter() {
+            let model = transform.calculate_local_transform();
+            for (index, chunk) in tilemap.get_chunks().iter().enumerate() {
+                let key = (entity, index);
+                if let Some(buffers) = self.chunks.get_mut(&key) {
+                    buffers.used = true;
+                }
+                let layer = &tilemap.get_layers()[chunk.layer];
+                if !layer.visible || chunk.batches.is_empty() || !is_in_view(&model, chunk.bounds, view) {
+                    continue;
+                }
+                let buffers = self.chunks.entry(key).or_insert_with(ChunkBuffers::new);
+                if buffers.version != chunk.version {
+                    buffers.upload(chunk, tilemap);
+                }
+                items.push(((layer.sorting_layer, false), DrawItem::Chunk(key, model, layer.opacity)));
+            }
+        }
+        self.chunks.retain(|_, buffers| std::mem::replace(&mut buffers.used, false));
+
+        let sprites: Vec<(Sprite, Transform)> = storage.query_read::<(&Sprite, &Transform)>().iter()
+            .map(|(sprite, transform)| (*sprite, *transform))
+            .collect();
+        let (vertices, batches) = build_batches(sprites);
+        let mut first = 0;
+        for (layer, texture, count) in batches {
+            items.push(((layer, true), DrawItem::Sprites(texture, first, count)));
+            first += count;
+        }
+        if items.is_empty() {
+            return;
+        }
+        items.sort_by_key(|(key, _)| *key);
+
+        let state = RenderState { depth_test: false, ..RenderState::transparent(BlendMode::Alpha) };
+        state.apply(None);
+        self.shader.use_program();
+        self.shader.set_mat4("projection", camera.projection(width, height));
+        self.shader.set_int("spriteTexture", 0);
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            if !vertices.is_empty() {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+                gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(vertices.as_slice()) as gl::types::GLsizeiptr, vertices.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            }
+        }
+        for (_, item) in items {
+            match item {
+                DrawItem::Chunk(key, model, opacity) => {
+                    let buffers = &self.chunks[&key];
+                    self.shader.set_mat4("model", model);
+                    self.shader.set_vec4("tint", Vector4::new(1.0, 1.0, 1.0, opacity));
+                    unsafe { gl::BindVertexArray(buffers.vao) };
+                    let mut first = 0;
+                    for (texture, count) in buffers.batches.iter() {
+                        texture.bind();
+                        unsafe { gl::DrawArrays(gl::TRIANGLES, first as i32, *count as i32) };
+                        first += count;
+                    }
+                },
+                DrawItem::Sprites(texture, first, count) => {
+                    self.shader.set_mat4("model", Matrix4::identity());
+                    self.shader.set_vec4("tint", Vector4::new(1.0, 1.0, 1.0, 1.0));
+                    unsafe { gl::BindVertexArray(self.vao) };
+                    texture.bind();
+                    unsafe { gl::DrawArrays(gl::TRIANGLES, first as i32, count as i32) };
+                },
+            }
+        }
+        unsafe { gl::BindVertexArray(0) };
+        RenderState::opaque().apply(Some(&state));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Quaternion, Rotation3};
+    use crate::graphics::camera::Camera2D;
+
+    const SHEET: &str = r#"{
+        "frames": {
+            "run 2": { "frame": { "x": 32, "y": 0, "w": 16, "h": 16 }, "rotated": false, "trimmed": false, "duration": 100 },
+            "run 10": { "frame": { "x": 0, "y": 16, "w": 16, "h": 16 }, "rotated": false, "trimmed": false, "duration": 200 },
+            "idle": { "frame": { "x": 0, "y": 0, "w": 16, "h": 16 }, "duration": 100 }
+        },
+        "meta": {
+            "image": "hero.png",
+            "size": { "w": 64, "h": 32 },
+            "frameTags": [ { "name": "run", "from": 0, "to": 1, "direction": "forward" } ]
+        }
+    }"#;
+
+    fn transform_at(x: f32, y: f32) -> Transform {
+        let mut transform = Transform::new_default();
+        transform.translation = Vector3::new(x, y, 0.0);
+        transform
+    }
+
+    #[test]
+    fn sheet_frames_keep_their_order() {
+        let sheet = parse_sheet(SHEET).unwrap();
+        assert_eq!(sheet.image, "hero.png");
+        let names: Vec<&str> = sheet.frames.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["run 2", "run 10", "idle"]);
+        let run = &sheet.animations["run"];
+        assert_eq!(run.frames, vec![Region::new(32, 0, 16, 16), Region::new(0, 16, 16, 16)]);
+        assert_eq!(run.durations, vec![0.1, 0.2]);
+
+        let array = r#"{ "frames": [ { "filename": "a", "frame": { "x": 1, "y": 2, "w": 3, "h": 4 } } ], "meta": { "image": "a.png" } }"#;
+        assert_eq!(parse_sheet(array).unwrap().frames, vec![("a".to_string(), Region::new(1, 2, 3, 4))]);
+        let rotated = r#"{ "frames": [ { "frame": { "x": 0, "y": 0, "w": 1, "h": 1 }, "rotated": true } ], "meta": { "image": "a.png" } }"#;
+        assert!(parse_sheet(rotated).is_err());
+    }
+
+    #[test]
+    fn clips_loop_stop_and_bounce() {
+        let frames: Vec<Region> = (0..3).map(|index| Region::new(index * 8, 0, 8, 8)).collect();
+        let looping = AnimationClip::new(frames.clone(), 0.1, PlayMode::Loop);
+        assert_eq!([looping.frame_at(0.05), looping.frame_at(0.15), looping.frame_at(0.25), looping.frame_at(0.35)], [0, 1, 2, 0]);
+        let once = AnimationClip::new(frames.clone(), 0.1, PlayMode::Once);
+        assert_eq!(once.frame_at(10.0), 2);
+        let ping_pong = AnimationClip::new(frames, 0.1, PlayMode::PingPong);
+        assert!((ping_pong.duration() - 0.4).abs() < 1e-6);
+        assert_eq!([ping_pong.frame_at(0.25), ping_pong.frame_at(0.35), ping_pong.frame_at(0.45)], [2, 1, 0]);
+
+        let mut animation = SpriteAnimation::new(once);
+        animation.speed = 2.0;
+        animation.advance(0.1);
+        assert_eq!(animation.get_frame_index(), 2);
+        assert!(!animation.is_finished());
+        animation.advance(0.1);
+        assert!(animation.is_finished());
+    }
+
+    #[test]
+    fn animations_update_the_sprite_region() {
+        let mut storage = ComponentStorageManager::new();
+        let id = storage.spawn();
+        storage.insert(id, Sprite::new(Texture::fake(1, 2, 1)));
+        let frames = vec![Region::new(0, 0, 1, 1), Region::new(1, 0, 1, 1)];
+        storage.insert(id, SpriteAnimation::new(AnimationClip::new(frames, 0.5, PlayMode::Loop)));
+        update_animations(&mut storage, 0.75);
+        assert_eq!(storage.get::<Sprite>(id).unwrap().region, Some(Region::new(1, 0, 1, 1)));
+    }
+
+    #[test]
+    fn corners_follow_pivot_flip_and_rotation() {
+        let texture = Texture::fake(1, 1, 1);
+        let mut sprite = Sprite::new(texture).with_pivot(Vector2::new(0.0, 0.0));
+        sprite.region = Some(Region::new(0, 0, 1, 1));
+        let mut transform = transform_at(10.0, 20.0);
+        transform.scale = Vector3::new(32.0, 16.0, 1.0);
+        let corners = sprite.corners(&transform);
+        assert_eq!(corners[0], ([10.0, 20.0], [0.0, 1.0]));
+        assert_eq!(corners[2], ([42.0, 36.0], [1.0, 0.0]));
+
+        let flipped = sprite.with_flip(true, false).corners(&transform);
+        assert_eq!(flipped[0].1, [1.0, 1.0]);
+
+        transform.rotation = Quaternion::from_angle_z(Deg(90.0));
+        let rotated = sprite.corners(&transform);
+        assert!((rotated[1].0[0] - 10.0).abs() < 1e-4 && (rotated[1].0[1] - 52.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn batches_follow_layers_and_textures() {
+        let (first, second) = (Texture::fake(2, 8, 8), Texture::fake(1, 8, 8));
+        let sprites = vec![
+            (Sprite::new(first).with_layer(1, 0), transform_at(0.0, 0.0)),
+            (Sprite::new(second), transform_at(0.0, 0.0)),
+            (Sprite::new(first), transform_at(0.0, 0.0)),
+            (Sprite::new(second).with_layer(0, 5), transform_at(0.0, 0.0)),
+        ];
+        let (vertices, batches) = build_batches(sprites);
+        assert_eq!(vertices.len(), 24);
+        let ids: Vec<(i32, gl::types::GLuint, usize)> = batches.iter().map(|(layer, texture, count)| (*layer, texture.get_id(), *count)).collect();
+        //The layer 0 sprites of order 0 are grouped by texture, the order 5 sprite comes next, then layer 1
+        assert_eq!(ids, vec![(0, 1, 6), (0, 2, 6), (0, 1, 6), (1, 2, 6)]);
+    }
+
+    #[test]
+    fn chunks_out_of_view_are_culled() {
+        let bounds = (Vector2::new(0.0, -256.0), Vector2::new(256.0, 0.0));
+        let view = (Vector2::new(-400.0, -300.0), Vector2::new(400.0, 300.0));
+        assert!(is_in_view(&Matrix4::identity(), bounds, view));
+        assert!(!is_in_view(&Matrix4::from_translation(Vector3::new(500.0, 0.0, 0.0)), bounds, view));
+        assert!(is_in_view(&Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0), bounds, view));
+    }
+
+    #[test]
+    fn camera_2d_maps_pixels() {
+        let camera = Camera2D::new(Vector2::new(100.0, 50.0), 2.0);
+        assert_eq!(camera.screen_to_world(Vector2::new(400.0, 300.0), 800.0, 600.0), Vector2::new(100.0, 50.0));
+        assert_eq!(camera.screen_to_world(Vector2::new(800.0, 0.0), 800.0, 600.0), Vector2::new(300.0, 200.0));
+        let corner = camera.projection(800.0, 600.0) * Vector4::new(300.0, 200.0, 0.0, 1.0);
+        assert_eq!((corner.x, corner.y), (1.0, 1.0));
+    }
+}
@@ -0,0 +1,316 @@
+use cgmath::{Matrix4, SquareMatrix, Vector2, Vector3, InnerSpace};
+use crate::graphics::shader::Shader;
+use crate::graphics::post_process::FULLSCREEN_VERT;
+
+
+/// Texture unit of the occlusion read by `ambientLight`, after the G-buffer ones.
+pub const AO_UNIT: u32 = 8;
+/// Size of the `samples` array of the occlusion shader.
+pub const MAX_SSAO_SAMPLES: usize = 64;
+const NOISE_SIZE: usize = 4;
+
+/// Fraction of the ambient light reaching each pixel, from the depth and the world space normals of the G-buffer.
+const SSAO_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec2 TexCoord;
+
+        layout (std140) uniform Frame {
+            mat4 view;
+            mat4 projection;
+            vec4 viewPos;
+            vec4 lightPos;
+            vec4 lightColor;
+        };
+        uniform sampler2D gNormal;
+        uniform sampler2D gDepth;
+        uniform sampler2D noiseTexture;
+        uniform mat4 inverseProjection;
+        uniform vec3 samples[64];
+        uniform int sampleCount;
+        uniform float radius;
+        uniform float bias;
+        uniform vec2 noiseScale;
+
+        vec3 viewPosition(vec2 uv)
+        {
+            vec4 position = inverseProjection * vec4(vec3(uv, texture(gDepth, uv).r) * 2.0 - 1.0, 1.0);
+            return position.xyz / position.w;
+        }
+
+        void main()
+        {
+            vec3 worldNormal = texture(gNormal, TexCoord).xyz;
+            // background and unlit surfaces
+            if (texture(gDepth, TexCoord).r == 1.0 || dot(worldNormal, worldNormal) < 0.01) {
+                FragColor = vec4(1.0);
+                return;
+            }
+            vec3 fragPos = viewPosition(TexCoord);
+            vec3 normal = normalize(mat3(view) * worldNormal);
+            vec3 randomVec = texture(noiseTexture, TexCoord * noiseScale).xyz;
+            vec3 tangent = normalize(randomVec - normal * dot(randomVec, normal));
+            mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+            float occlusion = 0.0;
+            for (int i = 0; i < sampleCount; i++) {
+                vec3 samplePos = fragPos + tbn * samples[i] * radius;
+                vec4 offset = projection * vec4(samplePos, 1.0);
+                vec2 sampleUv = offset.xy / offset.w * 0.5 + 0.5;
+                float sampleDepth = viewPosition(sampleUv).z;
+                float rangeCheck = smoothstep(0.0, 1.0, radius / abs(fragPos.z - sampleDepth));
+                occlusion += (sampleDepth >= samplePos.z + bias ? 1.0 : 0.0) * rangeCheck;
+            }
+            FragColor = vec4(vec3(1.0 - occlusion / float(sampleCount)), 1.0);
+        }
+    "#;
+
+/// Box blur hiding the noise pattern of the occlusion.
+const BLUR_FRAG: &str = r#"
+        #version 330 core
+        out vec4 FragColor;
+        in vec2 TexCoord;
+
+        uniform sampler2D occlusion;
+        uniform int blurRadius;
+
+        void main()
+        {
+            vec2 texel = 1.0 / vec2(textureSize(occlusion, 0));
+            float result = 0.0;
+            for (int x = -blurRadius; x <= blurRadius; x++)
+                for (int y = -blurRadius; y <= blurRadius; y++)
+                    result += texture(occlusion, TexCoord + vec2(x, y) * texel).r;
+            float size = float(2 * blurRadius + 1);
+            FragColor = vec4(vec3(result / (size * size)), 1.0);
+        }
+    "#;
+
+/// Element `index` of the Halton low discrepancy sequence of `base`, in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Points of the hemisphere around +Z, denser close to the center.
+fn sample_kernel(count: usize) -> Vec<Vector3<f32>> {
+    (0..count).map(|i| {
+        let index = i as u32 + 1;
+        let direction = Vector3::new(halton(index, 2) * 2.0 - 1.0, halton(index, 3) * 2.0 - 1.0, halton(index, 5));
+        let direction = if direction.magnitude2() > 1e-6 { direction.normalize() } else { Vector3::unit_z() };
+        let scale = i as f32 / count as f32;
+        direction * (0.1 + 0.9 * scale * scale) * halton(index, 7).max(0.1)
+    }).collect()
+}
+
+/// Random rotations around the normal, tiled over the screen.
+fn noise_vectors() -> Vec<[f32; 3]> {
+    (0..(NOISE_SIZE * NOISE_SIZE) as u32).map(|i| [halton(i + 1, 2) * 2.0 - 1.0, halton(i + 1, 3) * 2.0 - 1.0, 0.0]).collect()
+}
+
+struct OcclusionTarget {
+    fbo: gl::types::GLuint,
+    texture: gl::types::GLuint,
+}
+
+impl OcclusionTarget {
+    fn new(width: i32, height: i32) -> Self {
+        let mut target = Self { fbo: 0, texture: 0 };
+        unsafe {
+            gl::GenFramebuffers(1, &mut target.fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+            gl::GenTextures(1, &mut target.texture);
+            gl::BindTexture(gl::TEXTURE_2D, target.texture);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, width, height, 0, gl::RED, gl::UNSIGNED_BYTE, std::ptr::null());
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, target.texture, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        target
+    }
+
+    fn delete(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+struct SsaoResources {
+    ssao_shader: Shader,
+    blur_shader: Shader,
+    noise: gl::types::GLuint,
+    vao: gl::types::GLuint,
+    /// Raw then blurred occlusion.
+    targets: [OcclusionTarget; 2],
+    size: (i32, i32),
+}
+
+/// Screen-space ambient occlusion darkening the ambient light of the lit materials in creases and contact areas.
+/// Disabled by default, the occlusion is computed from the G-buffer in both render paths.
+pub struct Ssao {
+    pub enabled: bool,
+    /// World space distance around a pixel searched for occluders.
+    pub radius: f32,
+    /// Depth difference ignored, avoiding flat surfaces occluding themselves.
+    pub bias: f32,
+    /// Half size in pixels of the blur applied to the occlusion, 0 disables it.
+    pub blur_radius: i32,
+    kernel: Vec<Vector3<f32>>,
+    resources: Option<SsaoResources>,
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ssao {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            radius: 0.5,
+            bias: 0.025,
+            blur_radius: 2,
+            kernel: sample_kernel(16),
+            resources: None,
+        }
+    }
+
+    pub fn get_sample_count(&self) -> usize {
+        self.kernel.len()
+    }
+
+    /// Number of depth samples per pixel, clamped between 1 and `MAX_SSAO_SAMPLES`.
+    pub fn set_sample_count(&mut self, count: usize) {
+        self.kernel = sample_kernel(count.clamp(1, MAX_SSAO_SAMPLES));
+    }
+
+    /// Renders the occlusion of the G-buffer `normal` and `depth` textures and binds it on `AO_UNIT`.
+    /// The framebuffer is left to the caller to restore.
+    pub(crate) fn render(&mut self, normal: gl::types::GLuint, depth: gl::types::GLuint, width: i32, height: i32, projection: Matrix4<f32>) {
+        if self.resources.is_none() {
+            self.resources = Some(Self::create_resources(width, height));
+        }
+        let resources = self.resources.as_mut().unwrap();
+        if resources.size != (width, height) {
+            for target in resources.targets.iter_mut() {
+                target.delete();
+            }
+            resources.targets = [OcclusionTarget::new(width, height), OcclusionTarget::new(width, height)];
+            resources.size = (width, height);
+        }
+
+        let shader = &resources.ssao_shader;
+        shader.use_program();
+        shader.set_int("gNormal", 0);
+        shader.set_int("gDepth", 1);
+        shader.set_int("noiseTexture", 2);
+        shader.set_mat4("inverseProjection", projection.invert().unwrap_or_else(Matrix4::identity));
+        for (i, sample) in self.kernel.iter().enumerate() {
+            shader.set_vec3(&format!("samples[{}]", i), *sample);
+        }
+        shader.set_int("sampleCount", self.kernel.len() as i32);
+        shader.set_float("radius", self.radius);
+        shader.set_float("bias", self.bias);
+        shader.set_vec2("noiseScale", Vector2::new(width as f32 / NOISE_SIZE as f32, height as f32 / NOISE_SIZE as f32));
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::BLEND);
+            gl::Disable(gl::CULL_FACE);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, resources.targets[0].fbo);
+            gl::Viewport(0, 0, width, height);
+            for (unit, texture) in [normal, depth, resources.noise].iter().enumerate() {
+                gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                gl::BindTexture(gl::TEXTURE_2D, *texture);
+            }
+            gl::BindVertexArray(resources.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+
+        let mut result = resources.targets[0].texture;
+        if self.blur_radius > 0 {
+            resources.blur_shader.use_program();
+            resources.blur_shader.set_int("occlusion", 0);
+            resources.blur_shader.set_int("blurRadius", self.blur_radius);
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, resources.targets[1].fbo);
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, result);
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+            }
+            result = resources.targets[1].texture;
+        }
+        unsafe {
+            gl::BindVertexArray(0);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::ActiveTexture(gl::TEXTURE0 + AO_UNIT);
+            gl::BindTexture(gl::TEXTURE_2D, result);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+
+    fn create_resources(width: i32, height: i32) -> SsaoResources {
+        let mut resources = SsaoResources {
+            ssao_shader: Shader::new(FULLSCREEN_VERT, SSAO_FRAG),
+            blur_shader: Shader::new(FULLSCREEN_VERT, BLUR_FRAG),
+            noise: 0,
+            vao: 0,
+            targets: [OcclusionTarget::new(width, height), OcclusionTarget::new(width, height)],
+            size: (width, height),
+        };
+        let noise = noise_vectors();
+        unsafe {
+            gl::GenVertexArrays(1, &mut resources.vao);
+            gl::GenTextures(1, &mut resources.noise);
+            gl::BindTexture(gl::TEXTURE_2D, resources.noise);
+            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGB16F as i32, NOISE_SIZE as i32, NOISE_SIZE as i32, 0, gl::RGB, gl::FLOAT, noise.as_ptr() as *const gl::types::GLvoid);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        }
+        resources
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_fills_the_hemisphere() {
+        let kernel = sample_kernel(32);
+        assert_eq!(kernel.len(), 32);
+        assert!(kernel.iter().all(|sample| sample.z >= 0.0 && sample.magnitude() <= 1.0));
+        //Samples get further from the center along the kernel
+        assert!(kernel[0].magnitude() < kernel[31].magnitude());
+    }
+
+    #[test]
+    fn sample_count_is_clamped() {
+        let mut ssao = Ssao::new();
+        ssao.set_sample_count(0);
+        assert_eq!(ssao.get_sample_count(), 1);
+        ssao.set_sample_count(1000);
+        assert_eq!(ssao.get_sample_count(), MAX_SSAO_SAMPLES);
+    }
+
+    #[test]
+    fn halton_sequence() {
+        assert_eq!([1, 2, 3].map(|i| halton(i, 2)), [0.5, 0.25, 0.75]);
+        assert!(noise_vectors().iter().all(|vector| vector[2] == 0.0 && vector[0].abs() <= 1.0));
+    }
+}
@@ -1,22 +1,66 @@
 use crate::graphics::{Index, ComponentStorageManager};
 use crate::graphics::inputs::InputSystem;
+use crate::graphics::commands::Commands;
+use crate::graphics::entity::EntityBuilder;
+use crate::graphics::events::EntityEvent;
+use crate::graphics::debug_draw::DebugDraw;
+use crate::graphics::text::TextDraw;
+use crate::graphics::hud::Hud;
 use std::collections::HashMap;
 
 
-pub type GameData<'a> = (Index, &'a mut ComponentStorageManager, &'a InputSystem);
+pub struct GameData<'a> {
+    pub id: Index,
+    pub storage: &'a mut ComponentStorageManager,
+    pub input: &'a InputSystem,
+    pub commands: &'a mut Commands,
+}
+
+impl<'a> GameData<'a> {
+    /// Allocates the entity right away, its components are added when the commands are applied.
+    pub fn spawn(&mut self, builder: EntityBuilder) -> Index {
+        let id = self.storage.spawn();
+        self.storage.get_mut_events().send(EntityEvent::Spawned(id));
+        self.commands.build(id, builder);
+        id
+    }
+
+    /// Lines drawn over the scene this frame, e.g. `data.debug_draw().line(a, b, color)`.
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        self.storage.get_mut_debug_draw()
+    }
+
+    /// Text drawn this frame, e.g. `data.text().screen(&font, "Score", position, style)`.
+    pub fn text(&mut self) -> &mut TextDraw {
+        self.storage.get_mut_text()
+    }
+
+    /// Immediate-mode UI of the frame, e.g. `egui::Window::new("Inspector").show(data.ui(), |ui| ...)`.
+    pub fn ui(&self) -> &egui::Context {
+        self.storage.get_ui().get_context()
+    }
 
-pub trait SceneUpdate {
-    fn update(&self, data: &mut ComponentStorageManager);
+    /// Widgets of the HUD, their clicks are read as `WidgetEvent`.
+    pub fn hud(&mut self) -> &mut Hud {
+        self.storage.get_mut_hud()
+    }
 }
 
 pub trait EntityState {
-    fn on_create(&mut self, data: &mut ComponentStorageManager);
+    /// Called on the first frame following the registration of the state.
+    fn on_create(&mut self, data: GameData);
     fn on_update(&mut self, data: GameData, delta: f32);
-    fn on_delete(&mut self, data: &mut ComponentStorageManager);
+    /// Called when the entity is despawned.
+    fn on_delete(&mut self, data: GameData);
+}
+
+struct StateEntry {
+    states: Box<dyn EntityState>,
+    created: bool,
 }
 
 pub struct StateSystem {
-    states_manager: HashMap<Index, Box<EntityState>>
+    states_manager: HashMap<Index, Vec<StateEntry>>
 }
 
 impl StateSystem {
@@ -26,17 +70,95 @@ impl StateSystem {
         }
     }
 
-    pub fn set_entity_states<T: EntityState>(&mut self, id: Index, states: T)
-    where
-        T: 'static,
-    {
-        self.states_manager.insert(id, Box::new(states));
+    /// Adds a behaviour to the entity, behaviours of an entity run in the order they were added.
+    pub fn add_entity_states<T: EntityState + 'static>(&mut self, id: Index, states: T) {
+        self.add_boxed_entity_states(id, Box::new(states));
+    }
+
+    pub fn add_boxed_entity_states(&mut self, id: Index, states: Box<dyn EntityState>) {
+        self.states_manager.entry(id).or_default().push(StateEntry { states, created: false });
     }
 
-    pub fn run_update_state(&mut self, data: &mut ComponentStorageManager, input: &InputSystem, delta: f32) {
-        for (id, states) in self.states_manager.iter_mut() {
-            let game_data: GameData = (*id, data, input);
-            states.on_update(game_data, delta);
+    pub fn count_entity_states(&self, id: Index) -> usize {
+        self.states_manager.get(&id).map_or(0, |entries| entries.len())
+    }
+
+    pub fn remove_entity_states(&mut self, id: Index, data: &mut ComponentStorageManager, input: &InputSystem, commands: &mut Commands) {
+        for mut entry in self.states_manager.remove(&id).unwrap_or_default() {
+            if entry.created {
+                entry.states.on_delete(GameData { id, storage: data, input, commands });
+            }
         }
     }
-}
\ No newline at end of file
+
+    pub fn run_update_state(&mut self, data: &mut ComponentStorageManager, input: &InputSystem, commands: &mut Commands, delta: f32) {
+        for (id, entries) in self.states_manager.iter_mut() {
+            for entry in entries.iter_mut() {
+                if !entry.created {
+                    entry.created = true;
+                    entry.states.on_create(GameData { id: *id, storage: data, input, commands });
+                }
+                let game_data = GameData { id: *id, storage: data, input, commands };
+                entry.states.on_update(game_data, delta);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    impl EntityState for Recorder {
+        fn on_create(&mut self, _data: GameData) {
+            self.1.lock().unwrap().push(format!("{} create", self.0));
+        }
+
+        fn on_update(&mut self, data: GameData, _delta: f32) {
+            self.1.lock().unwrap().push(format!("{} update", self.0));
+            data.commands.despawn(data.id);
+        }
+
+        fn on_delete(&mut self, _data: GameData) {
+            self.1.lock().unwrap().push(format!("{} delete", self.0));
+        }
+    }
+
+    #[test]
+    fn lifecycle_hooks_are_called_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut storage = ComponentStorageManager::new();
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        let mut states = StateSystem::new();
+        let id = storage.spawn();
+        states.add_entity_states(id, Recorder("a", calls.clone()));
+        states.run_update_state(&mut storage, &input, &mut commands, 0.0);
+        states.run_update_state(&mut storage, &input, &mut commands, 0.0);
+        assert_eq!(commands.take().len(), 2);
+        states.remove_entity_states(id, &mut storage, &input, &mut commands);
+        states.remove_entity_states(id, &mut storage, &input, &mut commands);
+        assert_eq!(*calls.lock().unwrap(), vec!["a create", "a update", "a update", "a delete"]);
+    }
+
+    #[test]
+    fn behaviours_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut storage = ComponentStorageManager::new();
+        let input = InputSystem::new();
+        let mut commands = Commands::new();
+        let mut states = StateSystem::new();
+        let id = storage.spawn();
+        states.add_entity_states(id, Recorder("a", calls.clone()));
+        states.add_entity_states(id, Recorder("b", calls.clone()));
+        assert_eq!(states.count_entity_states(id), 2);
+        states.run_update_state(&mut storage, &input, &mut commands, 0.0);
+        states.remove_entity_states(id, &mut storage, &input, &mut commands);
+        assert_eq!(states.count_entity_states(id), 0);
+        assert_eq!(*calls.lock().unwrap(), vec!["a create", "a update", "b create", "b update", "a delete", "b delete"]);
+    }
+}
@@ -0,0 +1,697 @@
+extern crate ab_glyph;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ab_glyph::{Font as _, FontVec, PxScale, ScaleFont};
+use cgmath::{Matrix4, Vector2, Vector3, Vector4, InnerSpace};
+use crate::graphics::camera::Camera;
+use crate::graphics::shader::Shader;
+
+
+/// Pixel size of the glyphs in the atlas, text of any size is scaled from it.
+pub const SDF_FONT_SIZE: f32 = 48.0;
+/// Distance in atlas pixels covered by the distance field on each side of the outlines.
+pub const SDF_SPREAD: f32 = 6.0;
+const ATLAS_WIDTH: usize = 512;
+/// Drawn for the characters missing from the atlas.
+const FALLBACK_CHAR: char = '?';
+const INF: f32 = 1e20;
+
+static NEXT_FONT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Printable ASCII and Latin-1 characters, put in the atlas of every font.
+fn default_charset() -> impl Iterator<Item = char> {
+    (' '..='~').chain('\u{a0}'..='\u{ff}')
+}
+
+/// Squared distance transform of a row or column, `f` holds 0 on the features and `INF` elsewhere.
+/// Felzenszwalb and Huttenlocher, "Distance Transforms of Sampled Functions".
+fn distance_transform_1d(f: &[f32], d: &mut [f32]) {
+    let n = f.len();
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0f32; n + 1];
+    let mut k = 0;
+    z[0] = -INF;
+    z[1] = INF;
+    for q in 1..n {
+        let qf = q as f32;
+        let mut s;
+        loop {
+            let vk = v[k] as f32;
+            s = ((f[q] + qf * qf) - (f[v[k]] + vk * vk)) / (2.0 * qf - 2.0 * vk);
+            if s <= z[k] && k > 0 {
+                k -= 1;
+            } else {
+                break;
+            }
+        }
+        if s <= z[k] {
+            //Only reached with k == 0, the new parabola hides the first one
+            v[0] = q;
+            z[1] = INF;
+            continue;
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = INF;
+    }
+    k = 0;
+    for (q, distance) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - v[k] as f32;
+        *distance = offset * offset + f[v[k]];
+    }
+}
+
+fn distance_transform_2d(grid: &mut [f32], width: usize, height: usize) {
+    let mut column = vec![0.0; height];
+    let mut result = vec![0.0; width.max(height)];
+    for x in 0..width {
+        for y in 0..height {
+            column[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&column, &mut result[..height]);
+        for y in 0..height {
+            grid[y * width + x] = result[y];
+        }
+    }
+    for y in 0..height {
+        let row = grid[y * width..(y + 1) * width].to_vec();
+        distance_transform_1d(&row, &mut grid[y * width..(y + 1) * width]);
+    }
+}
+
+/// Signed distance field of a coverage bitmap, 0.5 on the outlines and 1 at `spread` pixels inside.
+/// Partially covered pixels place the outline between pixel centers, as in Mapbox's TinySDF.
+fn signed_distance_field(coverage: &[f32], width: usize, height: usize, spread: f32) -> Vec<u8> {
+    let mut outer: Vec<f32> = coverage.iter().map(|&a| match a {
+        a if a >= 1.0 => 0.0,
+        a if a <= 0.0 => INF,
+        a => (0.5 - a).max(0.0).powi(2),
+    }).collect();
+    let mut inner: Vec<f32> = coverage.iter().map(|&a| match a {
+        a if a >= 1.0 => INF,
+        a if a <= 0.0 => 0.0,
+        a => (a - 0.5).max(0.0).powi(2),
+    }).collect();
+    distance_transform_2d(&mut outer, width, height);
+    distance_transform_2d(&mut inner, width, height);
+    outer.iter().zip(inner.iter()).map(|(outer, inner)| {
+        let distance = outer.sqrt() - inner.sqrt();
+        ((0.5 - distance / (2.0 * spread)).clamp(0.0, 1.0) * 255.0).round() as u8
+    }).collect()
+}
+
+/// Placement of a glyph in the atlas, distances in atlas pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphInfo {
+    /// Top left corner of the glyph quad from the pen position on the baseline, y down.
+    pub offset: Vector2<f32>,
+    pub size: Vector2<f32>,
+    pub uv_min: Vector2<f32>,
+    pub uv_max: Vector2<f32>,
+    pub advance: f32,
+}
+
+/// TrueType or OpenType font rasterized once to a signed distance field atlas, sharp at any size.
+pub struct Font {
+    id: usize,
+    glyphs: HashMap<char, GlyphInfo>,
+    kerning: HashMap<(char, char), f32>,
+    /// Distance from the top of a line to its baseline.
+    ascent: f32,
+    line_height: f32,
+    atlas: Vec<u8>,
+    atlas_size: (usize, usize),
+}
+
+impl Font {
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::from_bytes(data).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, String> {
+        let font = FontVec::try_from_vec(data).map_err(|err| err.to_string())?;
+        let scaled = font.as_scaled(PxScale::from(SDF_FONT_SIZE));
+        let padding = SDF_SPREAD.ceil() as usize;
+
+        //Glyphs are packed in rows of the atlas width
+        let mut glyphs = HashMap::new();
+        let mut bitmaps = Vec::new();
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for character in default_charset() {
+            let id = font.glyph_id(character);
+            if id.0 == 0 && character != ' ' {
+                continue;
+            }
+            let mut info = GlyphInfo {
+                offset: Vector2::new(0.0, 0.0),
+                size: Vector2::new(0.0, 0.0),
+                uv_min: Vector2::new(0.0, 0.0),
+                uv_max: Vector2::new(0.0, 0.0),
+                advance: scaled.h_advance(id),
+            };
+            if let Some(outline) = font.outline_glyph(id.with_scale(SDF_FONT_SIZE)) {
+                let bounds = outline.px_bounds();
+                let (width, height) = (bounds.width() as usize + 2 * padding, bounds.height() as usize + 2 * padding);
+                let mut coverage = vec![0.0; width * height];
+                outline.draw(|px, py, alpha| coverage[(py as usize + padding) * width + px as usize + padding] = alpha);
+                if x + width > ATLAS_WIDTH {
+                    x = 0;
+                    y += row_height;
+                    row_height = 0;
+                }
+                info.offset = Vector2::new(bounds.min.x - padding as f32, bounds.min.y - padding as f32);
+                info.size = Vector2::new(width as f32, height as f32);
+                bitmaps.push((character, x, y, width, height, signed_distance_field(&coverage, width, height, SDF_SPREAD)));
+                x += width;
+                row_height = row_height.max(height);
+            }
+            glyphs.insert(character, info);
+        }
+
+        let atlas_height = (y + row_height).next_power_of_two();
+        let mut atlas = vec![0u8; ATLAS_WIDTH * atlas_height];
+        for (character, x, y, width, height, sdf) in bitmaps {
+            for row in 0..height {
+                atlas[(y + row) * ATLAS_WIDTH + x..(y + row) * ATLAS_WIDTH + x + width].copy_from_slice(&sdf[row * width..(row + 1) * width]);
+            }
+            let info = glyphs.get_mut(&character).unwrap();
+            info.uv_min = Vector2::new(x as f32 / ATLAS_WIDTH as f32, y as f32 / atlas_height as f32);
+            info.uv_max = Vector2::new((x + width) as f32 / ATLAS_WIDTH as f32, (y + height) as f32 / atlas_height as f32);
+        }
+
+        let mut kerning = HashMap::new();
+        let characters: Vec<char> = glyphs.keys().copied().collect();
+        for first in characters.iter() {
+            for second in characters.iter() {
+                let kern = scaled.kern(font.glyph_id(*first), font.glyph_id(*second));
+                if kern != 0.0 {
+                    kerning.insert((*first, *second), kern);
+                }
+            }
+        }
+
+        Ok(Self {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            glyphs,
+            kerning,
+            ascent: scaled.ascent(),
+            line_height: scaled.height() + scaled.line_gap(),
+            atlas,
+            atlas_size: (ATLAS_WIDTH, atlas_height),
+        })
+    }
+
+    pub fn get_glyph(&self, character: char) -> Option<&GlyphInfo> {
+        self.glyphs.get(&character).or_else(|| self.glyphs.get(&FALLBACK_CHAR))
+    }
+
+    /// Height of a line of text of size 1.
+    pub fn line_height(&self) -> f32 {
+        self.line_height / SDF_FONT_SIZE
+    }
+
+    /// Width of a single line of text of size 1.
+    fn line_width(&self, line: &str) -> f32 {
+        let mut width = 0.0;
+        let mut previous = None;
+        for character in line.chars() {
+            width += self.advance(previous, character);
+            previous = Some(character);
+        }
+        width / SDF_FONT_SIZE
+    }
+
+    fn advance(&self, previous: Option<char>, character: char) -> f32 {
+        let kerning = previous.and_then(|previous| self.kerning.get(&(previous, character))).copied().unwrap_or(0.0);
+        self.get_glyph(character).map_or(0.0, |glyph| glyph.advance) + kerning
+    }
+
+    /// Lines of the text once wrapped between words to fit `max_width`, for a text of size 1.
+    fn wrap(&self, text: &str, max_width: Option<f32>) -> Vec<String> {
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let max_width = match max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.to_string());
+                    continue;
+                },
+            };
+            let mut line = String::new();
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if !line.is_empty() && self.line_width(&candidate) > max_width {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    /// Quads of the glyphs, from the top left corner of the text with y down, in the unit of `style.size`.
+    pub fn layout(&self, text: &str, style: &TextStyle) -> TextLayout {
+        let lines = self.wrap(text, style.max_width.map(|max_width| max_width / style.size));
+        let widths: Vec<f32> = lines.iter().map(|line| self.line_width(line) * style.size).collect();
+        let width = style.max_width.unwrap_or_else(|| widths.iter().copied().fold(0.0, f32::max));
+        let scale = style.size / SDF_FONT_SIZE;
+        let mut quads = Vec::new();
+        for (i, (line, line_width)) in lines.iter().zip(widths.iter()).enumerate() {
+            let baseline = (self.ascent + i as f32 * self.line_height) * scale;
+            let mut pen = (width - line_width) * style.align.factor();
+            let mut previous = None;
+            for character in line.chars() {
+                let kerning = previous.and_then(|previous| self.kerning.get(&(previous, character))).copied().unwrap_or(0.0);
+                pen += kerning * scale;
+                if let Some(glyph) = self.get_glyph(character) {
+                    if glyph.size.x > 0.0 {
+                        let min = Vector2::new(pen + glyph.offset.x * scale, baseline + glyph.offset.y * scale);
+                        quads.push(GlyphQuad { min, max: min + glyph.size * scale, uv_min: glyph.uv_min, uv_max: glyph.uv_max });
+                    }
+                    pen += glyph.advance * scale;
+                }
+                previous = Some(character);
+            }
+        }
+        TextLayout { quads, size: Vector2::new(width, lines.len() as f32 * self.line_height * scale) }
+    }
+
+    /// Size of the text block laid out with `style`.
+    pub fn measure(&self, text: &str, style: &TextStyle) -> Vector2<f32> {
+        self.layout(text, style).size
+    }
+
+    pub(crate) fn get_id(&self) -> usize {
+        self.id
+    }
+
+    /// Font with a 24 pixels advance for every letter, without a GPU or a font file.
+    #[cfg(test)]
+    pub(crate) fn monospace() -> Self {
+        let mut glyphs = HashMap::new();
+        for character in ('a'..='z').chain([' ', '?']) {
+            let size = if character == ' ' { 0.0 } else { 20.0 };
+            glyphs.insert(character, GlyphInfo {
+                offset: Vector2::new(2.0, -30.0),
+                size: Vector2::new(size, size),
+                uv_min: Vector2::new(0.0, 0.0),
+                uv_max: Vector2::new(1.0, 1.0),
+                advance: 24.0,
+            });
+        }
+        Font { id: usize::MAX, glyphs, kerning: HashMap::new(), ascent: 36.0, line_height: 48.0, atlas: Vec::new(), atlas_size: (0, 0) }
+    }
+}
+
+/// Single channel texture of the distance field atlas of `font`.
+pub(crate) fn create_atlas_texture(font: &Font) -> gl::types::GLuint {
+    let mut texture = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R8 as i32, font.atlas_size.0 as i32, font.atlas_size.1 as i32, 0, gl::RED, gl::UNSIGNED_BYTE, font.atlas.as_ptr() as *const gl::types::GLvoid);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+    }
+    texture
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    fn factor(self) -> f32 {
+        match self {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::Right => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextStyle {
+    /// Font size, in pixels for screen text and in world units for world text.
+    pub size: f32,
+    pub color: Vector4<f32>,
+    pub align: TextAlign,
+    /// Width the lines are wrapped to, in the unit of `size`. The lines are aligned within it.
+    pub max_width: Option<f32>,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            align: TextAlign::Left,
+            max_width: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Vector4<f32>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphQuad {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+    pub uv_min: Vector2<f32>,
+    pub uv_max: Vector2<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub quads: Vec<GlyphQuad>,
+    /// Width and height of the text block.
+    pub size: Vector2<f32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TextPlacement {
+    Screen(Vector2<f32>),
+    World(Matrix4<f32>),
+    Billboard(Vector3<f32>),
+}
+
+struct TextItem {
+    font: Arc<Font>,
+    text: String,
+    style: TextStyle,
+    placement: TextPlacement,
+}
+
+/// Text drawn this frame. The position of a text is its top edge, at the left, center or right depending on the alignment.
+#[derive(Default)]
+pub struct TextDraw {
+    items: Vec<TextItem>,
+}
+
+impl TextDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Text at `position` pixels from the top left corner of the window, drawn over the post-processed scene.
+    pub fn screen(&mut self, font: &Arc<Font>, text: &str, position: Vector2<f32>, style: TextStyle) {
+        self.push(font, text, style, TextPlacement::Screen(position));
+    }
+
+    /// Text on the XY plane of `model`, reading along +X with +Y up.
+    pub fn world(&mut self, font: &Arc<Font>, text: &str, model: Matrix4<f32>, style: TextStyle) {
+        self.push(font, text, style, TextPlacement::World(model));
+    }
+
+    /// Text at `position` in the world, facing the camera.
+    pub fn billboard(&mut self, font: &Arc<Font>, text: &str, position: Vector3<f32>, style: TextStyle) {
+        self.push(font, text, style, TextPlacement::Billboard(position));
+    }
+
+    fn push(&mut self, font: &Arc<Font>, text: &str, style: TextStyle, placement: TextPlacement) {
+        self.items.push(TextItem { font: font.clone(), text: text.to_string(), style, placement });
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    /// Vertices of the screen or world texts, grouped by font.
+    fn build_vertices(&self, screen: bool, camera: &Camera) -> Vec<(Arc<Font>, Vec<TextVertex>)> {
+        let forward = camera.direction.normalize();
+        let world_up = if forward.y.abs() < 0.99 { Vector3::unit_y() } else { Vector3::unit_z() };
+        let right = forward.cross(world_up).normalize();
+        let up = right.cross(forward);
+        let mut batches: Vec<(Arc<Font>, Vec<TextVertex>)> = Vec::new();
+        for item in self.items.iter() {
+            //Text space has y down, world text has it up
+            let transform = match item.placement {
+                TextPlacement::Screen(_) if !screen => continue,
+                TextPlacement::World(_) | TextPlacement::Billboard(_) if screen => continue,
+                TextPlacement::Screen(position) => Matrix4::from_translation(position.extend(0.0)),
+                TextPlacement::World(model) => model * Matrix4::from_nonuniform_scale(1.0, -1.0, 1.0),
+                TextPlacement::Billboard(position) => Matrix4::from_cols(right.extend(0.0), (-up).extend(0.0), (-forward).extend(0.0), position.extend(1.0)),
+            };
+            let layout = item.font.layout(&item.text, &item.style);
+            let anchor = Vector2::new(layout.size.x * item.style.align.factor(), 0.0);
+            let vertices = match batches.iter_mut().find(|(font, _)| font.id == item.font.id) {
+                Some((_, vertices)) => vertices,
+                None => {
+                    batches.push((item.font.clone(), Vec::new()));
+                    &mut batches.last_mut().unwrap().1
+                },
+            };
+            for quad in layout.quads {
+                let corner = |x: f32, y: f32, u: f32, v: f32| {
+                    let position = transform * Vector4::new(x - anchor.x, y - anchor.y, 0.0, 1.0);
+                    TextVertex { position: position.truncate().into(), uv: [u, v], color: item.style.color.into() }
+                };
+                let top_left = corner(quad.min.x, quad.min.y, quad.uv_min.x, quad.uv_min.y);
+                let top_right = corner(quad.max.x, quad.min.y, quad.uv_max.x, quad.uv_min.y);
+                let bottom_left = corner(quad.min.x, quad.max.y, quad.uv_min.x, quad.uv_max.y);
+                let bottom_right = corner(quad.max.x, quad.max.y, quad.uv_max.x, quad.uv_max.y);
+                vertices.extend_from_slice(&[top_left, bottom_left, bottom_right, top_left, bottom_right, top_right]);
+            }
+        }
+        batches
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(C)]
+struct TextVertex {
+    position: [f32; 3],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Draws the texts of a `TextDraw`, keeping an atlas texture per font.
+pub(crate) struct TextRenderer {
+    shader: Shader,
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+    textures: HashMap<usize, gl::types::GLuint>,
+}
+
+impl TextRenderer {
+    pub(crate) fn new() -> Self {
+        let vert_source = r#"
+        #version 330 core
+        layout (location = 0) in vec3 aPos;
+        layout (location = 1) in vec2 aTexCoord;
+        layout (location = 2) in vec4 aColor;
+
+        out vec2 TexCoord;
+        out vec4 ourColor;
+
+        uniform mat4 viewProjection;
+
+        void main()
+        {
+            gl_Position = viewProjection * vec4(aPos, 1.0);
+            TexCoord = aTexCoord;
+            ourColor = aColor;
+        }
+    "#;
+        let frag_source = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        in vec2 TexCoord;
+        in vec4 ourColor;
+
+        uniform sampler2D atlas;
+
+        void main()
+        {
+            // the outline is at 0.5, smoothed over one screen pixel
+            float distance = texture(atlas, TexCoord).r;
+            float width = max(fwidth(distance), 0.0001);
+            float alpha = smoothstep(0.5 - width, 0.5 + width, distance);
+            if (alpha <= 0.0)
+                discard;
+            FragColor = vec4(ourColor.rgb, ourColor.a * alpha);
+        }
+    "#;
+        let mut renderer = Self {
+            shader: Shader::new(vert_source, frag_source),
+            vao: 0,
+            vbo: 0,
+            textures: HashMap::new(),
+        };
+        let stride = std::mem::size_of::<TextVertex>() as gl::types::GLint;
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::BindVertexArray(renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 3]>() as *const gl::types::GLvoid);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 5]>() as *const gl::types::GLvoid);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+        renderer
+    }
+
+    fn texture(&mut self, font: &Font) -> gl::types::GLuint {
+        *self.textures.entry(font.id).or_insert_with(|| create_atlas_texture(font))
+    }
+
+    /// Draws the world and billboard texts, depth tested against the scene.
+    pub(crate) fn draw_world(&mut self, text: &TextDraw, camera: &Camera) {
+        let batches = text.build_vertices(false, camera);
+        self.draw(batches, camera.projection() * camera.lookat());
+    }
+
+    /// Draws the screen texts over the bound framebuffer, sized like the viewport.
+    pub(crate) fn draw_screen(&mut self, text: &TextDraw, camera: &Camera) {
+        let batches = text.build_vertices(true, camera);
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let projection = cgmath::ortho(0.0, viewport[2] as f32, viewport[3] as f32, 0.0, -1.0, 1.0);
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+        self.draw(batches, projection);
+        unsafe { gl::Enable(gl::DEPTH_TEST) };
+    }
+
+    fn draw(&mut self, batches: Vec<(Arc<Font>, Vec<TextVertex>)>, view_projection: Matrix4<f32>) {
+        if batches.is_empty() {
+            return;
+        }
+        self.shader.use_program();
+        self.shader.set_mat4("viewProjection", view_projection);
+        self.shader.set_int("atlas", 0);
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        }
+        for (font, vertices) in batches {
+            let texture = self.texture(&font);
+            unsafe {
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(vertices.as_slice()) as gl::types::GLsizeiptr, vertices.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+                gl::DrawArrays(gl::TRIANGLES, 0, vertices.len() as i32);
+            }
+        }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindVertexArray(0);
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_wraps_between_words() {
+        let font = Font::monospace();
+        //Letters are half as wide as the size
+        let style = TextStyle::new(10.0).with_max_width(42.0);
+        let layout = font.layout("abc def ghi", &style);
+        assert_eq!(layout.quads.len(), 9);
+        assert_eq!(layout.size, Vector2::new(42.0, 20.0));
+        assert_eq!(font.wrap("abc def ghi", Some(4.2)), vec!["abc def", "ghi"]);
+        assert_eq!(font.wrap("one\ntwo", None), vec!["one", "two"]);
+        //The first letter of the second line starts the line
+        assert_eq!(layout.quads[6].min, Vector2::new(2.0 * 10.0 / 48.0, (36.0 + 48.0 - 30.0) * 10.0 / 48.0));
+    }
+
+    #[test]
+    fn alignment_and_fallback() {
+        let font = Font::monospace();
+        let right = font.layout("ab", &TextStyle::new(48.0).with_max_width(100.0).with_align(TextAlign::Right));
+        assert_eq!(right.quads[0].min.x, 100.0 - 48.0 + 2.0);
+        let centered = font.layout("ab", &TextStyle::new(48.0).with_max_width(100.0).with_align(TextAlign::Center));
+        assert_eq!(centered.quads[0].min.x, 26.0 + 2.0);
+        //Unknown characters are drawn as '?'
+        assert_eq!(font.measure("A!", &TextStyle::new(48.0)), Vector2::new(48.0, 48.0));
+    }
+
+    #[test]
+    fn signed_distance_of_a_square() {
+        let (width, height) = (16, 16);
+        let coverage: Vec<f32> = (0..width * height).map(|i| {
+            let (x, y) = (i % width, i / width);
+            if (4..12).contains(&x) && (4..12).contains(&y) { 1.0 } else { 0.0 }
+        }).collect();
+        let sdf = signed_distance_field(&coverage, width, height, 4.0);
+        assert!(sdf[8 * width + 8] > 128 && sdf[0] < 128);
+        assert_eq!(sdf[0], 0);
+        //Decreasing from the center of the square to the outside
+        assert!(sdf[8 * width + 8] >= sdf[8 * width + 5] && sdf[8 * width + 5] > sdf[8 * width + 2]);
+    }
+
+    #[test]
+    fn distance_transform_squares_distances() {
+        let f = [INF, 0.0, INF, INF, INF, 0.0];
+        let mut d = [0.0; 6];
+        distance_transform_1d(&f, &mut d);
+        assert_eq!(d, [1.0, 0.0, 1.0, 4.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn text_draw_batches_by_font() {
+        let font = Arc::new(Font::monospace());
+        let mut text = TextDraw::new();
+        text.screen(&font, "ab", Vector2::new(10.0, 10.0), TextStyle::new(48.0));
+        text.billboard(&font, "c d", Vector3::new(0.0, 1.0, 0.0), TextStyle::new(1.0).with_align(TextAlign::Center));
+        assert_eq!(text.len(), 2);
+        let camera = Camera::default();
+        let screen = text.build_vertices(true, &camera);
+        assert_eq!((screen.len(), screen[0].1.len()), (1, 12));
+        assert_eq!(screen[0].1[0].position, [12.0, 16.0, 0.0]);
+        assert_eq!(text.build_vertices(false, &camera)[0].1.len(), 12);
+        text.clear();
+        assert!(text.is_empty());
+    }
+}
@@ -0,0 +1,1171 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use cgmath::{SquareMatrix, Vector2, Vector4};
+use serde::{Deserialize, Serialize};
+use crate::ecs::Entity;
+use crate::graphics::ComponentStorageManager;
+use crate::graphics::shader::Texture;
+use crate::graphics::sprite::{Region, SpriteVertex};
+use crate::graphics::transform::Transform;
+
+
+/// Width and height of the chunks in tiles, a chunk is rebuilt as a whole when one of its tiles changes.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// Bits of the Tiled global tile ids holding the flips.
+const FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL: u32 = 0x1000_0000;
+
+/// Versions are unique across tilemaps so a replaced component never reuses the buffers of the previous one.
+static NEXT_CHUNK_VERSION: AtomicU64 = AtomicU64::new(1);
+
+/// Cell of a tile layer, showing a tile of a tile set.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tile {
+    pub tileset: usize,
+    /// Tile of the tile set, counted row by row from its top left corner.
+    pub index: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Swaps the x and y axes of the tile, applied before the other flips like in Tiled.
+    pub flip_diagonal: bool,
+}
+
+impl Tile {
+    pub fn new(tileset: usize, index: u32) -> Self {
+        Self {
+            tileset,
+            index,
+            flip_x: false,
+            flip_y: false,
+            flip_diagonal: false,
+        }
+    }
+
+    pub fn with_flip(mut self, flip_x: bool, flip_y: bool) -> Self {
+        self.flip_x = flip_x;
+        self.flip_y = flip_y;
+        self
+    }
+
+    pub fn with_diagonal_flip(mut self, flip_diagonal: bool) -> Self {
+        self.flip_diagonal = flip_diagonal;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct TileFlags {
+    /// Blocks movement, see `Tilemap::is_solid`.
+    pub collision: bool,
+    /// Set by `TileSet::set_animation`.
+    pub animated: bool,
+}
+
+/// Settings of one tile of a tile set.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TileData {
+    pub flags: TileFlags,
+    /// Tiles shown in turn with their duration in seconds.
+    animation: Vec<(u32, f32)>,
+    /// Custom properties set in Tiled.
+    pub properties: HashMap<String, String>,
+}
+
+impl TileData {
+    pub fn get_animation(&self) -> &[(u32, f32)] {
+        &self.animation
+    }
+
+    /// Tile shown `time` seconds after the start of the animation.
+    fn frame_at(&self, index: u32, time: f32) -> u32 {
+        let duration: f32 = self.animation.iter().map(|(_, duration)| duration).sum();
+        if duration <= 0.0 {
+            return index;
+        }
+        let mut time = time.rem_euclid(duration);
+        for (frame, frame_duration) in self.animation.iter() {
+            if time < *frame_duration {
+                return *frame;
+            }
+            time -= frame_duration;
+        }
+        self.animation[self.animation.len() - 1].0
+    }
+}
+
+/// Texture cut in tiles of the same size, with optional margin around the image and spacing between tiles.
+#[derive(Debug, Clone)]
+pub struct TileSet {
+    texture: Texture,
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    count: u32,
+    tiles: HashMap<u32, TileData>,
+}
+
+impl TileSet {
+    pub fn new(texture: Texture, tile_width: u32, tile_height: u32) -> Self {
+        let mut tileset = Self {
+            texture,
+            tile_width,
+            tile_height,
+            margin: 0,
+            spacing: 0,
+            columns: 0,
+            count: 0,
+            tiles: HashMap::new(),
+        };
+        tileset.compute_grid();
+        tileset
+    }
+
+    pub fn with_spacing(mut self, margin: u32, spacing: u32) -> Self {
+        self.margin = margin;
+        self.spacing = spacing;
+        self.compute_grid();
+        self
+    }
+
+    fn compute_grid(&mut self) {
+        let (width, height) = self.texture.get_size();
+        self.columns = tiles_per_line(width, self.tile_width, self.margin, self.spacing);
+        self.count = self.columns * tiles_per_line(height, self.tile_height, self.margin, self.spacing);
+    }
+
+    pub fn get_texture(&self) -> Texture {
+        self.texture
+    }
+
+    pub fn get_tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    pub fn get_count(&self) -> u32 {
+        self.count
+    }
+
+    /// Area of the tile `index` in the texture.
+    pub fn get_region(&self, index: u32) -> Region {
+        let columns = self.columns.max(1);
+        let x = self.margin + (index % columns) * (self.tile_width + self.spacing);
+        let y = self.margin + (index / columns) * (self.tile_height + self.spacing);
+        Region::new(x, y, self.tile_width, self.tile_height)
+    }
+
+    pub fn get_tile(&self, index: u32) -> Option<&TileData> {
+        self.tiles.get(&index)
+    }
+
+    pub fn get_flags(&self, index: u32) -> TileFlags {
+        self.tiles.get(&index).map(|tile| tile.flags).unwrap_or_default()
+    }
+
+    pub fn set_collision(&mut self, index: u32, collision: bool) {
+        self.tiles.entry(index).or_default().flags.collision = collision;
+    }
+
+    /// Shows the tiles of `frames` in turn in place of the tile `index`, each for its duration in seconds.
+    pub fn set_animation(&mut self, index: u32, frames: Vec<(u32, f32)>) {
+        let tile = self.tiles.entry(index).or_default();
+        tile.flags.animated = !frames.is_empty();
+        tile.animation = frames;
+    }
+
+    /// Tile drawn for the tile `index`, `time` seconds after the start of its animation.
+    fn frame_at(&self, index: u32, time: f32) -> u32 {
+        match self.tiles.get(&index) {
+            Some(tile) if tile.flags.animated => tile.frame_at(index, time),
+            _ => index,
+        }
+    }
+}
+
+/// Number of tiles fitting in `size` pixels of an image.
+fn tiles_per_line(size: u32, tile: u32, margin: u32, spacing: u32) -> u32 {
+    (size.saturating_sub(2 * margin) + spacing) / (tile + spacing).max(1)
+}
+
+/// Grid of tiles drawn over or under the other layers of its tilemap.
+#[derive(Debug, Clone)]
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    /// Sorting layer shared with the sprites, the tile layers are drawn before the sprites of their layer.
+    pub sorting_layer: i32,
+    tiles: Vec<Option<Tile>>,
+}
+
+impl TileLayer {
+    /// Cells row by row from the top left corner.
+    pub fn get_tiles(&self) -> &[Option<Tile>] {
+        &self.tiles
+    }
+}
+
+/// Mesh of a square of `CHUNK_SIZE` tiles of a layer.
+#[derive(Debug, Clone)]
+pub(crate) struct TileChunk {
+    pub(crate) layer: usize,
+    x: u32,
+    y: u32,
+    /// Vertices of the tiles of each tile set.
+    pub(crate) batches: Vec<(usize, Vec<SpriteVertex>)>,
+    /// Local bounds of the vertices.
+    pub(crate) bounds: (Vector2<f32>, Vector2<f32>),
+    animated: bool,
+    dirty: bool,
+    /// Changed by every rebuild.
+    pub(crate) version: u64,
+}
+
+/// Grid of tiles in layers, its top left corner at the entity `Transform`, one tile set pixel per world unit.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<TileSet>,
+    layers: Vec<TileLayer>,
+    chunks: Vec<TileChunk>,
+    time: f32,
+}
+
+impl Tilemap {
+    /// Empty map of `width` by `height` tiles of `tile_width` by `tile_height` pixels.
+    pub fn new(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            chunks: Vec::new(),
+            time: 0.0,
+        }
+    }
+
+    /// Loads a Tiled map saved as .tmx or .json, see `parse_tmx` and `parse_json` for the supported features.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let map = if path.ends_with(".tmx") {
+            parse_tmx(&content, dir)
+        } else {
+            parse_json(&content, dir)
+        }.map_err(|err| format!("{}: {}", path, err))?;
+        build_tilemap(map, |image| {
+            if image.is_file() {
+                Ok(Texture::from_file(&image.to_string_lossy()).with_nearest_filter())
+            } else {
+                Err(format!("image {} not found", image.display()))
+            }
+        }).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Width and height in tiles.
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn get_tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    pub fn add_tileset(&mut self, tileset: TileSet) -> usize {
+        self.tilesets.push(tileset);
+        self.tilesets.len() - 1
+    }
+
+    pub fn get_tileset(&self, index: usize) -> Option<&TileSet> {
+        self.tilesets.get(index)
+    }
+
+    /// The whole map is rebuilt after a change of tile set.
+    pub fn get_mut_tileset(&mut self, index: usize) -> Option<&mut TileSet> {
+        for chunk in self.chunks.iter_mut() {
+            chunk.dirty = true;
+        }
+        self.tilesets.get_mut(index)
+    }
+
+    /// Adds an empty layer drawn over the previous ones.
+    pub fn add_layer(&mut self, name: &str) -> usize {
+        let layer = self.layers.len();
+        self.layers.push(TileLayer {
+            name: name.to_string(),
+            visible: true,
+            opacity: 1.0,
+            sorting_layer: 0,
+            tiles: vec![None; (self.width * self.height) as usize],
+        });
+        let (columns, rows) = self.get_chunk_grid();
+        for index in 0..columns * rows {
+            self.chunks.push(TileChunk {
+                layer,
+                x: index % columns,
+                y: index / columns,
+                batches: Vec::new(),
+                bounds: (Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0)),
+                animated: false,
+                dirty: true,
+                version: 0,
+            });
+        }
+        layer
+    }
+
+    pub fn get_layers(&self) -> &[TileLayer] {
+        &self.layers
+    }
+
+    pub fn get_layer(&self, layer: usize) -> Option<&TileLayer> {
+        self.layers.get(layer)
+    }
+
+    /// Name, visibility, opacity and sorting layer of the layer, tiles are changed with `set_tile`.
+    pub fn get_mut_layer(&mut self, layer: usize) -> Option<&mut TileLayer> {
+        self.layers.get_mut(layer)
+    }
+
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn get_tile(&self, layer: usize, x: u32, y: u32) -> Option<Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.layers.get(layer).and_then(|layer| layer.tiles[(y * self.width + x) as usize])
+    }
+
+    /// Sets the tile at column `x` and row `y` from the top, only its chunk is rebuilt.
+    /// Returns false if the layer or the position is out of the map.
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, tile: Option<Tile>) -> bool {
+        if layer >= self.layers.len() || x >= self.width || y >= self.height {
+            return false;
+        }
+        let cell = &mut self.layers[layer].tiles[(y * self.width + x) as usize];
+        if *cell != tile {
+            *cell = tile;
+            let chunk = self.get_chunk_index(layer, x / CHUNK_SIZE, y / CHUNK_SIZE);
+            self.chunks[chunk].dirty = true;
+        }
+        true
+    }
+
+    /// Replaces every cell of a layer, returns false if the layer doesn't exist or `tiles` has another size.
+    pub fn set_tiles(&mut self, layer: usize, tiles: Vec<Option<Tile>>) -> bool {
+        if layer >= self.layers.len() || tiles.len() != (self.width * self.height) as usize {
+            return false;
+        }
+        self.layers[layer].tiles = tiles;
+        for chunk in self.chunks.iter_mut().filter(|chunk| chunk.layer == layer) {
+            chunk.dirty = true;
+        }
+        true
+    }
+
+    pub fn get_flags(&self, layer: usize, x: u32, y: u32) -> TileFlags {
+        self.get_tile(layer, x, y)
+            .and_then(|tile| self.tilesets.get(tile.tileset).map(|tileset| tileset.get_flags(tile.index)))
+            .unwrap_or_default()
+    }
+
+    /// True if a tile with collision is at column `x` and row `y` in any layer, false out of the map.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        (0..self.layers.len()).any(|layer| self.get_flags(layer, x as u32, y as u32).collision)
+    }
+
+    /// Column and row of the tile under the world `point`, for a map placed at `transform`.
+    pub fn world_to_tile(&self, transform: &Transform, point: Vector2<f32>) -> Option<(u32, u32)> {
+        let local = transform.calculate_local_transform().invert()? * Vector4::new(point.x, point.y, 0.0, 1.0);
+        let x = (local.x / self.tile_width as f32).floor();
+        let y = (-local.y / self.tile_height as f32).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return None;
+        }
+        Some((x as u32, y as u32))
+    }
+
+    /// True if showing the tile animations at `time` changes a frame of a built chunk.
+    pub(crate) fn frame_changes(&self, time: f32) -> bool {
+        self.chunks.iter().any(|chunk| chunk.animated) && self.tilesets.iter().any(|tileset| {
+            tileset.tiles.iter().any(|(index, tile)| tile.flags.animated && tile.frame_at(*index, self.time) != tile.frame_at(*index, time))
+        })
+    }
+
+    /// Shows the tile animations `time` seconds after their start, the chunks holding animated tiles are rebuilt when a frame changes.
+    pub(crate) fn set_time(&mut self, time: f32) {
+        if self.frame_changes(time) {
+            for chunk in self.chunks.iter_mut().filter(|chunk| chunk.animated) {
+                chunk.dirty = true;
+            }
+        }
+        self.time = time;
+    }
+
+    fn get_chunk_grid(&self) -> (u32, u32) {
+        (self.width.div_ceil(CHUNK_SIZE), self.height.div_ceil(CHUNK_SIZE))
+    }
+
+    fn get_chunk_index(&self, layer: usize, x: u32, y: u32) -> usize {
+        let (columns, rows) = self.get_chunk_grid();
+        layer * (columns * rows) as usize + (y * columns + x) as usize
+    }
+
+    pub(crate) fn get_chunks(&self) -> &[TileChunk] {
+        &self.chunks
+    }
+
+    pub(crate) fn needs_rebuild(&self) -> bool {
+        self.chunks.iter().any(|chunk| chunk.dirty)
+    }
+
+    /// Rebuilds the meshes of the changed chunks, returns how many were rebuilt.
+    pub(crate) fn rebuild_chunks(&mut self) -> usize {
+        let mut rebuilt = 0;
+        for index in 0..self.chunks.len() {
+            if !self.chunks[index].dirty {
+                continue;
+            }
+            let (batches, animated) = self.build_chunk(&self.chunks[index]);
+            let chunk = &mut self.chunks[index];
+            chunk.bounds = batches.iter().flat_map(|(_, vertices)| vertices.iter())
+                .fold(None, |bounds: Option<(Vector2<f32>, Vector2<f32>)>, vertex| {
+                    let position = Vector2::new(vertex.position[0], vertex.position[1]);
+                    Some(match bounds {
+                        Some((min, max)) => (Vector2::new(min.x.min(position.x), min.y.min(position.y)), Vector2::new(max.x.max(position.x), max.y.max(position.y))),
+                        None => (position, position),
+                    })
+                })
+                .unwrap_or((Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0)));
+            chunk.batches = batches;
+            chunk.animated = animated;
+            chunk.dirty = false;
+            chunk.version = NEXT_CHUNK_VERSION.fetch_add(1, Ordering::Relaxed);
+            rebuilt += 1;
+        }
+        rebuilt
+    }
+
+    fn build_chunk(&self, chunk: &TileChunk) -> (Vec<(usize, Vec<SpriteVertex>)>, bool) {
+        let mut batches: Vec<(usize, Vec<SpriteVertex>)> = Vec::new();
+        let mut animated = false;
+        let layer = &self.layers[chunk.layer];
+        for y in chunk.y * CHUNK_SIZE..((chunk.y + 1) * CHUNK_SIZE).min(self.height) {
+            for x in chunk.x * CHUNK_SIZE..((chunk.x + 1) * CHUNK_SIZE).min(self.width) {
+                let tile = match layer.tiles[(y * self.width + x) as usize] {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let tileset = match self.tilesets.get(tile.tileset) {
+                    Some(tileset) => tileset,
+                    None => continue,
+                };
+                animated |= tileset.get_flags(tile.index).animated;
+                let region = tileset.get_region(tileset.frame_at(tile.index, self.time));
+                //Tiles larger than the grid overflow up and right from the bottom left corner of their cell, like in Tiled
+                let min = Vector2::new((x * self.tile_width) as f32, -(((y + 1) * self.tile_height) as f32));
+                let max = min + Vector2::new(tileset.tile_width as f32, tileset.tile_height as f32);
+                let vertices = match batches.iter_mut().find(|(index, _)| *index == tile.tileset) {
+                    Some((_, vertices)) => vertices,
+                    None => {
+                        batches.push((tile.tileset, Vec::new()));
+                        &mut batches.last_mut().unwrap().1
+                    },
+                };
+                push_tile(vertices, &tile, region, tileset.texture.get_size(), min, max);
+            }
+        }
+        (batches, animated)
+    }
+}
+
+/// Adds the two triangles of a tile, the texture coordinates of each corner follow the flips of the tile.
+fn push_tile(vertices: &mut Vec<SpriteVertex>, tile: &Tile, region: Region, texture_size: (u32, u32), min: Vector2<f32>, max: Vector2<f32>) {
+    let (texture_width, texture_height) = (texture_size.0.max(1) as f32, texture_size.1.max(1) as f32);
+    //Corners counter clockwise from the bottom left, in image space with y down
+    let corners = [((min.x, min.y), (0.0, 1.0)), ((max.x, min.y), (1.0, 1.0)), ((max.x, max.y), (1.0, 0.0)), ((min.x, max.y), (0.0, 0.0))];
+    let corner = |index: usize| {
+        let (position, (mut u, mut v)) = corners[index];
+        if tile.flip_y {
+            v = 1.0 - v;
+        }
+        if tile.flip_x {
+            u = 1.0 - u;
+        }
+        if tile.flip_diagonal {
+            std::mem::swap(&mut u, &mut v);
+        }
+        SpriteVertex {
+            position: [position.0, position.1],
+            uv: [(region.x as f32 + u * region.width as f32) / texture_width, (region.y as f32 + v * region.height as f32) / texture_height],
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    };
+    for index in [0, 1, 2, 0, 2, 3] {
+        vertices.push(corner(index));
+    }
+}
+
+/// Advances the tile animations, run by the "animation" engine system.
+/// Only the maps about to be rebuilt are borrowed mutably, so the others are not reported as changed.
+pub(crate) fn update_tilemaps(storage: &mut ComponentStorageManager, delta: f32) {
+    storage.tile_time += delta;
+    let time = storage.tile_time;
+    let changed: Vec<Entity> = storage.query_read::<(Entity, &Tilemap)>().iter()
+        .filter(|(_, tilemap)| tilemap.needs_rebuild() || tilemap.frame_changes(time))
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in changed {
+        if let Some(tilemap) = storage.get_mut::<Tilemap>(entity) {
+            tilemap.set_time(time);
+        }
+    }
+}
+
+/// Tile set of a Tiled map before its image is loaded.
+struct TiledTileset {
+    first_gid: u32,
+    image: PathBuf,
+    tile_width: u32,
+    tile_height: u32,
+    margin: u32,
+    spacing: u32,
+    columns: u32,
+    count: u32,
+    tiles: HashMap<u32, TileData>,
+}
+
+struct TiledLayer {
+    name: String,
+    visible: bool,
+    opacity: f32,
+    /// Global tile ids row by row, 0 is an empty cell.
+    gids: Vec<u32>,
+}
+
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tilesets: Vec<TiledTileset>,
+    layers: Vec<TiledLayer>,
+}
+
+/// Creates the tilemap of a parsed Tiled map, `load` creates the texture of a tile set image.
+fn build_tilemap<F: FnMut(&Path) -> Result<Texture, String>>(mut map: TiledMap, mut load: F) -> Result<Tilemap, String> {
+    let mut tilemap = Tilemap::new(map.width, map.height, map.tile_width, map.tile_height);
+    map.tilesets.sort_by_key(|tileset| tileset.first_gid);
+    let mut first_gids = Vec::new();
+    for tileset in map.tilesets {
+        first_gids.push((tileset.first_gid, tileset.count));
+        tilemap.add_tileset(TileSet {
+            texture: load(&tileset.image)?,
+            tile_width: tileset.tile_width,
+            tile_height: tileset.tile_height,
+            margin: tileset.margin,
+            spacing: tileset.spacing,
+            columns: tileset.columns,
+            count: tileset.count,
+            tiles: tileset.tiles,
+        });
+    }
+    for layer in map.layers {
+        if layer.gids.len() != (map.width * map.height) as usize {
+            return Err(format!("Layer {} has {} tiles instead of {}", layer.name, layer.gids.len(), map.width * map.height));
+        }
+        let index = tilemap.add_layer(&layer.name);
+        let tile_layer = &mut tilemap.layers[index];
+        tile_layer.visible = layer.visible;
+        tile_layer.opacity = layer.opacity;
+        for (cell, gid) in layer.gids.iter().enumerate() {
+            if *gid == 0 {
+                continue;
+            }
+            if gid & ROTATED_HEXAGONAL != 0 {
+                return Err("Hexagonal maps are not supported".to_string());
+            }
+            let id = gid & !(FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY);
+            let tileset = first_gids.iter().rposition(|(first_gid, _)| *first_gid <= id)
+                .filter(|tileset| id - first_gids[*tileset].0 < first_gids[*tileset].1)
+                .ok_or_else(|| format!("Tile {} of layer {} is in no tile set", id, layer.name))?;
+            tile_layer.tiles[cell] = Some(Tile::new(tileset, id - first_gids[tileset].0)
+                .with_flip(gid & FLIPPED_HORIZONTALLY != 0, gid & FLIPPED_VERTICALLY != 0)
+                .with_diagonal_flip(gid & FLIPPED_DIAGONALLY != 0));
+        }
+    }
+    Ok(tilemap)
+}
+
+/// Reads the layer data, as CSV or as base64 little endian ids optionally compressed with zlib or gzip.
+fn decode_gids(data: &str, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>, String> {
+    match encoding {
+        Some("csv") => data.split(',')
+            .map(|gid| gid.trim().parse::<u32>().map_err(|_| format!("Invalid tile id {}", gid.trim())))
+            .collect(),
+        Some("base64") => {
+            use base64::Engine;
+            let compact: String = data.chars().filter(|character| !character.is_whitespace()).collect();
+            let bytes = base64::engine::general_purpose::STANDARD.decode(compact).map_err(|err| err.to_string())?;
+            let mut decoded = Vec::new();
+            match compression {
+                None | Some("") => decoded = bytes,
+                Some("zlib") => {
+                    flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decoded).map_err(|err| err.to_string())?;
+                },
+                Some("gzip") => {
+                    flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded).map_err(|err| err.to_string())?;
+                },
+                Some(other) => return Err(format!("Unsupported layer compression {}", other)),
+            }
+            if decoded.len() % 4 != 0 {
+                return Err("Truncated layer data".to_string());
+            }
+            Ok(decoded.chunks_exact(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect())
+        },
+        Some(other) => Err(format!("Unsupported layer encoding {}", other)),
+        None => Err("Missing layer encoding".to_string()),
+    }
+}
+
+/// Adds the property to the tile, a "collision" property set to true gives it collision.
+fn set_tile_property(tile: &mut TileData, name: &str, value: String) {
+    if name == "collision" {
+        tile.flags.collision = value == "true";
+    }
+    tile.properties.insert(name.to_string(), value);
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    orientation: Option<String>,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    compression: Option<String>,
+    /// Children of a group layer.
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+    #[serde(default)]
+    animation: Vec<JsonFrame>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    tileid: u32,
+    /// Milliseconds.
+    duration: f32,
+}
+
+/// Reads a finite orthogonal map saved by Tiled as JSON, group layers are flattened and object or image layers ignored.
+/// External tile sets are read relative to `dir`.
+fn parse_json(json: &str, dir: &Path) -> Result<TiledMap, String> {
+    let map: JsonMap = serde_json::from_str(json).map_err(|err| err.to_string())?;
+    check_map(map.orientation.as_deref(), map.infinite)?;
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        let first_gid = tileset_first_gid(tileset.firstgid);
+        tilesets.push(match tileset.source.clone() {
+            Some(source) => load_external_tileset(&dir.join(source), first_gid)?,
+            None => json_tileset(tileset, first_gid, dir)?,
+        });
+    }
+    let mut layers = Vec::new();
+    json_layers(map.layers, true, 1.0, &mut layers)?;
+    Ok(TiledMap {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        tilesets,
+        layers,
+    })
+}
+
+fn tileset_first_gid(first_gid: u32) -> u32 {
+    first_gid.max(1)
+}
+
+fn check_map(orientation: Option<&str>, infinite: bool) -> Result<(), String> {
+    if orientation.unwrap_or("orthogonal") != "orthogonal" {
+        return Err(format!("Unsupported {} orientation", orientation.unwrap_or_default()));
+    }
+    if infinite {
+        return Err("Infinite maps are not supported".to_string());
+    }
+    Ok(())
+}
+
+fn json_layers(layers: Vec<JsonLayer>, visible: bool, opacity: f32, output: &mut Vec<TiledLayer>) -> Result<(), String> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                let gids = match &layer.data {
+                    Some(serde_json::Value::Array(gids)) => gids.iter()
+                        .map(|gid| gid.as_u64().map(|gid| gid as u32).ok_or_else(|| format!("Invalid tile id {} in layer {}", gid, layer.name)))
+                        .collect::<Result<Vec<u32>, String>>()?,
+                    Some(serde_json::Value::String(data)) => decode_gids(data, layer.encoding.as_deref(), layer.compression.as_deref())?,
+                    _ => return Err(format!("Layer {} has no data", layer.name)),
+                };
+                output.push(TiledLayer { name: layer.name, visible: visible && layer.visible, opacity: opacity * layer.opacity, gids });
+            },
+            "group" => json_layers(layer.layers, visible && layer.visible, opacity * layer.opacity, output)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn json_tileset(tileset: JsonTileset, first_gid: u32, dir: &Path) -> Result<TiledTileset, String> {
+    let image = tileset.image.ok_or_else(|| "Tile sets of separate images are not supported".to_string())?;
+    let mut tiles = HashMap::new();
+    for tile in tileset.tiles {
+        let data: &mut TileData = tiles.entry(tile.id).or_default();
+        for property in tile.properties {
+            let value = match property.value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            set_tile_property(data, &property.name, value);
+        }
+        if !tile.animation.is_empty() {
+            data.flags.animated = true;
+            data.animation = tile.animation.iter().map(|frame| (frame.tileid, frame.duration / 1000.0)).collect();
+        }
+    }
+    Ok(TiledTileset {
+        first_gid,
+        image: dir.join(image),
+        tile_width: tileset.tilewidth,
+        tile_height: tileset.tileheight,
+        margin: tileset.margin,
+        spacing: tileset.spacing,
+        columns: tileset.columns,
+        count: tileset.tilecount,
+        tiles,
+    })
+}
+
+/// Reads a tile set saved in its own .tsx or .json file, its image is relative to that file.
+fn load_external_tileset(path: &Path, first_gid: u32) -> Result<TiledTileset, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    if path.extension().is_some_and(|extension| extension == "tsx") {
+        let document = roxmltree::Document::parse(&content).map_err(|err| format!("{}: {}", path.display(), err))?;
+        tmx_tileset(document.root_element(), first_gid, dir)
+    } else {
+        let tileset: JsonTileset = serde_json::from_str(&content).map_err(|err| format!("{}: {}", path.display(), err))?;
+        json_tileset(tileset, first_gid, dir)
+    }
+}
+
+/// Value of the attribute `name`, `None` when it is missing.
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<Option<T>, String> {
+    node.attribute(name)
+        .map(|value| value.parse().map_err(|_| format!("Invalid {} of {}: {}", name, node.tag_name().name(), value)))
+        .transpose()
+}
+
+fn required_attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, String> {
+    attribute(node, name)?.ok_or_else(|| format!("Missing {} of {}", name, node.tag_name().name()))
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+/// Reads a finite orthogonal map saved by Tiled as .tmx, group layers are flattened and object or image layers ignored.
+/// External tile sets are read relative to `dir`.
+fn parse_tmx(xml: &str, dir: &Path) -> Result<TiledMap, String> {
+    let document = roxmltree::Document::parse(xml).map_err(|err| err.to_string())?;
+    let map = document.root_element();
+    if !map.has_tag_name("map") {
+        return Err("Not a Tiled map".to_string());
+    }
+    check_map(map.attribute("orientation"), attribute::<u32>(map, "infinite")? == Some(1))?;
+    let mut tilesets = Vec::new();
+    for tileset in map.children().filter(|child| child.has_tag_name("tileset")) {
+        let first_gid = tileset_first_gid(attribute(tileset, "firstgid")?.unwrap_or(1));
+        tilesets.push(match tileset.attribute("source") {
+            Some(source) => load_external_tileset(&dir.join(source), first_gid)?,
+            None => tmx_tileset(tileset, first_gid, dir)?,
+        });
+    }
+    let mut layers = Vec::new();
+    tmx_layers(map, true, 1.0, &mut layers)?;
+    Ok(TiledMap {
+        width: required_attribute(map, "width")?,
+        height: required_attribute(map, "height")?,
+        tile_width: required_attribute(map, "tilewidth")?,
+        tile_height: required_attribute(map, "tileheight")?,
+        tilesets,
+        layers,
+    })
+}
+
+fn tmx_layers(parent: roxmltree::Node, visible: bool, opacity: f32, output: &mut Vec<TiledLayer>) -> Result<(), String> {
+    for layer in parent.children().filter(|child| child.is_element()) {
+        let layer_visible = visible && attribute::<u32>(layer, "visible")?.unwrap_or(1) != 0;
+        let layer_opacity = opacity * attribute::<f32>(layer, "opacity")?.unwrap_or(1.0);
+        let name = layer.attribute("name").unwrap_or_default().to_string();
+        match layer.tag_name().name() {
+            "layer" => {
+                let data = child(layer, "data").ok_or_else(|| format!("Layer {} has no data", name))?;
+                if child(data, "chunk").is_some() {
+                    return Err("Infinite maps are not supported".to_string());
+                }
+                let gids = match data.attribute("encoding") {
+                    Some(encoding) => decode_gids(data.text().unwrap_or_default(), Some(encoding), data.attribute("compression"))?,
+                    None => data.children().filter(|tile| tile.has_tag_name("tile"))
+                        .map(|tile| attribute(tile, "gid").map(|gid| gid.unwrap_or(0)))
+                        .collect::<Result<Vec<u32>, String>>()?,
+                };
+                output.push(TiledLayer { name, visible: layer_visible, opacity: layer_opacity, gids });
+            },
+            "group" => tmx_layers(layer, layer_visible, layer_opacity, output)?,
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn tmx_tileset(tileset: roxmltree::Node, first_gid: u32, dir: &Path) -> Result<TiledTileset, String> {
+    let image = child(tileset, "image").ok_or_else(|| "Tile sets of separate images are not supported".to_string())?;
+    let tile_width: u32 = required_attribute(tileset, "tilewidth")?;
+    let tile_height = required_attribute(tileset, "tileheight")?;
+    let margin = attribute(tileset, "margin")?.unwrap_or(0);
+    let spacing = attribute(tileset, "spacing")?.unwrap_or(0);
+    let image_width: u32 = required_attribute(image, "width")?;
+    let columns = match attribute(tileset, "columns")? {
+        Some(columns) => columns,
+        None => tiles_per_line(image_width, tile_width, margin, spacing),
+    };
+    let mut tiles = HashMap::new();
+    for tile in tileset.children().filter(|child| child.has_tag_name("tile")) {
+        let data: &mut TileData = tiles.entry(required_attribute(tile, "id")?).or_default();
+        if let Some(properties) = child(tile, "properties") {
+            for property in properties.children().filter(|child| child.has_tag_name("property")) {
+                let value = property.attribute("value").or_else(|| property.text()).unwrap_or_default();
+                set_tile_property(data, property.attribute("name").unwrap_or_default(), value.to_string());
+            }
+        }
+        if let Some(animation) = child(tile, "animation") {
+            data.animation = animation.children().filter(|child| child.has_tag_name("frame"))
+                .map(|frame| Ok((required_attribute(frame, "tileid")?, required_attribute::<f32>(frame, "duration")? / 1000.0)))
+                .collect::<Result<Vec<(u32, f32)>, String>>()?;
+            data.flags.animated = !data.animation.is_empty();
+        }
+    }
+    Ok(TiledTileset {
+        first_gid,
+        image: dir.join(required_attribute::<String>(image, "source")?),
+        tile_width,
+        tile_height,
+        margin,
+        spacing,
+        columns,
+        count: required_attribute(tileset, "tilecount")?,
+        tiles,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use base64::Engine;
+    use crate::ecs::Changed;
+
+    fn tilemap(width: u32, height: u32) -> Tilemap {
+        let mut tilemap = Tilemap::new(width, height, 16, 16);
+        tilemap.add_tileset(TileSet::new(Texture::fake(1, 64, 32), 16, 16));
+        tilemap.add_layer("ground");
+        tilemap
+    }
+
+    fn versions(tilemap: &Tilemap) -> Vec<u64> {
+        tilemap.get_chunks().iter().map(|chunk| chunk.version).collect()
+    }
+
+    #[test]
+    fn only_changed_chunks_are_rebuilt() {
+        let mut tilemap = tilemap(40, 20);
+        assert_eq!(tilemap.rebuild_chunks(), 6);
+        assert_eq!(tilemap.rebuild_chunks(), 0);
+        let before = versions(&tilemap);
+        tilemap.set_tile(0, 20, 5, Some(Tile::new(0, 3)));
+        tilemap.set_tile(0, 21, 5, Some(Tile::new(0, 3)));
+        assert_eq!(tilemap.rebuild_chunks(), 1);
+        let after = versions(&tilemap);
+        let changed: Vec<usize> = (0..before.len()).filter(|index| before[*index] != after[*index]).collect();
+        assert_eq!(changed, vec![1]);
+        assert_eq!(tilemap.get_chunks()[1].batches[0].1.len(), 12);
+        assert!(tilemap.set_tile(0, 21, 5, Some(Tile::new(0, 3))));
+        assert_eq!(tilemap.rebuild_chunks(), 0);
+        assert!(!tilemap.set_tile(0, 40, 5, None));
+        assert!(!tilemap.set_tile(1, 0, 0, None));
+    }
+
+    #[test]
+    fn tiles_follow_their_cell_and_flips() {
+        let mut tilemap = tilemap(2, 2);
+        tilemap.set_tile(0, 1, 1, Some(Tile::new(0, 5)));
+        tilemap.rebuild_chunks();
+        let vertices = &tilemap.get_chunks()[0].batches[0].1;
+        //Tile 5 is the second tile of the second row, (16, 16) to (32, 32) in the texture
+        assert_eq!(vertices[0].position, [16.0, -32.0]);
+        assert_eq!(vertices[0].uv, [0.25, 1.0]);
+        assert_eq!(vertices[2].position, [32.0, -16.0]);
+        assert_eq!(vertices[2].uv, [0.5, 0.5]);
+        assert_eq!(tilemap.get_chunks()[0].bounds, (Vector2::new(16.0, -32.0), Vector2::new(32.0, -16.0)));
+
+        tilemap.set_tile(0, 1, 1, Some(Tile::new(0, 5).with_flip(true, false)));
+        tilemap.rebuild_chunks();
+        assert_eq!(tilemap.get_chunks()[0].batches[0].1[0].uv, [0.5, 1.0]);
+        //The diagonal flip swaps the top right and bottom left corners
+        tilemap.set_tile(0, 1, 1, Some(Tile::new(0, 5).with_diagonal_flip(true)));
+        tilemap.rebuild_chunks();
+        let vertices = &tilemap.get_chunks()[0].batches[0].1;
+        assert_eq!([vertices[0].uv, vertices[1].uv, vertices[2].uv], [[0.5, 0.5], [0.5, 1.0], [0.25, 1.0]]);
+    }
+
+    #[test]
+    fn animated_tiles_rebuild_their_chunks() {
+        let mut tilemap = tilemap(32, 16);
+        tilemap.get_mut_tileset(0).unwrap().set_animation(0, vec![(0, 0.1), (1, 0.1)]);
+        tilemap.set_tile(0, 20, 0, Some(Tile::new(0, 0)));
+        tilemap.set_tile(0, 0, 0, Some(Tile::new(0, 2)));
+        tilemap.rebuild_chunks();
+        assert!(tilemap.get_flags(0, 20, 0).animated);
+        assert!(!tilemap.frame_changes(0.05));
+        tilemap.set_time(0.05);
+        assert_eq!(tilemap.rebuild_chunks(), 0);
+        assert!(tilemap.frame_changes(0.15));
+        tilemap.set_time(0.15);
+        assert_eq!(tilemap.rebuild_chunks(), 1);
+        assert_eq!(tilemap.get_chunks()[1].batches[0].1[0].uv, [0.25, 0.5]);
+    }
+
+    #[test]
+    fn idle_tilemaps_are_not_changed() {
+        let mut storage = ComponentStorageManager::new();
+        let idle = storage.spawn();
+        let mut map = tilemap(16, 16);
+        map.rebuild_chunks();
+        storage.insert(idle, map);
+        let animated = storage.spawn();
+        let mut map = tilemap(16, 16);
+        map.get_mut_tileset(0).unwrap().set_animation(0, vec![(0, 0.1), (1, 0.1)]);
+        map.set_tile(0, 0, 0, Some(Tile::new(0, 0)));
+        map.rebuild_chunks();
+        storage.insert(animated, map);
+        storage.clear_trackers();
+        update_tilemaps(&mut storage, 0.05);
+        assert_eq!(storage.query_read_filtered::<Entity, Changed<Tilemap>>().iter().count(), 0);
+        update_tilemaps(&mut storage, 0.1);
+        let changed: Vec<Entity> = storage.query_read_filtered::<Entity, Changed<Tilemap>>().iter().collect();
+        assert_eq!(changed, vec![animated]);
+        assert!(storage.get::<Tilemap>(animated).unwrap().needs_rebuild());
+    }
+
+    #[test]
+    fn collision_and_world_positions() {
+        let mut tilemap = tilemap(4, 4);
+        tilemap.get_mut_tileset(0).unwrap().set_collision(1, true);
+        let top = tilemap.add_layer("top");
+        tilemap.set_tile(top, 2, 3, Some(Tile::new(0, 1)));
+        tilemap.set_tile(0, 1, 3, Some(Tile::new(0, 0)));
+        assert!(tilemap.is_solid(2, 3));
+        assert!(!tilemap.is_solid(1, 3));
+        assert!(!tilemap.is_solid(-1, 3));
+        let mut transform = Transform::new_default();
+        transform.translation.x = 100.0;
+        assert_eq!(tilemap.world_to_tile(&transform, Vector2::new(140.0, -60.0)), Some((2, 3)));
+        assert_eq!(tilemap.world_to_tile(&transform, Vector2::new(90.0, -10.0)), None);
+    }
+
+    fn zlib_base64(gids: &[u32]) -> String {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        for gid in gids {
+            encoder.write_all(&gid.to_le_bytes()).unwrap();
+        }
+        base64::engine::general_purpose::STANDARD.encode(encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn tiled_json_maps() {
+        let json = format!(r#"{{
+            "width": 2, "height": 2, "tilewidth": 16, "tileheight": 16, "orientation": "orthogonal", "infinite": false,
+            "layers": [
+                {{ "type": "tilelayer", "name": "ground", "data": [1, 2, 0, {}], "visible": true, "opacity": 1 }},
+                {{ "type": "objectgroup", "name": "spawns", "objects": [] }},
+                {{ "type": "group", "name": "details", "visible": false, "opacity": 0.5, "layers": [
+                    {{ "type": "tilelayer", "name": "grass", "data": "{}", "encoding": "base64", "compression": "zlib", "opacity": 0.5 }}
+                ] }}
+            ],
+            "tilesets": [
+                {{ "firstgid": 1, "image": "tiles.png", "imagewidth": 64, "imageheight": 32, "tilewidth": 16, "tileheight": 16, "columns": 4, "tilecount": 8,
+                   "tiles": [ {{ "id": 1, "properties": [ {{ "name": "collision", "type": "bool", "value": true }}, {{ "name": "kind", "type": "string", "value": "wall" }} ],
+                                 "animation": [ {{ "tileid": 1, "duration": 100 }}, {{ "tileid": 2, "duration": 300 }} ] }} ] }},
+                {{ "firstgid": 9, "image": "props.png", "tilewidth": 32, "tileheight": 32, "columns": 2, "tilecount": 4 }}
+            ]
+        }}"#, 10 | FLIPPED_HORIZONTALLY, zlib_base64(&[0, 9, 3, 0]));
+        let map = parse_json(&json, Path::new("maps")).unwrap();
+        assert_eq!(map.layers.iter().map(|layer| layer.name.as_str()).collect::<Vec<&str>>(), vec!["ground", "grass"]);
+        assert!(!map.layers[1].visible && (map.layers[1].opacity - 0.25).abs() < 1e-6);
+        assert_eq!(map.tilesets[0].image, Path::new("maps").join("tiles.png"));
+        let tile = &map.tilesets[0].tiles[&1];
+        assert!(tile.flags.collision && tile.flags.animated);
+        assert_eq!(tile.properties["kind"], "wall");
+        assert_eq!(tile.animation, vec![(1, 0.1), (2, 0.3)]);
+
+        let mut loaded = Vec::new();
+        let tilemap = build_tilemap(map, |image| {
+            loaded.push(image.to_path_buf());
+            Ok(Texture::fake(loaded.len() as u32, 64, 64))
+        }).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(tilemap.get_tile(0, 0, 0), Some(Tile::new(0, 0)));
+        assert_eq!(tilemap.get_tile(0, 1, 1), Some(Tile::new(1, 1).with_flip(true, false)));
+        assert_eq!(tilemap.get_tile(1, 1, 0), Some(Tile::new(1, 0)));
+        assert!(tilemap.is_solid(1, 0));
+    }
+
+    #[test]
+    fn tiled_tmx_maps() {
+        let tmx = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map version="1.10" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="8" tileheight="8" infinite="0">
+            <tileset firstgid="1" name="tiles" tilewidth="8" tileheight="8" spacing="1" margin="1" tilecount="6" columns="3">
+                <image source="../images/tiles.png" width="28" height="19"/>
+                <tile id="4">
+                    <properties><property name="collision" type="bool" value="true"/></properties>
+                    <animation><frame tileid="4" duration="250"/><frame tileid="5" duration="250"/></animation>
+                </tile>
+            </tileset>
+            <layer id="1" name="ground" width="3" height="2">
+                <data encoding="csv">
+        1,2,3,
+        4,5,2147483654
+        </data>
+            </layer>
+            <group id="2" name="top" opacity="0.5">
+                <layer id="3" name="marks" width="3" height="2" visible="0">
+                    <data><tile gid="5"/><tile/><tile/><tile/><tile/><tile gid="1"/></data>
+                </layer>
+            </group>
+            <objectgroup id="4" name="spawns"/>
+        </map>"#;
+        let map = parse_tmx(tmx, Path::new("maps")).unwrap();
+        assert_eq!((map.width, map.height, map.tile_width, map.tile_height), (3, 2, 8, 8));
+        assert_eq!(map.tilesets[0].image, Path::new("maps").join("../images/tiles.png"));
+        assert_eq!(map.layers[0].gids, vec![1, 2, 3, 4, 5, 6 | FLIPPED_HORIZONTALLY]);
+        assert_eq!(map.layers[1].gids, vec![5, 0, 0, 0, 0, 1]);
+        assert!(!map.layers[1].visible && (map.layers[1].opacity - 0.5).abs() < 1e-6);
+
+        let tilemap = build_tilemap(map, |_| Ok(Texture::fake(1, 28, 19))).unwrap();
+        let tileset = tilemap.get_tileset(0).unwrap();
+        assert_eq!(tileset.get_region(4), Region::new(10, 10, 8, 8));
+        assert!(tileset.get_flags(4).collision && tileset.get_flags(4).animated);
+        assert!(tilemap.is_solid(1, 1));
+        assert_eq!(tilemap.get_tile(0, 2, 1), Some(Tile::new(0, 5).with_flip(true, false)));
+
+        assert!(parse_tmx(&tmx.replace("infinite=\"0\"", "infinite=\"1\""), Path::new("")).is_err());
+        assert!(parse_tmx(&tmx.replace("orthogonal", "isometric"), Path::new("")).is_err());
+    }
+
+    #[test]
+    fn layer_data_decoding() {
+        assert_eq!(decode_gids(" 1, 2,\n3 ", Some("csv"), None).unwrap(), vec![1, 2, 3]);
+        let raw = base64::engine::general_purpose::STANDARD.encode([7, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(decode_gids(&raw, Some("base64"), None).unwrap(), vec![7, 256]);
+        assert_eq!(decode_gids(&zlib_base64(&[4, 5]), Some("base64"), Some("zlib")).unwrap(), vec![4, 5]);
+        assert!(decode_gids(&raw, Some("base64"), Some("zstd")).is_err());
+    }
+}
@@ -1,23 +1,25 @@
 extern crate cgmath;
 pub use cgmath::prelude::*;
-pub use cgmath::{Vector3, Matrix4, Quaternion, Euler, Deg};
+pub use cgmath::{Vector3, Matrix3, Matrix4, Quaternion, Euler, Deg, Rad};
 
 
+/// Squared lengths under which `look_at` treats a vector as zero.
+const LOOK_AT_EPSILON: f32 = 1e-10;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Transform {
     pub translation: Vector3<f32>,
-    pub rotation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
     pub scale: Vector3<f32>,
     pub local_transform: Matrix4<f32>,
 }
 
 impl Transform {
-    pub fn new(trans: Vector3<f32>, rotation: Vector3<f32>, scale: Vector3<f32>) -> Self {
+    pub fn new(trans: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
         Self {
             translation: trans,
-            rotation: rotation,
-            scale: scale,
+            rotation,
+            scale,
             local_transform: Matrix4::one(),
         }
     }
@@ -25,30 +27,168 @@ impl Transform {
     pub fn new_default() -> Self {
         Self {
             translation: Vector3::new(0.0, 0.0, 0.0),
-            rotation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
             scale: Vector3::new(1.0, 1.0, 1.0),
             local_transform: Matrix4::one(),
         }
     }
 
     pub fn calculate_local_transform(&self) -> Matrix4<f32> {
-        let translation_mat = Matrix4::from_translation(self.translation); //transforms::translate(Mat4::new_identity(), self.translation);
-        let scale_mat = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);//transforms::scale(Mat4::new_identity(), self.scale);
-        let rotate_mat = Matrix4::from(Quaternion::from(Euler::new(Deg(self.rotation.x), Deg(self.rotation.y), Deg(self.rotation.z))));//self.calculate_rotation_mat();
+        let translation_mat = Matrix4::from_translation(self.translation);
+        let scale_mat = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+        let rotate_mat = Matrix4::from(self.rotation);
         translation_mat * rotate_mat * scale_mat
     }
 
     pub fn update_local_transform(&mut self) {
-        let translation_mat = Matrix4::from_translation(self.translation); //transforms::translate(Mat4::new_identity(), self.translation);
-        let scale_mat = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);//transforms::scale(Mat4::new_identity(), self.scale);
-        let rotate_mat = Matrix4::from(Quaternion::from(Euler::new(Deg(self.rotation.x), Deg(self.rotation.y), Deg(self.rotation.z))));//self.calculate_rotation_mat();
-        self.local_transform = translation_mat * rotate_mat * scale_mat;
+        self.local_transform = self.calculate_local_transform();
     }
 
+    /// Rotates the transform by `angle` around a world space `axis`.
+    pub fn rotate_around(&mut self, axis: Vector3<f32>, angle: Deg<f32>) {
+        let delta = Quaternion::from_axis_angle(axis.normalize(), angle);
+        self.rotation = (delta * self.rotation).normalize();
+    }
+
+    /// Orients the transform so that `forward()` points at `target`, unchanged when `target` is the translation.
+    /// Another up axis is used when `up` is parallel to the direction of the target.
+    pub fn look_at(&mut self, target: Vector3<f32>, up: Vector3<f32>) {
+        let direction = target - self.translation;
+        if direction.magnitude2() < LOOK_AT_EPSILON {
+            return;
+        }
+        let forward = direction.normalize();
+        let mut right = forward.cross(up);
+        if right.magnitude2() < LOOK_AT_EPSILON {
+            let fallback = if forward.z.abs() < 0.9 { Vector3::unit_z() } else { Vector3::unit_x() };
+            right = forward.cross(fallback);
+        }
+        let right = right.normalize();
+        let up = right.cross(forward);
+        self.rotation = Quaternion::from(Matrix3::from_cols(right, up, -forward)).normalize();
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(-Vector3::unit_z())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_x())
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_y())
+    }
+
+    /// Rotation as Euler angles in degrees, for editing purposes only.
+    pub fn euler_angles(&self) -> Vector3<f32> {
+        let euler = Euler::from(self.rotation);
+        Vector3::new(Deg::from(euler.x).0, Deg::from(euler.y).0, Deg::from(euler.z).0)
+    }
+
+    pub fn set_euler_angles(&mut self, angles: Vector3<f32>) {
+        self.rotation = Quaternion::from(Euler::new(Deg(angles.x), Deg(angles.y), Deg(angles.z)));
+    }
+
+    /// Linear interpolation of translation and scale, normalized lerp of the rotation.
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        let rotation = self.rotation.nlerp(Self::shortest_path(self.rotation, other.rotation), t);
+        Transform::new(self.translation.lerp(other.translation, t), rotation, self.scale.lerp(other.scale, t))
+    }
+
+    /// Same as `lerp` but with a constant angular velocity for the rotation.
+    pub fn slerp(&self, other: &Transform, t: f32) -> Transform {
+        let rotation = self.rotation.slerp(Self::shortest_path(self.rotation, other.rotation), t);
+        Transform::new(self.translation.lerp(other.translation, t), rotation, self.scale.lerp(other.scale, t))
+    }
+
+    fn shortest_path(from: Quaternion<f32>, to: Quaternion<f32>) -> Quaternion<f32> {
+        if from.dot(to) < 0.0 {
+            -to
+        } else {
+            to
+        }
+    }
 }
 
 pub struct Translation {
     pub x: f32,
     pub y: f32,
     pub z: f32,
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_eq(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn default_directions() {
+        let trans = Transform::new_default();
+        assert_vec_eq(trans.forward(), Vector3::new(0.0, 0.0, -1.0));
+        assert_vec_eq(trans.right(), Vector3::new(1.0, 0.0, 0.0));
+        assert_vec_eq(trans.up(), Vector3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn rotate_around_accumulates() {
+        let mut trans = Transform::new_default();
+        for _ in 0..9 {
+            trans.rotate_around(Vector3::unit_y(), Deg(10.0));
+        }
+        assert_vec_eq(trans.forward(), Vector3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn look_at_own_position_keeps_rotation() {
+        let mut trans = Transform::new_default();
+        trans.rotate_around(Vector3::unit_y(), Deg(30.0));
+        let rotation = trans.rotation;
+        trans.look_at(trans.translation, Vector3::unit_y());
+        assert_eq!(trans.rotation, rotation);
+    }
+
+    #[test]
+    fn look_at_along_up_stays_finite() {
+        let mut trans = Transform::new_default();
+        trans.look_at(Vector3::new(0.0, 5.0, 0.0), Vector3::unit_y());
+        assert!(trans.rotation.s.is_finite() && trans.rotation.v.x.is_finite() && trans.rotation.v.y.is_finite() && trans.rotation.v.z.is_finite());
+        assert_vec_eq(trans.forward(), Vector3::new(0.0, 1.0, 0.0));
+        trans.look_at(Vector3::new(0.0, 0.0, -3.0), Vector3::unit_z());
+        assert_vec_eq(trans.forward(), Vector3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn look_at_target() {
+        let mut trans = Transform::new_default();
+        trans.translation = Vector3::new(1.0, 2.0, 3.0);
+        trans.look_at(Vector3::new(4.0, 2.0, 3.0), Vector3::unit_y());
+        assert_vec_eq(trans.forward(), Vector3::new(1.0, 0.0, 0.0));
+        assert_vec_eq(trans.up(), Vector3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let mut trans = Transform::new_default();
+        trans.set_euler_angles(Vector3::new(30.0, 45.0, 10.0));
+        assert_vec_eq(trans.euler_angles(), Vector3::new(30.0, 45.0, 10.0));
+    }
+
+    #[test]
+    fn slerp_halfway() {
+        let a = Transform::new_default();
+        let mut b = Transform::new_default();
+        b.translation = Vector3::new(2.0, 0.0, 0.0);
+        b.rotate_around(Vector3::unit_y(), Deg(90.0));
+        let half = a.slerp(&b, 0.5);
+        assert_vec_eq(half.translation, Vector3::new(1.0, 0.0, 0.0));
+        let mut expected = Transform::new_default();
+        expected.rotate_around(Vector3::unit_y(), Deg(45.0));
+        assert_vec_eq(half.forward(), expected.forward());
+        assert_vec_eq(a.lerp(&b, 1.0).forward(), b.forward());
+    }
+}
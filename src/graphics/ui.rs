@@ -0,0 +1,446 @@
+extern crate egui;
+
+use std::collections::HashMap;
+use std::time::Instant;
+use egui::epaint::{ClippedPrimitive, ImageData, Primitive, Vertex};
+use egui::epaint::textures::{TextureFilter, TextureWrapMode, TexturesDelta};
+use egui::{Context, FullOutput, Pos2, RawInput, Rect, TextureId, ViewportId};
+use glutin::{ElementState, KeyboardInput, ModifiersState};
+use crate::graphics::inputs::Key;
+use crate::graphics::render_state::{BlendMode, RenderState};
+use crate::graphics::shader::Shader;
+
+
+/// Immediate-mode UI drawn over the scene, e.g. `egui::Window::new("Light").show(data.ui(), |ui| ...)`.
+/// The input used by the UI, like a click on a window, does not reach the `InputSystem`.
+pub struct Ui {
+    context: Context,
+    input: RawInput,
+    modifiers: egui::Modifiers,
+    pointer: Pos2,
+    in_frame: bool,
+    start: Instant,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ui {
+    pub fn new() -> Self {
+        Self {
+            context: Context::default(),
+            input: RawInput::default(),
+            modifiers: egui::Modifiers::default(),
+            pointer: Pos2::ZERO,
+            in_frame: false,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn get_context(&self) -> &Context {
+        &self.context
+    }
+
+    /// True when the pointer is over a UI element or dragging one.
+    pub fn wants_pointer(&self) -> bool {
+        self.context.wants_pointer_input() || self.context.is_pointer_over_area()
+    }
+
+    /// True when a UI element, like a text field, has the keyboard focus.
+    pub fn wants_keyboard(&self) -> bool {
+        self.context.wants_keyboard_input()
+    }
+
+    /// True while a UI element is dragged, the mouse motion is then not given to the gameplay.
+    pub fn is_using_pointer(&self) -> bool {
+        self.context.is_using_pointer()
+    }
+
+    /// Position of the cursor in logical pixels from the top left corner of the window.
+    pub(crate) fn set_cursor_position(&mut self, x: f32, y: f32) {
+        self.pointer = Pos2::new(x, y);
+        self.input.events.push(egui::Event::PointerMoved(self.pointer));
+    }
+
+    pub(crate) fn set_cursor_left(&mut self) {
+        self.input.events.push(egui::Event::PointerGone);
+    }
+
+    /// Returns true when the press is captured by the UI. Releases are never captured so no button stays pressed.
+    pub(crate) fn set_mouse_button_event(&mut self, button: glutin::MouseButton, state: ElementState) -> bool {
+        let button = match button {
+            glutin::MouseButton::Left => egui::PointerButton::Primary,
+            glutin::MouseButton::Right => egui::PointerButton::Secondary,
+            glutin::MouseButton::Middle => egui::PointerButton::Middle,
+            _ => return false,
+        };
+        let pressed = state == ElementState::Pressed;
+        self.input.events.push(egui::Event::PointerButton { pos: self.pointer, button, pressed, modifiers: self.modifiers });
+        pressed && self.wants_pointer()
+    }
+
+    /// Scroll in lines, returns true when the UI captures it.
+    pub(crate) fn set_mouse_wheel_event(&mut self, x_delta: f32, y_delta: f32) -> bool {
+        self.input.events.push(egui::Event::MouseWheel {
+            unit: egui::MouseWheelUnit::Line,
+            delta: egui::vec2(x_delta, y_delta),
+            modifiers: self.modifiers,
+        });
+        self.wants_pointer()
+    }
+
+    /// Returns true when the key press is captured by the UI.
+    pub(crate) fn set_key_event(&mut self, event: KeyboardInput) -> bool {
+        self.modifiers = modifiers(event.modifiers);
+        let pressed = event.state == ElementState::Pressed;
+        if let Some(key) = event.virtual_keycode.and_then(egui_key) {
+            self.input.events.push(egui::Event::Key { key, physical_key: None, pressed, repeat: false, modifiers: self.modifiers });
+        }
+        pressed && self.wants_keyboard()
+    }
+
+    pub(crate) fn set_character_event(&mut self, character: char) {
+        if !character.is_control() {
+            self.input.events.push(egui::Event::Text(character.to_string()));
+        }
+    }
+
+    pub(crate) fn set_focused(&mut self, focused: bool) {
+        self.input.focused = focused;
+        self.input.events.push(egui::Event::WindowFocused(focused));
+    }
+
+    /// Starts the UI of the frame with the events received since the previous one, the window size is in logical pixels.
+    pub(crate) fn begin_frame(&mut self, width: f32, height: f32, pixels_per_point: f32) {
+        if self.in_frame {
+            self.end_frame();
+        }
+        let mut input = std::mem::take(&mut self.input);
+        self.input.focused = input.focused;
+        input.screen_rect = Some(Rect::from_min_size(Pos2::ZERO, egui::vec2(width, height)));
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+        input.viewports.entry(ViewportId::ROOT).or_default().native_pixels_per_point = Some(pixels_per_point);
+        self.context.begin_pass(input);
+        self.in_frame = true;
+    }
+
+    /// Ends the UI of the frame, `None` when no frame was started.
+    pub(crate) fn end_frame(&mut self) -> Option<FullOutput> {
+        if !self.in_frame {
+            return None;
+        }
+        self.in_frame = false;
+        Some(self.context.end_pass())
+    }
+}
+
+fn modifiers(state: ModifiersState) -> egui::Modifiers {
+    egui::Modifiers {
+        alt: state.alt,
+        ctrl: state.ctrl,
+        shift: state.shift,
+        mac_cmd: cfg!(target_os = "macos") && state.logo,
+        command: if cfg!(target_os = "macos") { state.logo } else { state.ctrl },
+    }
+}
+
+fn egui_key(key: Key) -> Option<egui::Key> {
+    use egui::Key as K;
+    Some(match key {
+        Key::Left => K::ArrowLeft,
+        Key::Right => K::ArrowRight,
+        Key::Up => K::ArrowUp,
+        Key::Down => K::ArrowDown,
+        Key::Escape => K::Escape,
+        Key::Tab => K::Tab,
+        Key::Back => K::Backspace,
+        Key::Return | Key::NumpadEnter => K::Enter,
+        Key::Space => K::Space,
+        Key::Insert => K::Insert,
+        Key::Delete => K::Delete,
+        Key::Home => K::Home,
+        Key::End => K::End,
+        Key::PageUp => K::PageUp,
+        Key::PageDown => K::PageDown,
+        Key::Minus | Key::Subtract => K::Minus,
+        Key::Equals => K::Equals,
+        Key::Key0 | Key::Numpad0 => K::Num0,
+        Key::Key1 | Key::Numpad1 => K::Num1,
+        Key::Key2 | Key::Numpad2 => K::Num2,
+        Key::Key3 | Key::Numpad3 => K::Num3,
+        Key::Key4 | Key::Numpad4 => K::Num4,
+        Key::Key5 | Key::Numpad5 => K::Num5,
+        Key::Key6 | Key::Numpad6 => K::Num6,
+        Key::Key7 | Key::Numpad7 => K::Num7,
+        Key::Key8 | Key::Numpad8 => K::Num8,
+        Key::Key9 | Key::Numpad9 => K::Num9,
+        Key::A => K::A,
+        Key::B => K::B,
+        Key::C => K::C,
+        Key::D => K::D,
+        Key::E => K::E,
+        Key::F => K::F,
+        Key::G => K::G,
+        Key::H => K::H,
+        Key::I => K::I,
+        Key::J => K::J,
+        Key::K => K::K,
+        Key::L => K::L,
+        Key::M => K::M,
+        Key::N => K::N,
+        Key::O => K::O,
+        Key::P => K::P,
+        Key::Q => K::Q,
+        Key::R => K::R,
+        Key::S => K::S,
+        Key::T => K::T,
+        Key::U => K::U,
+        Key::V => K::V,
+        Key::W => K::W,
+        Key::X => K::X,
+        Key::Y => K::Y,
+        Key::Z => K::Z,
+        Key::F1 => K::F1,
+        Key::F2 => K::F2,
+        Key::F3 => K::F3,
+        Key::F4 => K::F4,
+        Key::F5 => K::F5,
+        Key::F6 => K::F6,
+        Key::F7 => K::F7,
+        Key::F8 => K::F8,
+        Key::F9 => K::F9,
+        Key::F10 => K::F10,
+        Key::F11 => K::F11,
+        Key::F12 => K::F12,
+        _ => return None,
+    })
+}
+
+/// Draws the meshes tessellated by egui over the window, keeping the egui textures.
+pub(crate) struct UiRenderer {
+    shader: Shader,
+    vao: gl::types::GLuint,
+    vbo: gl::types::GLuint,
+    ebo: gl::types::GLuint,
+    textures: HashMap<TextureId, gl::types::GLuint>,
+}
+
+impl UiRenderer {
+    pub(crate) fn new() -> Self {
+        let vert_source = r#"
+        #version 330 core
+        layout (location = 0) in vec2 aPos;
+        layout (location = 1) in vec2 aTexCoord;
+        layout (location = 2) in vec4 aColor;
+
+        out vec2 TexCoord;
+        out vec4 ourColor;
+
+        uniform vec2 screenSize;
+
+        void main()
+        {
+            gl_Position = vec4(2.0 * aPos.x / screenSize.x - 1.0, 1.0 - 2.0 * aPos.y / screenSize.y, 0.0, 1.0);
+            TexCoord = aTexCoord;
+            ourColor = aColor;
+        }
+    "#;
+        let frag_source = r#"
+        #version 330 core
+        out vec4 FragColor;
+
+        in vec2 TexCoord;
+        in vec4 ourColor;
+
+        uniform sampler2D uiTexture;
+
+        void main()
+        {
+            // egui colors are premultiplied by their alpha
+            FragColor = ourColor * texture(uiTexture, TexCoord);
+        }
+    "#;
+        let mut renderer = Self {
+            shader: Shader::new(vert_source, frag_source),
+            vao: 0,
+            vbo: 0,
+            ebo: 0,
+            textures: HashMap::new(),
+        };
+        let stride = std::mem::size_of::<Vertex>() as gl::types::GLint;
+        unsafe {
+            gl::GenVertexArrays(1, &mut renderer.vao);
+            gl::BindVertexArray(renderer.vao);
+            gl::GenBuffers(1, &mut renderer.vbo);
+            gl::GenBuffers(1, &mut renderer.ebo);
+            gl::BindBuffer(gl::ARRAY_BUFFER, renderer.vbo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, renderer.ebo);
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, stride, std::mem::size_of::<[f32; 2]>() as *const gl::types::GLvoid);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(2, 4, gl::UNSIGNED_BYTE, gl::TRUE, stride, std::mem::size_of::<[f32; 4]>() as *const gl::types::GLvoid);
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0);
+        }
+        renderer
+    }
+
+    fn update_textures(&mut self, delta: &TexturesDelta) {
+        for (id, image_delta) in delta.set.iter() {
+            let ImageData::Color(image) = &image_delta.image;
+            let filter = |filter: TextureFilter| match filter {
+                TextureFilter::Nearest => gl::NEAREST as i32,
+                TextureFilter::Linear => gl::LINEAR as i32,
+            };
+            let wrap = match image_delta.options.wrap_mode {
+                TextureWrapMode::ClampToEdge => gl::CLAMP_TO_EDGE as i32,
+                TextureWrapMode::Repeat => gl::REPEAT as i32,
+                TextureWrapMode::MirroredRepeat => gl::MIRRORED_REPEAT as i32,
+            };
+            let pixels = image.pixels.as_ptr() as *const gl::types::GLvoid;
+            let texture = *self.textures.entry(*id).or_insert_with(|| {
+                let mut texture = 0;
+                unsafe { gl::GenTextures(1, &mut texture) };
+                texture
+            });
+            unsafe {
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                match image_delta.pos {
+                    Some([x, y]) => gl::TexSubImage2D(gl::TEXTURE_2D, 0, x as i32, y as i32, image.width() as i32, image.height() as i32, gl::RGBA, gl::UNSIGNED_BYTE, pixels),
+                    None => gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, image.width() as i32, image.height() as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels),
+                }
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter(image_delta.options.minification));
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter(image_delta.options.magnification));
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, wrap);
+                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, wrap);
+            }
+        }
+    }
+
+    fn free_textures(&mut self, delta: &TexturesDelta) {
+        for id in delta.free.iter() {
+            if let Some(texture) = self.textures.remove(id) {
+                unsafe { gl::DeleteTextures(1, &texture) };
+            }
+        }
+    }
+
+    /// Draws the UI over the bound framebuffer, sized like the viewport.
+    pub(crate) fn paint(&mut self, textures: &TexturesDelta, primitives: &[ClippedPrimitive], pixels_per_point: f32) {
+        self.update_textures(textures);
+        let mut viewport = [0; 4];
+        unsafe { gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr()) };
+        let (width, height) = (viewport[2] as f32, viewport[3] as f32);
+
+        let state = RenderState { depth_test: false, ..RenderState::transparent(BlendMode::Premultiplied) };
+        state.apply(None);
+        self.shader.use_program();
+        self.shader.set_vec2("screenSize", cgmath::Vector2::new(width / pixels_per_point, height / pixels_per_point));
+        self.shader.set_int("uiTexture", 0);
+        unsafe {
+            gl::Enable(gl::SCISSOR_TEST);
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        }
+        for primitive in primitives {
+            //Paint callbacks are not supported
+            let mesh = match &primitive.primitive {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue,
+            };
+            let texture = match self.textures.get(&mesh.texture_id) {
+                Some(texture) => *texture,
+                None => continue,
+            };
+            let clip = primitive.clip_rect;
+            let min_x = (clip.min.x * pixels_per_point).round().clamp(0.0, width);
+            let min_y = (clip.min.y * pixels_per_point).round().clamp(0.0, height);
+            let max_x = (clip.max.x * pixels_per_point).round().clamp(min_x, width);
+            let max_y = (clip.max.y * pixels_per_point).round().clamp(min_y, height);
+            if max_x <= min_x || max_y <= min_y || mesh.indices.is_empty() {
+                continue;
+            }
+            unsafe {
+                gl::Scissor(min_x as i32, (height - max_y) as i32, (max_x - min_x) as i32, (max_y - min_y) as i32);
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+                gl::BufferData(gl::ARRAY_BUFFER, std::mem::size_of_val(mesh.vertices.as_slice()) as gl::types::GLsizeiptr, mesh.vertices.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, std::mem::size_of_val(mesh.indices.as_slice()) as gl::types::GLsizeiptr, mesh.indices.as_ptr() as *const gl::types::GLvoid, gl::STREAM_DRAW);
+                gl::DrawElements(gl::TRIANGLES, mesh.indices.len() as i32, gl::UNSIGNED_INT, std::ptr::null());
+            }
+        }
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        RenderState::opaque().apply(Some(&state));
+        self.free_textures(textures);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show_window(ui: &mut Ui) {
+        ui.begin_frame(800.0, 600.0, 1.0);
+        egui::Window::new("Inspector")
+            .fixed_pos(Pos2::new(10.0, 10.0))
+            .fixed_size(egui::vec2(200.0, 100.0))
+            .show(ui.get_context(), |ui| ui.label("Light"));
+        ui.end_frame();
+    }
+
+    #[test]
+    fn clicks_over_a_window_are_captured() {
+        let mut ui = Ui::new();
+        show_window(&mut ui);
+        ui.set_cursor_position(50.0, 50.0);
+        show_window(&mut ui);
+        assert!(ui.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        //Releases always reach the gameplay
+        assert!(!ui.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Released));
+        show_window(&mut ui);
+
+        ui.set_cursor_position(700.0, 500.0);
+        show_window(&mut ui);
+        assert!(!ui.set_mouse_button_event(glutin::MouseButton::Left, ElementState::Pressed));
+        assert!(!ui.set_mouse_wheel_event(0.0, 1.0));
+    }
+
+    #[test]
+    fn keys_are_captured_by_focused_text_fields() {
+        let mut ui = Ui::new();
+        let key = |state| KeyboardInput { scancode: 0, state, virtual_keycode: Some(Key::W), modifiers: ModifiersState::default() };
+        assert!(!ui.set_key_event(key(ElementState::Pressed)));
+        let mut text = String::new();
+        ui.begin_frame(800.0, 600.0, 1.0);
+        egui::CentralPanel::default().show(ui.get_context(), |panel| panel.text_edit_singleline(&mut text).request_focus());
+        ui.end_frame();
+        assert!(ui.wants_keyboard());
+        assert!(ui.set_key_event(key(ElementState::Pressed)));
+        assert_eq!(egui_key(Key::Back), Some(egui::Key::Backspace));
+        assert_eq!(egui_key(Key::LShift), None);
+    }
+
+    #[test]
+    fn frames_end_once() {
+        let mut ui = Ui::new();
+        assert!(ui.end_frame().is_none());
+        ui.begin_frame(800.0, 600.0, 2.0);
+        //A frame left open is ended by the next one
+        ui.begin_frame(800.0, 600.0, 2.0);
+        let output = ui.end_frame().unwrap();
+        assert_eq!(output.pixels_per_point, 2.0);
+        assert!(ui.end_frame().is_none());
+    }
+}
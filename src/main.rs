@@ -1,3 +1,4 @@
+pub mod ecs;
 pub mod graphics;
 pub mod procedural;
 pub mod server;
@@ -14,12 +15,12 @@ use crate::graphics::primitives::PrimitiveBuilder;
 struct GameEntity {}
 
 impl EntityState for GameEntity {
-    fn on_create(&mut self, data: &mut graphics::ComponentStorageManager){
+    fn on_create(&mut self, _data: GameData){
 
     }
 
     fn on_update(&mut self, data: GameData, delta: f32){
-        let (id, storage, input) = data;
+        let GameData { storage, input, .. } = data;
         /* let mesh = storage.get_mut_mesh(id).unwrap();
         self.freq += delta as f64;
         let map = heigth_map(100, 100, self.freq);
@@ -47,7 +48,7 @@ impl EntityState for GameEntity {
         light.rotate(cgmath::vec3(0.0, 0.0 ,0.0), cgmath::vec3(0.0, deg_per_sec * delta, 0.0)); */
     }
 
-    fn on_delete(&mut self, data: &mut graphics::ComponentStorageManager){
+    fn on_delete(&mut self, _data: GameData){
 
     }
 }
@@ -81,20 +82,19 @@ fn example1() {
     }
 
     impl EntityState for GameEntity {
-        fn on_create(&mut self, data: &mut graphics::ComponentStorageManager){
+        fn on_create(&mut self, _data: GameData){
 
         }
 
         fn on_update(&mut self, data: GameData, delta: f32){
-            let (id, storage, input) = data;
+            let GameData { storage, input, .. } = data;
             let trans = storage.get_mut_transform(self.id).unwrap();
-            //trans.rotation.set_x(45.0);
             trans.translation.x = 1.5;
             trans.scale.x = 1.0;
             trans.scale.y = 1.0;
             if input.is_key_pressed(Key::Space) == ButtonState::PRESSED {
-                trans.rotation.y +=  delta*20.0;
-                trans.rotation.x +=  delta*20.0;
+                trans.rotate_around(cgmath::Vector3::unit_y(), cgmath::Deg(delta*20.0));
+                trans.rotate_around(cgmath::Vector3::unit_x(), cgmath::Deg(delta*20.0));
             }
             let camera = storage.get_mut_camera();
             if input.is_key_pressed(Key::Z) == ButtonState::PRESSED {
@@ -112,7 +112,7 @@ fn example1() {
             
         }
 
-        fn on_delete(&mut self, data: &mut graphics::ComponentStorageManager){
+        fn on_delete(&mut self, _data: GameData){
 
         }
     }
@@ -121,9 +121,9 @@ fn example1() {
     let mut entity_builder = EntityBuilder::new();
     //entity_builder.with_quad_mesh(1.0);
     entity_builder.with_cube_mesh(1.0).with_texture("container.jpg");
-    entity_builder.build(&mut engine);
+    let cube = entity_builder.build(&mut engine);
 
-    engine.add_states(2, GameEntity{id: 2});
+    engine.add_states(cube, GameEntity{id: cube});
 
     engine.start();
 }
@@ -136,7 +136,7 @@ fn example2() {
     let mut entity_builder = EntityBuilder::new();
     let start = Instant::now();
     let id = entity_builder.with_mesh(generate_mesh(&map)).build(&mut engine);
-    let plane = EntityBuilder::new().with_mesh(PrimitiveBuilder::plane(Color::new(14.0/255.0, 50.0/255.0, 214.0/255.0))).with_transform(Transform::new(cgmath::vec3(20.5, 0.8, -20.5), cgmath::vec3(0.0, 0.0, 0.0), cgmath::vec3(40.0, 0.0, 40.0))).build(&mut engine);
+    let plane = EntityBuilder::new().with_mesh(PrimitiveBuilder::plane(Color::new(14.0/255.0, 50.0/255.0, 214.0/255.0))).with_transform(Transform::new(cgmath::vec3(20.5, 0.8, -20.5), cgmath::Quaternion::one(), cgmath::vec3(40.0, 0.0, 40.0))).build(&mut engine);
     let elapsed_time = start.elapsed();
     println!("Time to generate terrain: {} ms", elapsed_time.as_millis());
     engine.add_states(id, GameEntity{});
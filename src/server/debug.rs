@@ -2,7 +2,8 @@ use std::thread;
 use std::net::{TcpListener, TcpStream, Shutdown};
 use std::io::{Read, Write};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use crate::graphics::{ComponentStorageManager};
+use crate::graphics::ComponentStorageManager;
+use crate::graphics::mesh::Mesh;
 
 
 pub struct DebugSystem {
@@ -93,7 +94,7 @@ impl Task {
     fn execute(&mut self, data: &mut ComponentStorageManager) {
         let response: String = match self.get_command() {
             DebugCommand::VERTICES => {
-                let vertices: usize = data.mesh_manager.iter().map(|(_, m)| {
+                let vertices: usize = data.query::<&Mesh>().iter().map(|m| {
                     m.positions.len()
                 }).sum();
                 vertices.to_string()